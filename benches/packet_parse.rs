@@ -0,0 +1,75 @@
+//! Compares `RadiusPacket::from_bytes` with the borrowed `RadiusPacketRef`.
+//!
+//! Run with `cargo bench --bench packet_parse`.
+
+use radius_server::packet::{RadiusAttribute, RadiusCode, RadiusPacket, RadiusPacketRef, ATTR_ACCT_SESSION_ID};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200_000;
+
+/// A typical Interim-Update: a few dozen small attributes.
+fn accounting_request() -> Vec<u8> {
+    let mut attributes = vec![
+        RadiusAttribute::user_name("ec:30:b3:6d:24:6a").unwrap(),
+        RadiusAttribute::new(ATTR_ACCT_SESSION_ID, b"5f3a9c2e00000042".to_vec()).unwrap(),
+    ];
+    for typ in [40, 42, 43, 46, 47, 48, 5, 61, 27, 28] {
+        attributes.push(RadiusAttribute::new(typ, 7u32.to_be_bytes().to_vec()).unwrap());
+    }
+    attributes.push(RadiusAttribute::new(4, vec![10, 0, 0, 1]).unwrap());
+    attributes.push(RadiusAttribute::new(30, b"00-11-22-33-44-55:ssid".to_vec()).unwrap());
+    attributes.push(RadiusAttribute::new(31, b"EC-30-B3-6D-24-6A".to_vec()).unwrap());
+    attributes.extend(RadiusAttribute::reply_messages(&"x".repeat(600)));
+
+    RadiusPacket {
+        code: RadiusCode::AccountingRequest,
+        identifier: 42,
+        length: 0,
+        authenticator: [7; 16],
+        attributes,
+    }
+    .to_bytes()
+    .unwrap()
+}
+
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let per_iter = start.elapsed() / ITERATIONS;
+    println!("{name:<40} {per_iter:>10.2?} per packet");
+    per_iter
+}
+
+fn main() {
+    let raw = accounting_request();
+    println!("📦 {} byte Accounting-Request, {} iterations\n", raw.len(), ITERATIONS);
+
+    let owned = bench("RadiusPacket::from_bytes", || {
+        black_box(RadiusPacket::from_bytes(black_box(&raw)).unwrap());
+    });
+    let borrowed = bench("RadiusPacketRef::parse", || {
+        black_box(RadiusPacketRef::parse(black_box(&raw)).unwrap());
+    });
+    bench("RadiusPacketRef::parse + find", || {
+        let packet = RadiusPacketRef::parse(black_box(&raw)).unwrap();
+        black_box(packet.find(ATTR_ACCT_SESSION_ID));
+    });
+    bench("RadiusPacketRef::parse + iterate", || {
+        let packet = RadiusPacketRef::parse(black_box(&raw)).unwrap();
+        black_box(packet.attributes().map(|a| a.value.len()).sum::<usize>());
+    });
+    bench("RadiusPacketRef::parse + to_packet", || {
+        black_box(RadiusPacketRef::parse(black_box(&raw)).unwrap().to_packet());
+    });
+
+    println!(
+        "\n⚡ parse is {:.1}x faster than from_bytes",
+        owned.as_secs_f64() / borrowed.as_secs_f64()
+    );
+}
@@ -33,7 +33,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if username.trim() == " " {
                 Ok(packet.reply_accept(vec![
                     RadiusAttribute::session_timeout(3600),
-                    RadiusAttribute::reply_message("Welcome, admin."),
+                    RadiusAttribute::reply_message("Welcome, admin.")?,
                 ]))
             } else {
                 Ok(packet.reply_reject("User not allowed"))
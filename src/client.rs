@@ -0,0 +1,382 @@
+use crate::error::RadiusError;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// An IPv4 or IPv6 network in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, RadiusError> {
+        Self::checked(addr, prefix).map_err(RadiusError::Config)
+    }
+
+    fn checked(addr: IpAddr, prefix: u8) -> Result<Self, String> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(format!("Prefix length {} is too long for {}", prefix, addr));
+        }
+        Ok(IpNetwork { addr, prefix })
+    }
+
+    /// Parses `addr` or `addr/prefix`; the error is the bare message used
+    /// in `clients.conf` diagnostics.
+    fn parse(s: &str) -> Result<Self, String> {
+        match s.split_once('/') {
+            Some((addr, prefix)) => {
+                let addr = addr
+                    .parse::<IpAddr>()
+                    .map_err(|e| format!("Invalid address '{}': {}", addr, e))?;
+                let prefix = prefix
+                    .parse::<u8>()
+                    .map_err(|e| format!("Invalid prefix '{}': {}", prefix, e))?;
+                IpNetwork::checked(addr, prefix)
+            }
+            None => s
+                .parse::<IpAddr>()
+                .map(IpNetwork::host)
+                .map_err(|e| format!("Invalid address '{}': {}", s, e)),
+        }
+    }
+
+    /// A network matching exactly one address.
+    pub fn host(addr: IpAddr) -> Self {
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        IpNetwork { addr, prefix }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = RadiusError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        IpNetwork::parse(s).map_err(RadiusError::Config)
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// A NAS allowed to talk to the server, as described by a `client { ... }`
+/// section in FreeRADIUS' `clients.conf`.
+#[derive(Debug, Clone)]
+pub struct RadiusClient {
+    pub network: IpNetwork,
+    pub secret: String,
+    pub shortname: String,
+    pub nas_type: Option<String>,
+    /// Any other `key = value` pairs from the client section.
+    pub options: HashMap<String, String>,
+}
+
+impl RadiusClient {
+    pub fn new(network: IpNetwork, secret: &str) -> Self {
+        RadiusClient {
+            network,
+            secret: secret.to_string(),
+            shortname: network.to_string(),
+            nas_type: None,
+            options: HashMap::new(),
+        }
+    }
+
+    pub fn shortname(mut self, name: &str) -> Self {
+        self.shortname = name.to_string();
+        self
+    }
+
+    pub fn nas_type(mut self, nas_type: &str) -> Self {
+        self.nas_type = Some(nas_type.to_string());
+        self
+    }
+
+    pub fn option(mut self, key: &str, value: &str) -> Self {
+        self.options.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Whether Access-Requests from this client must carry a valid
+    /// Message-Authenticator (`require_message_authenticator = yes`).
+    pub fn requires_message_authenticator(&self) -> bool {
+        self.flag("require_message_authenticator")
+    }
+
+    pub fn require_message_authenticator(self, required: bool) -> Self {
+        self.option("require_message_authenticator", if required { "yes" } else { "no" })
+    }
+
+    /// Returns a boolean option, accepting FreeRADIUS' `yes`/`no` spelling.
+    pub fn flag(&self, key: &str) -> bool {
+        matches!(
+            self.options.get(key).map(|v| v.to_ascii_lowercase()).as_deref(),
+            Some("yes" | "true" | "1")
+        )
+    }
+}
+
+/// Table of known NASes, matched against the source address of every packet.
+#[derive(Debug, Clone, Default)]
+pub struct ClientRegistry {
+    // Kept ordered by descending prefix length so the first hit is the most
+    // specific one.
+    clients: Vec<Arc<RadiusClient>>,
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry accepting every source address with the same secret.
+    pub fn catch_all(secret: &str) -> Self {
+        let mut registry = Self::new();
+        let any_v4 = IpNetwork::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0).unwrap();
+        let any_v6 = IpNetwork::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0).unwrap();
+        registry.add(RadiusClient::new(any_v4, secret).shortname("default"));
+        registry.add(RadiusClient::new(any_v6, secret).shortname("default"));
+        registry
+    }
+
+    pub fn add(&mut self, client: RadiusClient) {
+        let pos = self
+            .clients
+            .iter()
+            .position(|c| c.network.prefix < client.network.prefix)
+            .unwrap_or(self.clients.len());
+        self.clients.insert(pos, Arc::new(client));
+    }
+
+    /// Finds the most specific client whose network contains `ip`.
+    pub fn lookup(&self, ip: IpAddr) -> Option<Arc<RadiusClient>> {
+        self.clients.iter().find(|c| c.network.contains(ip)).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RadiusClient> {
+        self.clients.iter().map(|c| c.as_ref())
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, RadiusError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|source| RadiusError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        })?;
+        Self::parse_from_str(&content).map_err(|e| e.in_file(path))
+    }
+
+    /// Parses `client <name> { ... }` sections in `clients.conf` syntax.
+    /// Nested subsections (e.g. `limit { ... }`) are skipped.
+    pub fn parse_from_str(content: &str) -> Result<Self, RadiusError> {
+        let error = |line: usize, message: String| RadiusError::ConfigParse { file: None, line, message };
+        let mut registry = Self::new();
+        let mut current: Option<(String, usize, HashMap<String, String>)> = None;
+        let mut depth = 0usize;
+
+        for (lineno, line) in content.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(rest) = line.strip_suffix('{') {
+                let parts: Vec<&str> = rest.split_whitespace().collect();
+                if depth == 0 {
+                    if parts.first() != Some(&"client") {
+                        return Err(error(lineno + 1, format!("Unexpected section '{}'", rest.trim())));
+                    }
+                    let name = parts.get(1).copied().unwrap_or("").to_string();
+                    current = Some((name, lineno + 1, HashMap::new()));
+                }
+                depth += 1;
+                continue;
+            }
+
+            if line == "}" {
+                if depth == 0 {
+                    return Err(error(lineno + 1, "Unbalanced '}'".to_string()));
+                }
+                depth -= 1;
+                if depth == 0
+                    && let Some((name, start, fields)) = current.take()
+                {
+                    registry.add(
+                        client_from_section(&name, fields)
+                            .map_err(|e| error(start, format!("{} in client section", e)))?,
+                    );
+                }
+                continue;
+            }
+
+            if depth == 1 {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| error(lineno + 1, "Expected 'key = value'".to_string()))?;
+                let value = value.trim().trim_matches('"').to_string();
+                if let Some((_, _, fields)) = current.as_mut() {
+                    fields.insert(key.trim().to_string(), value);
+                }
+            }
+        }
+
+        if depth != 0 {
+            return Err(error(content.lines().count(), "Unterminated client section".to_string()));
+        }
+
+        Ok(registry)
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn client_from_section(
+    name: &str,
+    mut fields: HashMap<String, String>,
+) -> Result<RadiusClient, String> {
+    let addr = ["ipaddr", "ipv4addr", "ipv6addr"]
+        .iter()
+        .find_map(|k| fields.remove(*k))
+        .unwrap_or_else(|| name.to_string());
+    let mut network = IpNetwork::parse(&addr)?;
+
+    if let Some(mask) = fields.remove("netmask") {
+        let prefix = mask
+            .parse::<u8>()
+            .map_err(|e| format!("Invalid netmask '{}': {}", mask, e))?;
+        network = IpNetwork::checked(network.addr, prefix)?;
+    }
+
+    let secret = fields
+        .remove("secret")
+        .ok_or_else(|| format!("Missing secret for client '{}'", name))?;
+
+    let mut client = RadiusClient::new(network, &secret);
+    client.shortname = fields.remove("shortname").unwrap_or_else(|| name.to_string());
+    client.nas_type = fields.remove("nas_type");
+    client.options = fields;
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENTS: &str = r#"
+client 10.0.0.0/8 {
+    secret = "wide # not a comment"
+}
+
+client office {
+    ipaddr = 10.1.0.0
+    netmask = 16
+    secret = office-secret   # trailing comment
+    nas_type = cisco
+    require_message_authenticator = yes
+    limit {
+        max_connections = 16
+    }
+}
+
+client ap1 {
+    ipv6addr = 2001:db8::1
+    secret = ap-secret
+    shortname = lobby
+}
+"#;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_client_sections() {
+        let registry = ClientRegistry::parse_from_str(CLIENTS).unwrap();
+        assert_eq!(registry.len(), 3);
+
+        let office = registry.lookup(ip("10.1.2.3")).unwrap();
+        assert_eq!(office.shortname, "office");
+        assert_eq!(office.network, "10.1.0.0/16".parse().unwrap());
+        assert_eq!(office.nas_type.as_deref(), Some("cisco"));
+        assert!(office.requires_message_authenticator());
+        assert!(!office.options.contains_key("max_connections"));
+
+        let wide = registry.lookup(ip("10.200.0.1")).unwrap();
+        assert_eq!(wide.secret, "wide # not a comment");
+        assert!(!wide.requires_message_authenticator());
+
+        assert_eq!(registry.lookup(ip("2001:db8::1")).unwrap().shortname, "lobby");
+        assert!(registry.lookup(ip("2001:db8::2")).is_none());
+        assert!(registry.lookup(ip("192.168.0.1")).is_none());
+    }
+
+    #[test]
+    fn lookup_prefers_the_longest_prefix() {
+        let mut registry = ClientRegistry::catch_all("default");
+        for (network, secret) in [("10.1.2.3", "host"), ("10.0.0.0/8", "wide"), ("10.1.0.0/16", "office")] {
+            registry.add(RadiusClient::new(network.parse().unwrap(), secret));
+        }
+
+        let secret = |s: &str| registry.lookup(ip(s)).unwrap().secret.clone();
+        assert_eq!(secret("10.1.2.3"), "host");
+        assert_eq!(secret("::ffff:10.1.2.3"), "host");
+        assert_eq!(secret("10.1.9.9"), "office");
+        assert_eq!(secret("10.9.9.9"), "wide");
+        assert_eq!(secret("192.168.0.1"), "default");
+        assert_eq!(secret("2001:db8::1"), "default");
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let line = |content: &str| match ClientRegistry::parse_from_str(content) {
+            Err(RadiusError::ConfigParse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(line("\nclient nas {\n    ipaddr = 10.0.0.1\n}\n"), 2);
+        assert_eq!(line("client nas {\n    secret\n}\n"), 2);
+        assert_eq!(line("client 10.0.0.1/33 {\n    secret = x\n}\n"), 1);
+        assert_eq!(line("client nas {\n    secret = x\n"), 2);
+        assert_eq!(line("server default {\n}\n"), 1);
+    }
+}
@@ -1,5 +1,11 @@
-use std::collections::{HashMap, HashSet};
+use crate::error::RadiusError;
+use crate::packet::is_extended_type;
+use crate::value::{split_tlvs, AttributeValue, ValueTree};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
@@ -8,169 +14,1724 @@ pub struct RadiusAttributeDef {
     pub code: u32,
     pub vendor: Option<u32>,
     pub data_type: String,
+    /// Options from the ATTRIBUTE line and any `FLAGS` in effect.
+    pub flags: AttributeFlags,
+    /// Attributes nested inside this one, keyed by their number under it:
+    /// the children of a `tlv` (`Net.Src` is child 1 of `Net`), the
+    /// members of a `struct` in order, or the STRUCTs a `key` member picks.
+    pub children: BTreeMap<u32, RadiusAttributeDef>,
 }
 
-#[derive(Debug)]
+impl RadiusAttributeDef {
+    pub fn new(name: &str, code: u32, vendor: Option<u32>, data_type: &str) -> Self {
+        RadiusAttributeDef {
+            name: name.to_string(),
+            code,
+            vendor,
+            data_type: data_type.to_string(),
+            flags: AttributeFlags::default(),
+            children: BTreeMap::new(),
+        }
+    }
+
+    /// Splits off the RFC 2868 tag of a `has_tag` attribute. Integers keep
+    /// their tag in the high octet, which reads as zero once removed;
+    /// strings carry it only when the first octet is below 0x20.
+    pub fn split_tag<'a>(&self, bytes: &'a [u8]) -> (u8, Cow<'a, [u8]>) {
+        if !self.flags.has_tag || bytes.is_empty() {
+            return (0, Cow::Borrowed(bytes));
+        }
+        if matches!(self.data_type.as_str(), "integer" | "uint32") {
+            let mut value = bytes.to_vec();
+            value[0] = 0;
+            return (bytes[0], Cow::Owned(value));
+        }
+        if bytes[0] < 0x20 || self.flags.encrypt == Some(Encryption::TunnelPassword) {
+            return (bytes[0], Cow::Borrowed(&bytes[1..]));
+        }
+        (0, Cow::Borrowed(bytes))
+    }
+
+    /// Reverses [`split_tag`](Self::split_tag).
+    pub fn join_tag(&self, tag: u8, mut data: Vec<u8>) -> Vec<u8> {
+        if !self.flags.has_tag {
+            return data;
+        }
+        if matches!(self.data_type.as_str(), "integer" | "uint32") && !data.is_empty() {
+            data[0] = tag;
+        } else if tag != 0 || self.flags.encrypt == Some(Encryption::TunnelPassword) {
+            data.insert(0, tag);
+        }
+        data
+    }
+
+    /// The elements of an `array` attribute: back to back for fixed-size
+    /// types, each with a 16-bit length prefix otherwise.
+    pub fn split_array<'a>(&self, mut bytes: &'a [u8]) -> Result<Vec<&'a [u8]>, RadiusError> {
+        if !self.flags.array {
+            return Ok(vec![bytes]);
+        }
+        if let Some(size) = AttributeValue::fixed_size(&self.data_type) {
+            if !bytes.len().is_multiple_of(size) {
+                return Err(RadiusError::ValueLength {
+                    data_type: "array",
+                    expected: format!("a multiple of {}", size),
+                    actual: bytes.len(),
+                });
+            }
+            return Ok(bytes.chunks(size).collect());
+        }
+
+        let mut elements = Vec::new();
+        while !bytes.is_empty() {
+            let len = match bytes {
+                [hi, lo, ..] => u16::from_be_bytes([*hi, *lo]) as usize,
+                _ => usize::MAX,
+            };
+            let element = bytes.get(2..2usize.saturating_add(len)).ok_or(RadiusError::ValueLength {
+                data_type: "array",
+                expected: "a 2-byte length and that many bytes".to_string(),
+                actual: bytes.len(),
+            })?;
+            elements.push(element);
+            bytes = &bytes[2 + len..];
+        }
+        Ok(elements)
+    }
+
+    /// Decodes `bytes` along this definition: the sub-attributes of a
+    /// `tlv` and the members of a `struct` become a [`ValueTree::Group`],
+    /// other types a plain value. Unknown sub-attributes are kept as
+    /// `Attr-N` octets.
+    pub fn decode_tree(&self, bytes: &[u8]) -> Result<ValueTree, RadiusError> {
+        match self.data_type.as_str() {
+            "tlv" => {
+                let mut members = Vec::new();
+                for (typ, data) in split_tlvs(bytes)? {
+                    members.push(match self.children.get(&(typ as u32)) {
+                        Some(child) => (child.name.clone(), child.decode_tree(data)?),
+                        None => (format!("Attr-{}", typ), AttributeValue::Octets(data.to_vec()).into()),
+                    });
+                }
+                Ok(ValueTree::Group(members))
+            }
+            "struct" => self.decode_struct(bytes),
+            _ => AttributeValue::decode(&self.data_type, bytes).map(ValueTree::Value),
+        }
+    }
+
+    /// Members are laid out back to back in order; only the last may be
+    /// variable-length. A `key` member's value picks the STRUCT that
+    /// decodes whatever follows.
+    fn decode_struct(&self, bytes: &[u8]) -> Result<ValueTree, RadiusError> {
+        let mut members = Vec::new();
+        let mut variant = None;
+        let mut rest = bytes;
+        for member in self.children.values() {
+            let len = AttributeValue::fixed_size(&member.data_type).unwrap_or(rest.len());
+            let data = rest.get(..len).ok_or_else(|| RadiusError::ValueLength {
+                data_type: "struct",
+                expected: format!("at least {}", len),
+                actual: rest.len(),
+            })?;
+            let value = member.decode_tree(data)?;
+            if member.flags.key
+                && let Some(n) = value.value().and_then(AttributeValue::as_u64)
+            {
+                variant = u32::try_from(n).ok().and_then(|n| member.children.get(&n));
+            }
+            members.push((member.name.clone(), value));
+            rest = &rest[len..];
+        }
+        match variant {
+            Some(variant) => members.push((variant.name.clone(), variant.decode_struct(rest)?)),
+            None if !rest.is_empty() => {
+                return Err(RadiusError::ValueLength {
+                    data_type: "struct",
+                    expected: (bytes.len() - rest.len()).to_string(),
+                    actual: bytes.len(),
+                });
+            }
+            None => {}
+        }
+        Ok(ValueTree::Group(members))
+    }
+
+    /// Encodes a tree shaped like the ones [`decode_tree`](Self::decode_tree)
+    /// returns. A plain value is accepted for any type, so raw octets can
+    /// stand in for a whole `tlv` or `struct`.
+    pub fn encode_tree(&self, tree: &ValueTree) -> Result<Vec<u8>, RadiusError> {
+        let members = match tree {
+            ValueTree::Value(value) => return self.encode_leaf(value),
+            ValueTree::Group(members) => members,
+        };
+        match self.data_type.as_str() {
+            "tlv" => {
+                let mut data = Vec::new();
+                for (name, value) in members {
+                    let child = self
+                        .children
+                        .values()
+                        .find(|c| c.name == *name)
+                        .ok_or_else(|| RadiusError::UnknownAttribute(format!("{}.{}", self.name, name)))?;
+                    let value = child.encode_tree(value)?;
+                    let typ = u8::try_from(child.code).ok().filter(|_| value.len() <= 253).ok_or(
+                        RadiusError::ValueLength {
+                            data_type: "tlv",
+                            expected: "at most 253".to_string(),
+                            actual: value.len(),
+                        },
+                    )?;
+                    data.extend_from_slice(&[typ, (value.len() + 2) as u8]);
+                    data.extend(value);
+                }
+                Ok(data)
+            }
+            "struct" => self.encode_struct(members),
+            _ => Err(RadiusError::TypeMismatch {
+                attribute: self.name.clone(),
+                expected: self.data_type.clone(),
+                found: "tlv",
+            }),
+        }
+    }
+
+    fn encode_leaf(&self, value: &AttributeValue) -> Result<Vec<u8>, RadiusError> {
+        if !value.fits(&self.data_type) {
+            return Err(RadiusError::TypeMismatch {
+                attribute: self.name.clone(),
+                expected: self.data_type.clone(),
+                found: value.data_type(),
+            });
+        }
+        let data = value.encode();
+        match AttributeValue::fixed_size(&self.data_type) {
+            Some(size) if size != data.len() => Err(RadiusError::ValueLength {
+                data_type: "fixed-size",
+                expected: size.to_string(),
+                actual: data.len(),
+            }),
+            _ => Ok(data),
+        }
+    }
+
+    /// A `key` member left out of the tree takes the number of the STRUCT
+    /// that is present.
+    fn encode_struct(&self, members: &[(String, ValueTree)]) -> Result<Vec<u8>, RadiusError> {
+        let find = |name: &str| members.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+        let variants = || self.children.values().filter(|m| m.flags.key).flat_map(|m| m.children.values());
+        if let Some((name, _)) = members
+            .iter()
+            .find(|(n, _)| !self.children.values().chain(variants()).any(|m| m.name == *n))
+        {
+            return Err(RadiusError::UnknownAttribute(format!("{}.{}", self.name, name)));
+        }
+
+        let mut data = Vec::new();
+        let mut chosen = None;
+        for member in self.children.values() {
+            let variant = member.children.values().find(|v| member.flags.key && find(&v.name).is_some());
+            let implied;
+            let value = match (find(&member.name), variant) {
+                (Some(value), _) => value,
+                (None, Some(variant)) => {
+                    implied = AttributeValue::from_number(&member.data_type, variant.code as u64)
+                        .map(ValueTree::Value)
+                        .ok_or_else(|| RadiusError::TypeMismatch {
+                            attribute: member.name.clone(),
+                            expected: member.data_type.clone(),
+                            found: "integer",
+                        })?;
+                    &implied
+                }
+                (None, None) => {
+                    return Err(RadiusError::MissingMember {
+                        attribute: self.name.clone(),
+                        member: member.name.clone(),
+                    });
+                }
+            };
+            data.extend(member.encode_tree(value)?);
+            chosen = chosen.or(variant);
+        }
+        if let Some(variant) = chosen
+            && let Some(value) = find(&variant.name)
+        {
+            data.extend(variant.encode_tree(value)?);
+        }
+        Ok(data)
+    }
+
+    /// The wire form of one element of an `array` attribute.
+    pub fn array_element(&self, data: Vec<u8>) -> Vec<u8> {
+        if !self.flags.array || AttributeValue::fixed_size(&self.data_type).is_some() {
+            return data;
+        }
+        let mut element = (data.len() as u16).to_be_bytes().to_vec();
+        element.extend(data);
+        element
+    }
+}
+
+/// How an attribute value is obfuscated on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    /// RFC 2865 §5.2 (`encrypt=1`).
+    UserPassword,
+    /// RFC 2868 §3.5, salted (`encrypt=2`).
+    TunnelPassword,
+    /// Ascend's `MD5(authenticator + secret)` XOR (`encrypt=3`).
+    AscendSecret,
+}
+
+/// Attribute options, e.g. `encrypt=1,has_tag` (v3) or `array`, `concat`
+/// and `internal` (v4).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AttributeFlags {
+    pub encrypt: Option<Encryption>,
+    /// Carries an RFC 2868 tag in its first octet.
+    pub has_tag: bool,
+    /// Values longer than one attribute are split across several
+    /// consecutive attributes and joined again when read.
+    pub concat: bool,
+    /// One attribute carries several values of its type back to back.
+    pub array: bool,
+    /// Server-side only; never sent in packets.
+    pub internal: bool,
+    /// Hidden in logs.
+    pub secret: bool,
+    /// A struct member whose value picks the STRUCT that follows it.
+    pub key: bool,
+}
+
+/// FreeRADIUS options that are valid but change nothing here.
+const IGNORED_OPTIONS: &[&str] = &["virtual", "long", "extended", "evs", "counter", "unsafe", "abinary"];
+/// The same for `name=value` options.
+const IGNORED_VALUE_OPTIONS: &[&str] = &["clone", "ref", "enum", "length", "offset", "precision", "format"];
+
+impl AttributeFlags {
+    /// Applies a comma-separated option list. Options this crate does not
+    /// act on (`clone=`, `unsafe`, ...) are accepted and ignored; the ones
+    /// it does not know at all are skipped and returned as warnings.
+    fn parse_options(&mut self, options: &str) -> Vec<String> {
+        let mut unknown = Vec::new();
+        for option in options.split(',').filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                Some(("encrypt", method)) => match method {
+                    "0" => self.encrypt = None,
+                    "1" | "User-Password" => self.encrypt = Some(Encryption::UserPassword),
+                    "2" | "Tunnel-Password" => self.encrypt = Some(Encryption::TunnelPassword),
+                    "3" | "Ascend-Secret" => self.encrypt = Some(Encryption::AscendSecret),
+                    _ => unknown.push(format!("unknown encrypt method '{}', ignored", method)),
+                },
+                Some((name, _)) if IGNORED_VALUE_OPTIONS.contains(&name) => {}
+                Some(_) => unknown.push(format!("unknown option '{}', ignored", option)),
+                None => match option {
+                    "has_tag" => self.has_tag = true,
+                    "concat" => self.concat = true,
+                    "array" => self.array = true,
+                    "internal" => self.internal = true,
+                    "!internal" => self.internal = false,
+                    "secret" => self.secret = true,
+                    "key" => self.key = true,
+                    _ if IGNORED_OPTIONS.contains(&option) => {}
+                    _ => unknown.push(format!("unknown option '{}', ignored", option)),
+                },
+            }
+        }
+        unknown
+    }
+}
+
+/// Where an attribute lives: its number in one of the dictionary's maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttributeKey {
+    /// A standard attribute, or a server-side one above 255 from a v3
+    /// dictionary.
+    Standard(u32),
+    /// An RFC 6929 attribute: (type, Extended-Type).
+    Extended(u8, u8),
+    /// A Vendor-Specific attribute: (vendor ID, vendor type).
+    Vendor(u32, u32),
+    /// A server-internal attribute (`FLAGS internal`). These have their
+    /// own numbering, e.g. the internal `Auth-Type` is 12, like Framed-MTU.
+    Internal(u32),
+}
+
+#[derive(Debug, Default)]
 pub struct Dictionary {
     pub attributes: HashMap<u32, RadiusAttributeDef>,
+    /// RFC 6929 attributes such as `241.1`, keyed by (type, Extended-Type).
+    pub extended: HashMap<(u8, u8), RadiusAttributeDef>,
+    /// Vendor-Specific attributes, keyed by (vendor ID, vendor type).
+    pub vendor_attributes: HashMap<(u32, u32), RadiusAttributeDef>,
     pub vendors: HashMap<String, u32>,
+    /// Server-internal attributes, which never appear in packets.
+    pub internal: HashMap<u32, RadiusAttributeDef>,
+    /// Named values of enumerated attributes, from VALUE lines.
+    pub values: HashMap<AttributeKey, ValueNames>,
+    /// Attribute names (and `Vendor.Attribute` names, and ALIAS names) to
+    /// their keys.
+    names: HashMap<String, AttributeKey>,
+    /// Full names of nested attributes, e.g. `Net.Src.IP`, to their
+    /// top-level key and the child numbers below it.
+    nested: HashMap<String, (AttributeKey, Vec<u32>)>,
+}
+
+/// Dictionary files held in memory, by name, so that `$INCLUDE` can be
+/// resolved without a filesystem. Names are `/`-separated paths relative
+/// to the root of the set.
+#[derive(Debug, Clone, Default)]
+pub struct DictionaryFiles {
+    files: HashMap<String, Cow<'static, str>>,
+}
+
+macro_rules! embedded_files {
+    ($($name:literal),* $(,)?) => {
+        &[$(($name, include_str!(concat!("../dictionaries/", $name)))),*]
+    };
+}
+
+/// The dictionaries compiled into the crate.
+const EMBEDDED_FILES: &[(&str, &str)] = embedded_files![
+    "dictionary",
+    "dictionary.rfc2865",
+    "dictionary.rfc2866",
+    "dictionary.rfc2867",
+    "dictionary.rfc2868",
+    "dictionary.rfc2869",
+    "dictionary.rfc3162",
+    "dictionary.rfc3576",
+    "dictionary.rfc4072",
+    "dictionary.rfc4372",
+    "dictionary.rfc4675",
+    "dictionary.rfc4679",
+    "dictionary.rfc5090",
+    "dictionary.rfc5176",
+    "dictionary.rfc5447",
+    "dictionary.rfc5580",
+    "dictionary.rfc5607",
+    "dictionary.rfc5904",
+    "dictionary.rfc6519",
+    "dictionary.rfc6572",
+    "dictionary.rfc6911",
+    "dictionary.rfc7055",
+    "dictionary.rfc7155",
+    "dictionary.rfc7268",
+    "dictionary.cisco",
+    "dictionary.juniper",
+    "dictionary.microsoft",
+    "dictionary.mikrotik",
+    "dictionary.wispr",
+    "dictionary.freeradius.internal",
+    "dictionary.freeradius.internal.crl",
+    "dictionary.freeradius.internal.eap",
+    "dictionary.freeradius.internal.ippool",
+    "dictionary.freeradius.internal.password",
+    "dictionary.freeradius.internal.sim",
+    "v3/dictionary.freeradius.internal",
+];
+
+impl DictionaryFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bundled dictionaries; `dictionary` includes all the others.
+    pub fn embedded() -> Self {
+        let mut files = Self::new();
+        for (name, content) in EMBEDDED_FILES {
+            files.insert(name, *content);
+        }
+        files
+    }
+
+    /// Adds or replaces a file.
+    pub fn insert(&mut self, name: &str, content: impl Into<Cow<'static, str>>) {
+        self.files.insert(name.to_string(), content.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.files.get(name).map(|content| content.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+}
+
+/// The name of a file included from `current`: relative to its directory
+/// unless it starts with `/`, with `.` and `..` segments resolved.
+fn resolve_include(current: Option<&str>, include: &str) -> String {
+    let dir = current.and_then(|c| c.rsplit_once('/')).map_or("", |(dir, _)| dir);
+    let mut segments: Vec<&str> = if include.starts_with('/') {
+        Vec::new()
+    } else {
+        dir.split('/').filter(|s| !s.is_empty()).collect()
+    };
+    for segment in include.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Where dictionary files are read from while parsing.
+pub trait DictionarySource {
+    /// The contents of the file `name`. A missing file is
+    /// `io::ErrorKind::NotFound`.
+    fn read(&self, name: &str) -> io::Result<Cow<'_, str>>;
+
+    /// The name of the file `include`d from `current` (`None` for text
+    /// that was not read from this source).
+    fn resolve(&self, current: Option<&str>, include: &str) -> String {
+        resolve_include(current, include)
+    }
+}
+
+impl DictionarySource for DictionaryFiles {
+    fn read(&self, name: &str) -> io::Result<Cow<'_, str>> {
+        self.get(name)
+            .map(Cow::Borrowed)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such dictionary file"))
+    }
+}
+
+/// Dictionary files on disk. Names are paths, and includes are relative
+/// to the including file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystem;
+
+impl DictionarySource for FileSystem {
+    fn read(&self, name: &str) -> io::Result<Cow<'_, str>> {
+        fs::read_to_string(name).map(Cow::Owned)
+    }
+
+    fn resolve(&self, current: Option<&str>, include: &str) -> String {
+        let dir = current.and_then(|c| Path::new(c).parent()).unwrap_or(Path::new(""));
+        dir.join(include).to_string_lossy().into_owned()
+    }
+}
+
+/// What kind of problem a [`Diagnostic`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// A line that could not be used: a bad number, an unknown vendor or
+    /// attribute, a missing field.
+    Invalid,
+    /// An `$INCLUDE` of a file that is missing or unreadable.
+    UnresolvedInclude,
+    /// A line starting with a keyword the parser does not know; skipped.
+    UnknownDirective,
+    /// An attribute option (or `encrypt=` method) the parser does not
+    /// know; the rest of the line is used.
+    UnknownOption,
+    /// An attribute number already taken by another name, which the new
+    /// definition replaces.
+    DuplicateCode,
+    /// An attribute defined again with a different type.
+    Redefinition,
+}
+
+impl DiagnosticKind {
+    /// Errors make `load_from_file` and friends fail; the rest are
+    /// warnings.
+    pub fn is_error(self) -> bool {
+        matches!(self, DiagnosticKind::Invalid | DiagnosticKind::UnresolvedInclude)
+    }
+}
+
+/// A problem found while parsing a dictionary, with where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// `None` for dictionaries parsed from a string.
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{} in {} at line {}", self.message, file.display(), self.line),
+            None => write!(f, "{} at line {}", self.message, self.line),
+        }
+    }
+}
+
+impl From<Diagnostic> for RadiusError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        RadiusError::DictionaryParse {
+            file: diagnostic.file,
+            line: diagnostic.line,
+            message: diagnostic.message,
+        }
+    }
+}
+
+/// Per-file state while parsing: the BEGIN-VENDOR block, the BEGIN
+/// blocks, and the attribute that `.N` numbers are relative to.
+#[derive(Debug, Default)]
+struct ParseScope {
+    vendor: Option<u32>,
+    /// Set by `FLAGS` lines for the rest of the file.
+    flags: AttributeFlags,
+    parents: Vec<(AttributeKey, Vec<u32>)>,
+    relative: Option<(AttributeKey, Vec<u32>)>,
+    /// The last `struct` ATTRIBUTE, which STRUCT keys are named in.
+    structure: Option<(AttributeKey, Vec<u32>)>,
+    /// Where MEMBER lines go: that struct, or the STRUCT after it.
+    members: Option<(AttributeKey, Vec<u32>)>,
+}
+
+impl ParseScope {
+    /// Called for every ATTRIBUTE line: a `struct` takes the MEMBER lines
+    /// that follow, anything else ends them.
+    fn defined(&mut self, data_type: &str, key: AttributeKey, path: Vec<u32>) {
+        self.structure = (data_type == "struct").then_some((key, path));
+        self.members = self.structure.clone();
+    }
+}
+
+/// The VALUE names of one attribute, indexed in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct ValueNames {
+    by_name: HashMap<String, u64>,
+    by_value: HashMap<u64, String>,
+}
+
+impl ValueNames {
+    /// Adds a name. When several names share a value, the first one is
+    /// used for display.
+    pub fn insert(&mut self, name: &str, value: u64) {
+        self.by_name.insert(name.to_string(), value);
+        self.by_value.entry(value).or_insert_with(|| name.to_string());
+    }
+
+    pub fn name(&self, value: u64) -> Option<&str> {
+        self.by_value.get(&value).map(String::as_str)
+    }
+
+    pub fn value(&self, name: &str) -> Option<u64> {
+        self.by_name.get(name).copied()
+    }
+}
+
+fn unknown_options(messages: Vec<String>) -> impl Iterator<Item = (DiagnosticKind, String)> {
+    messages.into_iter().map(|message| (DiagnosticKind::UnknownOption, message))
+}
+
+fn parse_number(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u32::from_str_radix(&s[2..], 16)
+            .map_err(|e| format!("Invalid hex '{}': {}", s, e))
+    } else {
+        s.parse::<u32>()
+            .map_err(|e| format!("Invalid number '{}': {}", s, e))
+    }
+}
+
+/// Parses the number of a VALUE line: decimal, hex, or negative for
+/// `signed` attributes (stored as their 32-bit two's complement).
+fn parse_value_number(s: &str) -> Result<u64, String> {
+    if s.starts_with('-') {
+        s.parse::<i32>()
+            .map(|v| v as u32 as u64)
+            .map_err(|e| format!("Invalid number '{}': {}", s, e))
+    } else if s.starts_with("0x") || s.starts_with("0X") {
+        u64::from_str_radix(&s[2..], 16).map_err(|e| format!("Invalid hex '{}': {}", s, e))
+    } else {
+        s.parse::<u64>().map_err(|e| format!("Invalid number '{}': {}", s, e))
+    }
+}
+
+/// Parses a dotted attribute number such as `60.1.2` into its parts.
+fn parse_oid(s: &str) -> Result<Vec<u32>, String> {
+    s.split('.').map(parse_number).collect()
+}
+
+/// Formats `bytes` as the attribute `def`, as `{ Child = value, ... }`
+/// for a TLV whose children are defined.
+fn format_node(def: &RadiusAttributeDef, names: Option<&ValueNames>, bytes: &[u8]) -> String {
+    if def.flags.encrypt.is_some() || def.flags.secret {
+        return "<hidden>".to_string();
+    }
+    let (tag, bytes) = def.split_tag(bytes);
+    let elements = match def.split_array(&bytes) {
+        Ok(elements) => elements,
+        Err(_) => return AttributeValue::Octets(bytes.to_vec()).to_string(),
+    };
+    let value = elements
+        .into_iter()
+        .map(|element| format_element(def, names, element))
+        .collect::<Vec<_>>()
+        .join(", ");
+    match tag {
+        0 => value,
+        tag => format!("{} (tag {})", value, tag),
+    }
+}
+
+fn format_element(def: &RadiusAttributeDef, names: Option<&ValueNames>, bytes: &[u8]) -> String {
+    if def.data_type == "struct" && !def.children.is_empty() {
+        return match def.decode_tree(bytes) {
+            Ok(tree) => tree.to_string(),
+            Err(_) => AttributeValue::Octets(bytes.to_vec()).to_string(),
+        };
+    }
+    match AttributeValue::decode(&def.data_type, bytes) {
+        Ok(AttributeValue::Tlv(tlvs)) if !def.children.is_empty() => {
+            let parts: Vec<String> = tlvs
+                .iter()
+                .map(|(typ, value)| match def.children.get(&(*typ as u32)) {
+                    Some(child) => format!("{} = {}", child.name, format_node(child, None, value)),
+                    None => format!("Attr-{} = {}", typ, AttributeValue::Octets(value.clone())),
+                })
+                .collect();
+            format!("{{ {} }}", parts.join(", "))
+        }
+        Ok(value) => value
+            .as_u64()
+            .and_then(|n| names?.name(n))
+            .map(str::to_string)
+            .unwrap_or_else(|| value.to_string()),
+        Err(_) => AttributeValue::Octets(bytes.to_vec()).to_string(),
+    }
 }
 
 impl Dictionary {
-    pub fn load_embedded() -> Result<Self, String> {
-        let embedded = include_str!("../dictionaries/dictionary");
-        Self::parse_from_str(embedded)
+    /// The bundled dictionaries: the RFC attributes, common vendors and
+    /// the server-internal attributes.
+    pub fn load_embedded() -> Result<Self, RadiusError> {
+        Self::load_from_files(&DictionaryFiles::embedded(), "dictionary")
+    }
+
+    /// Adds (or replaces) a definition and indexes its name, dropping the
+    /// name of any definition it replaces. Vendor
+    /// attributes are also indexed as `Vendor.Attribute` once the vendor
+    /// is known.
+    pub fn insert(&mut self, key: AttributeKey, def: RadiusAttributeDef) {
+        if let AttributeKey::Vendor(vendor_id, _) = key
+            && let Some(vendor) = self.vendor_name(vendor_id)
+        {
+            self.names.insert(format!("{}.{}", vendor, def.name), key);
+        }
+        // The name it had before no longer refers to it.
+        if let Some(old) = self.get(key).map(|old| old.name.clone())
+            && old != def.name
+        {
+            let qualified = format!(".{}", old);
+            self.names.retain(|name, k| *k != key || (*name != old && !name.ends_with(&qualified)));
+            self.forget_nested(&old);
+        }
+        self.names.insert(def.name.clone(), key);
+
+        match key {
+            AttributeKey::Standard(code) => self.attributes.insert(code, def),
+            AttributeKey::Extended(typ, ext_type) => self.extended.insert((typ, ext_type), def),
+            AttributeKey::Vendor(vendor_id, code) => self.vendor_attributes.insert((vendor_id, code), def),
+            AttributeKey::Internal(code) => self.internal.insert(code, def),
+        };
+    }
+
+    pub fn get(&self, key: AttributeKey) -> Option<&RadiusAttributeDef> {
+        match key {
+            AttributeKey::Standard(code) => self.attributes.get(&code),
+            AttributeKey::Extended(typ, ext_type) => self.extended.get(&(typ, ext_type)),
+            AttributeKey::Vendor(vendor_id, code) => self.vendor_attributes.get(&(vendor_id, code)),
+            AttributeKey::Internal(code) => self.internal.get(&code),
+        }
+    }
+
+    fn get_mut(&mut self, key: AttributeKey) -> Option<&mut RadiusAttributeDef> {
+        match key {
+            AttributeKey::Standard(code) => self.attributes.get_mut(&code),
+            AttributeKey::Extended(typ, ext_type) => self.extended.get_mut(&(typ, ext_type)),
+            AttributeKey::Vendor(vendor_id, code) => self.vendor_attributes.get_mut(&(vendor_id, code)),
+            AttributeKey::Internal(code) => self.internal.get_mut(&code),
+        }
+    }
+
+    /// The attribute `path` levels below the top-level attribute at `key`.
+    /// An empty path gives the top-level definition itself.
+    pub fn child(&self, key: AttributeKey, path: &[u32]) -> Option<&RadiusAttributeDef> {
+        path.iter().try_fold(self.get(key)?, |def, n| def.children.get(n))
     }
- pub fn parse_from_str(content: &str) -> Result<Self, String> {
-        let mut attributes = HashMap::new();
-        let mut vendors = HashMap::new();
 
+    /// The dotted name of the attribute `path` levels below `key`.
+    fn full_name(&self, key: AttributeKey, path: &[u32]) -> Option<String> {
+        let mut def = self.get(key)?;
+        let mut name = def.name.clone();
+        for n in path {
+            def = def.children.get(n)?;
+            name = format!("{}.{}", name, def.name);
+        }
+        Some(name)
+    }
+
+    /// Indexes the dotted names of `def` and everything below it.
+    fn index_nested(&mut self, key: AttributeKey, path: Vec<u32>, name: String, def: &RadiusAttributeDef) {
+        for (n, child) in &def.children {
+            let mut child_path = path.clone();
+            child_path.push(*n);
+            self.index_nested(key, child_path, format!("{}.{}", name, child.name), child);
+        }
+        self.nested.insert(name, (key, path));
+    }
+
+    /// Drops the nested names at and below `name`, whose definition is
+    /// being replaced.
+    fn forget_nested(&mut self, name: &str) {
+        let below = format!("{}.", name);
+        self.nested.retain(|nested, _| nested != name && !nested.starts_with(&below));
+    }
+
+    /// Adds `def` as a child of the attribute `parent` levels below `key`,
+    /// under the number `def.code`, and indexes its full dotted name.
+    pub fn insert_child(&mut self, key: AttributeKey, parent: &[u32], def: RadiusAttributeDef) -> Result<(), RadiusError> {
+        let parent_name = self.full_name(key, parent).ok_or_else(|| RadiusError::InvalidDefinition {
+            attribute: def.name.clone(),
+            reason: "no parent attribute".to_string(),
+        })?;
+        let parent_def = self.child(key, parent);
+        let parent_type = parent_def.map(|d| d.data_type.as_str()).unwrap_or_default();
+        let is_key = parent_def.is_some_and(|d| d.flags.key);
+        if !matches!(parent_type, "tlv" | "struct" | "vsa" | "vendor") && !is_key {
+            return Err(RadiusError::InvalidDefinition {
+                reason: format!("parent '{}' is of type {} and cannot have children", parent_name, parent_type),
+                attribute: def.name,
+            });
+        }
+        let mut path = parent.to_vec();
+        path.push(def.code);
+        let name = format!("{}.{}", parent_name, def.name);
+        if let Some(old) = self.full_name(key, &path)
+            && old != name
+        {
+            self.forget_nested(&old);
+        }
+        self.index_nested(key, path, name, &def);
+
+        let mut node = self.get_mut(key);
+        for n in parent {
+            node = node.and_then(|d| d.children.get_mut(n));
+        }
+        if let Some(node) = node {
+            node.children.insert(def.code, def);
+        }
+        Ok(())
+    }
+
+    /// Resolves an attribute name or ALIAS, including nested ones such as
+    /// `Net.Src.IP`, to its top-level key and child path.
+    pub fn lookup_path(&self, name: &str) -> Option<(AttributeKey, &[u32], &RadiusAttributeDef)> {
+        if let Some(&key) = self.names.get(name) {
+            return self.get(key).map(|def| (key, &[][..], def));
+        }
+        let (key, path) = self.nested.get(name)?;
+        self.child(*key, path).map(|def| (*key, path.as_slice(), def))
+    }
+
+    /// Resolves an attribute name such as `Calling-Station-Id`,
+    /// `Frag-Status`, `Microsoft.MS-MPPE-Send-Key` or an ALIAS such as
+    /// `Cleartext-Password`. A nested attribute comes back with the key of
+    /// the top-level attribute carrying it; [`lookup_path`](Self::lookup_path)
+    /// gives the path below that as well.
+    pub fn lookup(&self, name: &str) -> Option<(AttributeKey, &RadiusAttributeDef)> {
+        self.lookup_path(name).map(|(key, _, def)| (key, def))
+    }
+
+    /// Like [`lookup`](Self::lookup), for top-level attributes only.
+    fn lookup_top_level(&self, name: &str) -> Option<(AttributeKey, &RadiusAttributeDef)> {
+        match self.lookup_path(name)? {
+            (key, [], def) => Some((key, def)),
+            _ => None,
+        }
+    }
+
+    /// Records a VALUE line: `name` is `value` of `attribute`. Fails if
+    /// the attribute is not defined.
+    pub fn add_value(&mut self, attribute: &str, name: &str, value: u64) -> Result<(), RadiusError> {
+        let (key, _) = self
+            .lookup_top_level(attribute)
+            .ok_or_else(|| RadiusError::UnknownAttribute(attribute.to_string()))?;
+        self.values.entry(key).or_default().insert(name, value);
+        Ok(())
+    }
+
+    /// The VALUE names of an attribute, if it has any.
+    pub fn value_names(&self, key: AttributeKey) -> Option<&ValueNames> {
+        self.values.get(&key)
+    }
+
+    /// Resolves a value name, e.g. `("Service-Type", "Framed-User")` to
+    /// `Integer(2)`, typed according to the attribute.
+    pub fn value(&self, attribute: &str, name: &str) -> Option<AttributeValue> {
+        let (key, def) = self.lookup_top_level(attribute)?;
+        let number = self.value_names(key)?.value(name)?;
+        AttributeValue::from_number(&def.data_type, number)
+    }
+
+    /// Renders a raw value of the attribute at `key` the way it is shown
+    /// in logs: by VALUE name when it has one, otherwise decoded according
+    /// to the attribute type, or as hex when undefined or malformed. TLVs
+    /// with child definitions are decoded recursively.
+    pub fn format_value(&self, key: AttributeKey, bytes: &[u8]) -> String {
+        match self.get(key) {
+            Some(def) => format_node(def, self.value_names(key), bytes),
+            None => AttributeValue::Octets(bytes.to_vec()).to_string(),
+        }
+    }
+
+    /// The vendor an ATTRIBUTE line belongs to: the old-style trailing
+    /// vendor name (`ATTRIBUTE name code type Vendor`) or the enclosing
+    /// BEGIN-VENDOR block.
+    fn attribute_vendor(&self, parts: &[&str], scope: Option<u32>) -> Option<u32> {
+        parts.get(4).and_then(|field| self.vendors.get(*field).copied()).or(scope)
+    }
+
+    fn vendor_name(&self, vendor_id: u32) -> Option<&str> {
+        self.vendors.iter().find(|(_, id)| **id == vendor_id).map(|(name, _)| name.as_str())
+    }
+
+    /// The vendor ID named by a BEGIN-VENDOR line.
+    fn begin_vendor(&self, parts: &[&str]) -> Result<u32, String> {
+        let name = parts.get(1).ok_or("BEGIN-VENDOR without a vendor name")?;
+        self.vendors
+            .get(*name)
+            .copied()
+            .ok_or_else(|| format!("BEGIN-VENDOR for unknown vendor '{}'", name))
+    }
+
+    /// Inside a vendor block, VALUE lines name the vendor's attributes.
+    fn scoped_name(&self, scope: Option<u32>, attribute: &str) -> String {
+        if let Some(vendor_id) = scope
+            && let Some(vendor) = self.vendor_name(vendor_id)
+        {
+            let scoped = format!("{}.{}", vendor, attribute);
+            if self.names.contains_key(&scoped) {
+                return scoped;
+            }
+        }
+        attribute.to_string()
+    }
+
+    /// Records an ATTRIBUTE line. The number may be a top-level code
+    /// (`60`), an Extended-Type (`241.1`), a dotted path into a `tlv`
+    /// (`60.1.2`), a path relative to the last non-relative attribute
+    /// (`.1.2`), or a child number inside a BEGIN block. Returns warnings
+    /// for unknown options and for clashes with an earlier definition.
+    fn define_attribute(&mut self, parts: &[&str], scope: &mut ParseScope) -> Result<Vec<(DiagnosticKind, String)>, String> {
+        let (name, number, data_type) = (parts[1], parts[2], parts[3]);
+        let vendor = self.attribute_vendor(parts, scope.vendor);
+        let mut flags = scope.flags;
+        let mut warnings = Vec::new();
+        for field in parts[4..].iter().take_while(|f| !f.starts_with('#')) {
+            if !self.vendors.contains_key(*field) {
+                warnings.extend(unknown_options(flags.parse_options(field)));
+            }
+        }
+        let new_def = |code| RadiusAttributeDef {
+            flags,
+            ..RadiusAttributeDef::new(name, code, vendor, data_type)
+        };
+
+        let (key, mut path) = if let Some(relative) = number.strip_prefix('.') {
+            let (key, mut path) = scope
+                .relative
+                .clone()
+                .ok_or_else(|| format!("relative attribute '{}' without a parent", name))?;
+            path.extend(parse_oid(relative)?);
+            (key, path)
+        } else if let Some((key, parent)) = scope.parents.last() {
+            let mut path = parent.clone();
+            path.extend(parse_oid(number)?);
+            (*key, path)
+        } else {
+            let oid = parse_oid(number)?;
+            match (oid[0], oid.get(1)) {
+                (typ, Some(&ext_type)) if vendor.is_none() && u8::try_from(typ).is_ok_and(is_extended_type) => {
+                    let ext_type = u8::try_from(ext_type)
+                        .map_err(|_| format!("Invalid Extended-Type '{}'", number))?;
+                    (AttributeKey::Extended(typ as u8, ext_type), oid[2..].to_vec())
+                }
+                (code, _) => match vendor {
+                    Some(vendor_id) => (AttributeKey::Vendor(vendor_id, code), oid[1..].to_vec()),
+                    None if flags.internal => (AttributeKey::Internal(code), oid[1..].to_vec()),
+                    None => (AttributeKey::Standard(code), oid[1..].to_vec()),
+                },
+            }
+        };
+
+        if !number.starts_with('.') {
+            scope.relative = Some((key, path.clone()));
+        }
+        let Some(code) = path.pop() else {
+            let code = match key {
+                AttributeKey::Extended(_, ext_type) => ext_type as u32,
+                AttributeKey::Standard(code) | AttributeKey::Vendor(_, code) | AttributeKey::Internal(code) => code,
+            };
+            let def = new_def(code);
+            warnings.extend(self.conflict(key, &[], &def));
+            self.insert(key, def);
+            scope.defined(data_type, key, Vec::new());
+            return Ok(warnings);
+        };
+
+        let def = new_def(code);
+        warnings.extend(self.conflict(key, &[path.as_slice(), &[code]].concat(), &def));
+        self.insert_child(key, &path, def).map_err(|e| e.to_string())?;
+        scope.defined(data_type, key, [path.as_slice(), &[code]].concat());
+        // `clone=.Src` gives this attribute the same children as its
+        // sibling `Src`.
+        if let Some(source) = parts.iter().skip(4).find_map(|p| p.strip_prefix("clone=")) {
+            let source = match source.strip_prefix('.') {
+                Some(sibling) => self
+                    .child(key, &path)
+                    .and_then(|parent| parent.children.values().find(|d| d.name == sibling)),
+                None => self.lookup_path(source).map(|(_, _, def)| def),
+            };
+            let children = source
+                .map(|def| def.children.clone())
+                .ok_or_else(|| format!("clone of unknown attribute for '{}'", name))?;
+            path.push(code);
+            for child in children.into_values() {
+                self.insert_child(key, &path, child).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// How `def`, about to be defined `path` levels below `key`, clashes
+    /// with what is already there: another name at the same number, or the
+    /// same name with a different type.
+    fn conflict(&self, key: AttributeKey, path: &[u32], def: &RadiusAttributeDef) -> Option<(DiagnosticKind, String)> {
+        let (name, existing) = match self.child(key, path) {
+            Some(existing) if existing.name != def.name => {
+                return Some((
+                    DiagnosticKind::DuplicateCode,
+                    format!("'{}' replaces '{}', which had the same number", def.name, existing.name),
+                ));
+            }
+            Some(existing) => (def.name.clone(), existing),
+            None => {
+                let name = match (key, path.split_last()) {
+                    (AttributeKey::Vendor(vendor_id, _), None) => {
+                        format!("{}.{}", self.vendor_name(vendor_id)?, def.name)
+                    }
+                    (_, None) => def.name.clone(),
+                    (_, Some((_, parent))) => format!("{}.{}", self.full_name(key, parent)?, def.name),
+                };
+                let (_, _, existing) = self.lookup_path(&name)?;
+                (name, existing)
+            }
+        };
+        (existing.data_type != def.data_type).then(|| {
+            (
+                DiagnosticKind::Redefinition,
+                format!("'{}' redefined as {}, it was {}", name, def.data_type, existing.data_type),
+            )
+        })
+    }
+
+    /// Records an `ALIAS <name> <target>` line: `name` becomes another name
+    /// for the attribute `target`, which must already be defined. Inside a
+    /// BEGIN block the alias is named below the block's attribute, and the
+    /// target may be relative to it.
+    fn define_alias(&mut self, parts: &[&str], scope: &ParseScope) -> Result<(), String> {
+        let [_, name, target, ..] = parts else {
+            return Err("ALIAS needs a name and a target".to_string());
+        };
+        let parent = scope.parents.last().and_then(|(key, path)| self.full_name(*key, path));
+        let (key, path) = parent
+            .iter()
+            .map(|parent| format!("{}.{}", parent, target))
+            .chain([self.scoped_name(scope.vendor, target)])
+            .find_map(|target| self.find_path(&target))
+            .ok_or_else(|| format!("ALIAS '{}' to unknown attribute '{}'", name, target))?;
+
+        let name = match parent {
+            Some(parent) => format!("{}.{}", parent, name),
+            None => name.to_string(),
+        };
+        if self.lookup_path(&name).is_some() {
+            return Err(format!("ALIAS '{}' is already an attribute", name));
+        }
+        if path.is_empty() {
+            self.names.insert(name, key);
+        } else {
+            self.nested.insert(name, (key, path));
+        }
+        Ok(())
+    }
+
+    /// Like [`lookup_path`](Self::lookup_path), but falls back to ignoring
+    /// case, as FreeRADIUS does (`Password.crypt` is `Password.Crypt`).
+    fn find_path(&self, name: &str) -> Option<(AttributeKey, Vec<u32>)> {
+        if let Some((key, path, _)) = self.lookup_path(name) {
+            return Some((key, path.to_vec()));
+        }
+        self.names
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, key)| (*key, Vec::new()))
+            .or_else(|| {
+                self.nested
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|(_, found)| found.clone())
+            })
+    }
+
+    /// Records a `MEMBER <name> <type> [options]` line: the next field of
+    /// the struct being defined. Members are numbered from 1 in order, and
+    /// only the last may be variable-length. Returns warnings for unknown
+    /// options.
+    fn define_member(&mut self, parts: &[&str], scope: &mut ParseScope) -> Result<Vec<(DiagnosticKind, String)>, String> {
+        let [_, name, data_type, options @ ..] = parts else {
+            return Err("MEMBER needs a name and a type".to_string());
+        };
+        let (key, path) = scope
+            .members
+            .clone()
+            .ok_or_else(|| format!("MEMBER '{}' outside a struct", name))?;
+        let parent = self.child(key, &path).ok_or_else(|| format!("no struct for MEMBER '{}'", name))?;
+        if let Some(last) = parent.children.values().next_back()
+            && AttributeValue::fixed_size(&last.data_type).is_none()
+        {
+            return Err(format!("MEMBER '{}' follows '{}', which has no fixed size", name, last.name));
+        }
+        let code = parent.children.keys().next_back().map_or(1, |n| n + 1);
+        let mut flags = scope.flags;
+        let mut warnings = Vec::new();
+        for option in options.iter().take_while(|o| !o.starts_with('#')) {
+            warnings.extend(unknown_options(flags.parse_options(option)));
+        }
+        if flags.key && AttributeValue::from_number(data_type, 0).is_none() {
+            return Err(format!("key MEMBER '{}' must be an integer, not {}", name, data_type));
+        }
+        let def = RadiusAttributeDef {
+            flags,
+            ..RadiusAttributeDef::new(name, code, parent.vendor, data_type)
+        };
+        self.insert_child(key, &path, def).map_err(|e| e.to_string())?;
+
+        let member = [path.as_slice(), &[code]].concat();
+        match *data_type {
+            // Nothing can follow a nested struct, so its members come next.
+            "struct" => scope.members = Some((key, member)),
+            // `.N` attributes after a tlv member are its children.
+            "tlv" => scope.relative = Some((key, member)),
+            _ => {}
+        }
+        Ok(warnings)
+    }
+
+    /// Records a `STRUCT <name> <key member> <number>` line: the layout
+    /// that follows the struct's members when its key member has that
+    /// value. The MEMBER lines after it belong to it.
+    fn define_struct(&mut self, parts: &[&str], scope: &mut ParseScope) -> Result<(), String> {
+        let [_, name, key_member, number, ..] = parts else {
+            return Err("STRUCT needs a name, a key member and a number".to_string());
+        };
+        let base = scope.structure.as_ref().and_then(|(key, path)| self.full_name(*key, path));
+        let (key, mut path) = base
+            .iter()
+            .map(|base| format!("{}.{}", base, key_member))
+            .chain([key_member.to_string()])
+            .find_map(|name| self.find_path(&name))
+            .ok_or_else(|| format!("STRUCT '{}' for unknown key member '{}'", name, key_member))?;
+        let key_def = self.child(key, &path).filter(|def| def.flags.key).ok_or_else(|| {
+            format!("STRUCT '{}': '{}' is not a key member", name, key_member)
+        })?;
+        let code = parse_number(number)?;
+        let def = RadiusAttributeDef::new(name, code, key_def.vendor, "struct");
+        self.insert_child(key, &path, def).map_err(|e| e.to_string())?;
+        path.push(code);
+        scope.members = Some((key, path));
+        Ok(())
+    }
+
+    /// Opens a `BEGIN <attribute>` block: numbers inside it are children
+    /// of that attribute.
+    fn begin(&self, parts: &[&str], scope: &mut ParseScope) -> Result<(), String> {
+        let name = parts.get(1).ok_or("BEGIN without an attribute name")?;
+        let (key, path, _) = self
+            .lookup_path(name)
+            .ok_or_else(|| format!("BEGIN for unknown attribute '{}'", name))?;
+        scope.parents.push((key, path.to_vec()));
+        Ok(())
+    }
+
+    /// Finds a standard attribute by name.
+    pub fn attribute_by_name(&self, name: &str) -> Option<&RadiusAttributeDef> {
+        match self.lookup_top_level(name)? {
+            (AttributeKey::Standard(_), def) => Some(def),
+            _ => None,
+        }
+    }
+
+    /// Looks up an Extended-Type attribute such as `241.1`.
+    pub fn extended_attribute(&self, typ: u8, ext_type: u8) -> Option<&RadiusAttributeDef> {
+        self.extended.get(&(typ, ext_type))
+    }
+
+    /// Parses dictionary text. `$INCLUDE` lines name the bundled
+    /// dictionaries (see [`DictionaryFiles::embedded`]).
+    pub fn parse_from_str(content: &str) -> Result<Self, RadiusError> {
+        Self::parse_from_str_with(content, &DictionaryFiles::embedded())
+    }
+
+    /// Parses dictionary text, resolving `$INCLUDE` against `source`.
+    /// Fails on the first error; warnings are ignored.
+    pub fn parse_from_str_with<S: DictionarySource + ?Sized>(content: &str, source: &S) -> Result<Self, RadiusError> {
+        let (dict, diagnostics) = Self::parse_str_with_diagnostics(content, source);
+        dict.or_first_error(diagnostics)
+    }
+
+    /// Loads the file `name` from `source`, with everything it includes.
+    /// Fails on the first error; warnings are ignored.
+    pub fn load_from_files<S: DictionarySource + ?Sized>(source: &S, name: &str) -> Result<Self, RadiusError> {
+        let (dict, diagnostics) = Self::load_with_diagnostics(source, name)?;
+        dict.or_first_error(diagnostics)
+    }
+
+    /// Loads a dictionary file, and the files it includes, from disk.
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, RadiusError> {
+        Self::load_from_files(&FileSystem, &path.as_ref().to_string_lossy())
+    }
+
+    /// Parses dictionary text, carrying on past problems and returning
+    /// every one of them alongside what could be parsed.
+    pub fn parse_str_with_diagnostics<S: DictionarySource + ?Sized>(
+        content: &str,
+        source: &S,
+    ) -> (Self, Vec<Diagnostic>) {
+        let mut parser = Parser::new(source);
+        parser.parse(None, content);
+        (parser.dict, parser.diagnostics)
+    }
+
+    /// Loads the file `name` from `source`, carrying on past problems and
+    /// returning every one of them. Only failing to read `name` itself is
+    /// an error.
+    pub fn load_with_diagnostics<S: DictionarySource + ?Sized>(
+        source: &S,
+        name: &str,
+    ) -> Result<(Self, Vec<Diagnostic>), RadiusError> {
+        let content = source.read(name).map_err(|e| RadiusError::Io {
+            path: Some(PathBuf::from(name)),
+            source: e,
+        })?;
+        let mut parser = Parser::new(source);
+        parser.visited.insert(name.to_string());
+        parser.parse(Some(name), &content);
+        Ok((parser.dict, parser.diagnostics))
+    }
+
+    fn or_first_error(self, diagnostics: Vec<Diagnostic>) -> Result<Self, RadiusError> {
+        match diagnostics.into_iter().find(|d| d.kind.is_error()) {
+            Some(diagnostic) => Err(diagnostic.into()),
+            None => Ok(self),
+        }
+    }
+}
+
+/// Reads dictionary files from a source, following `$INCLUDE`, and
+/// collects the problems found on the way.
+struct Parser<'s, S: ?Sized> {
+    source: &'s S,
+    dict: Dictionary,
+    diagnostics: Vec<Diagnostic>,
+    /// Files already parsed, so that include cycles end.
+    visited: HashSet<String>,
+}
+
+impl<'s, S: DictionarySource + ?Sized> Parser<'s, S> {
+    fn new(source: &'s S) -> Self {
+        Parser {
+            source,
+            dict: Dictionary::default(),
+            diagnostics: Vec::new(),
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Parses one file. `file` is `None` for text that did not come from
+    /// the source.
+    fn parse(&mut self, file: Option<&str>, content: &str) {
+        let mut scope = ParseScope::default();
         for (lineno, line) in content.lines().enumerate() {
             let line = line.trim();
             if line.is_empty() || line.starts_with('#') {
                 continue;
             }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if let Err((kind, message)) = self.directive(file, lineno + 1, &parts, &mut scope) {
+                self.report(kind, file, lineno + 1, message);
+            }
+        }
+    }
 
-            if line.starts_with("ATTRIBUTE") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 4 {
-                    let name = parts[1].to_string();
-                    let code = parts[2].parse::<u32>()
-                        .map_err(|e| format!("Invalid code on line {}: {}", lineno + 1, e))?;
-                    let data_type = parts[3].to_string();
+    fn report(&mut self, kind: DiagnosticKind, file: Option<&str>, line: usize, message: String) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            file: file.map(PathBuf::from),
+            line,
+            message,
+        });
+    }
 
-                    attributes.insert(code, RadiusAttributeDef {
-                        name,
-                        code,
-                        vendor: None,
-                        data_type,
-                    });
+    fn directive(
+        &mut self,
+        file: Option<&str>,
+        line: usize,
+        parts: &[&str],
+        scope: &mut ParseScope,
+    ) -> Result<(), (DiagnosticKind, String)> {
+        let invalid = |message: String| (DiagnosticKind::Invalid, message);
+        match parts[0] {
+            "$INCLUDE" | "$INCLUDE-" => {
+                let [directive, include] = parts else {
+                    return Err(invalid(format!("{} needs one file name", parts[0])));
+                };
+                let source = self.source;
+                let name = source.resolve(file, include);
+                if self.visited.contains(&name) {
+                    return Ok(());
+                }
+                match source.read(&name) {
+                    Ok(content) => {
+                        self.visited.insert(name.clone());
+                        self.parse(Some(&name), &content);
+                    }
+                    // `$INCLUDE-` tolerates a missing file.
+                    Err(e) if *directive == "$INCLUDE-" && e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err((
+                            DiagnosticKind::UnresolvedInclude,
+                            format!("cannot $INCLUDE '{}': {}", include, e),
+                        ));
+                    }
+                }
+            }
+            "BEGIN-VENDOR" => scope.vendor = Some(self.dict.begin_vendor(parts).map_err(invalid)?),
+            "END-VENDOR" => scope.vendor = None,
+            "BEGIN" => self.dict.begin(parts, scope).map_err(invalid)?,
+            "END" => {
+                scope.parents.pop();
+            }
+            "FLAGS" => {
+                for (kind, message) in unknown_options(scope.flags.parse_options(&parts[1..].join(","))) {
+                    self.report(kind, file, line, message);
+                }
+            }
+            "ATTRIBUTE" => {
+                if parts.len() < 4 {
+                    return Err(invalid("ATTRIBUTE needs a name, number and type".to_string()));
                 }
-            } else if line.starts_with("VENDOR") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 3 {
-                    let name = parts[1].to_string();
-                    let id = parts[2].parse::<u32>()
-                        .map_err(|e| format!("Invalid vendor ID on line {}: {}", lineno + 1, e))?;
-                    vendors.insert(name, id);
+                for (kind, message) in self.dict.define_attribute(parts, scope).map_err(invalid)? {
+                    self.report(kind, file, line, message);
                 }
             }
+            "ALIAS" => self.dict.define_alias(parts, scope).map_err(invalid)?,
+            "MEMBER" => {
+                for (kind, message) in self.dict.define_member(parts, scope).map_err(invalid)? {
+                    self.report(kind, file, line, message);
+                }
+            }
+            "STRUCT" => self.dict.define_struct(parts, scope).map_err(invalid)?,
+            "VALUE" => {
+                if parts.len() < 4 {
+                    return Err(invalid("VALUE needs an attribute, name and number".to_string()));
+                }
+                let attribute = self.dict.scoped_name(scope.vendor, parts[1]);
+                let value = parse_value_number(parts[3]).map_err(invalid)?;
+                self.dict
+                    .add_value(&attribute, parts[2], value)
+                    .map_err(|e| invalid(e.to_string()))?;
+            }
+            "VENDOR" => {
+                if parts.len() < 3 {
+                    return Err(invalid("VENDOR needs a name and number".to_string()));
+                }
+                let id = parse_number(parts[2]).map_err(invalid)?;
+                self.dict.vendors.insert(parts[1].to_string(), id);
+            }
+            directive => {
+                return Err((
+                    DiagnosticKind::UnknownDirective,
+                    format!("unknown directive '{}'", directive),
+                ));
+            }
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{RadiusAttribute, RadiusCode, RadiusPacket};
+    use std::net::Ipv4Addr;
+
+    const ALIASES: &str = "\
+ATTRIBUTE\tUser-Name\t1\tstring
+ATTRIBUTE\tNet\t200\ttlv
+ATTRIBUTE\tSrc-IP\t.1\tipaddr
+ATTRIBUTE\tDst-IP\t.2\tipaddr
+ALIAS\tLogin\tUser-Name
+ALIAS\tSource\tNet.Src-IP
+";
 
-        Ok(Dictionary { attributes, vendors })
+    fn packet() -> RadiusPacket {
+        RadiusPacket {
+            code: RadiusCode::AccessRequest,
+            identifier: 1,
+            length: 20,
+            authenticator: [0; 16],
+            attributes: Vec::new(),
+        }
     }
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
-        let mut attributes = HashMap::new();
-        let mut vendors = HashMap::new();
-        let mut visited = HashSet::new();
 
-        fn parse_number(s: &str) -> Result<u32, String> {
-            let s = s.trim();
-            if s.starts_with("0x") || s.starts_with("0X") {
-                u32::from_str_radix(&s[2..], 16)
-                    .map_err(|e| format!("Invalid hex '{}': {}", s, e))
-            } else {
-                s.parse::<u32>()
-                    .map_err(|e| format!("Invalid number '{}': {}", s, e))
-            }
+    #[test]
+    fn alias_resolves_to_its_target() {
+        let dict = Dictionary::parse_from_str(ALIASES).unwrap();
+        assert_eq!(dict.lookup("Login").unwrap().0, dict.lookup("User-Name").unwrap().0);
+        let (key, path, def) = dict.lookup_path("Source").unwrap();
+        assert_eq!((key, path, def.name.as_str()), (AttributeKey::Standard(200), &[1][..], "Src-IP"));
+        assert_eq!(dict.lookup_path("Net.Src-IP").unwrap().1, &[1][..]);
+    }
+
+    #[test]
+    fn packet_accessors_follow_aliases() {
+        let dict = Dictionary::parse_from_str(ALIASES).unwrap();
+        let mut packet = packet();
+        packet.add_typed(&dict, "Login", AttributeValue::String("alice".into())).unwrap();
+        assert_eq!(packet.attr(&dict, "User-Name").unwrap().as_ref(), b"alice");
+        assert_eq!(packet.render(&dict), ["User-Name = alice"]);
+
+        packet.set(&dict, "Login", AttributeValue::String("bob".into())).unwrap();
+        assert_eq!(packet.get_typed(&dict, "User-Name").unwrap(), Some(AttributeValue::String("bob".into())));
+        assert_eq!(packet.remove(&dict, "Login"), 1);
+        assert!(packet.attributes.is_empty());
+    }
+
+    #[test]
+    fn packet_accessors_follow_aliases_into_tlvs() {
+        let dict = Dictionary::parse_from_str(ALIASES).unwrap();
+        let mut packet = packet();
+        packet.add_typed(&dict, "Source", AttributeValue::IpAddr(Ipv4Addr::new(192, 0, 2, 1))).unwrap();
+        packet.add_typed(&dict, "Net.Dst-IP", AttributeValue::IpAddr(Ipv4Addr::new(192, 0, 2, 2))).unwrap();
+        assert_eq!(packet.attributes[0].value, [1, 6, 192, 0, 2, 1]);
+
+        let source = AttributeValue::IpAddr(Ipv4Addr::new(192, 0, 2, 1));
+        assert_eq!(packet.get_typed(&dict, "Net.Src-IP").unwrap(), Some(source.clone()));
+        assert_eq!(packet.get_typed(&dict, "Source").unwrap(), Some(source));
+        assert_eq!(packet.attrs_all(&dict, "Net.Dst-IP").len(), 1);
+
+        assert_eq!(packet.remove(&dict, "Source"), 1);
+        assert_eq!(packet.get_typed(&dict, "Source").unwrap(), None);
+        assert_eq!(packet.attributes.len(), 1);
+        assert_eq!(packet.attributes[0].value, [2, 6, 192, 0, 2, 2]);
+    }
+
+    fn diagnostics(files: &[(&str, &'static str)]) -> (Dictionary, Vec<(DiagnosticKind, Option<PathBuf>, usize)>) {
+        let mut source = DictionaryFiles::new();
+        for (name, content) in files {
+            source.insert(name, *content);
         }
+        let (dict, diagnostics) = Dictionary::load_with_diagnostics(&source, files[0].0).unwrap();
+        (dict, diagnostics.into_iter().map(|d| (d.kind, d.file, d.line)).collect())
+    }
 
-        fn parse_file(
-            path: PathBuf,
-            attributes: &mut HashMap<u32, RadiusAttributeDef>,
-            vendors: &mut HashMap<String, u32>,
-            visited: &mut HashSet<PathBuf>,
-        ) -> Result<(), String> {
-            if !visited.insert(path.clone()) {
-                return Ok(()); // Prevent cyclic includes
-            }
+    #[test]
+    fn reports_each_diagnostic_with_file_and_line() {
+        let (_, found) = diagnostics(&[
+            (
+                "dictionary",
+                "ATTRIBUTE\tUser-Name\t1\tstring\n\
+                 ATTRIBUTE\tBroken\tone\tstring\n\
+                 $INCLUDE\tdictionary.extra\n\
+                 $INCLUDE\tdictionary.missing\n\
+                 FROBNICATE\tUser-Name\n",
+            ),
+            (
+                "dictionary.extra",
+                "ATTRIBUTE\tLogin-Name\t1\tstring\n\
+                 ATTRIBUTE\tLogin-Name\t1\tinteger\n\
+                 ATTRIBUTE\tFiltered\t11\tstring\tsparkly\n",
+            ),
+        ]);
+        let main = Some(PathBuf::from("dictionary"));
+        let extra = Some(PathBuf::from("dictionary.extra"));
+        assert_eq!(
+            found,
+            [
+                (DiagnosticKind::Invalid, main.clone(), 2),
+                (DiagnosticKind::DuplicateCode, extra.clone(), 1),
+                (DiagnosticKind::Redefinition, extra.clone(), 2),
+                (DiagnosticKind::UnknownOption, extra, 3),
+                (DiagnosticKind::UnresolvedInclude, main.clone(), 4),
+                (DiagnosticKind::UnknownDirective, main, 5),
+            ]
+        );
+    }
 
-            let content = fs::read_to_string(&path)
-                .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    #[test]
+    fn replaced_definition_takes_over_the_name_index() {
+        let (dict, _) = diagnostics(&[(
+            "dictionary",
+            "ATTRIBUTE\tUser-Name\t1\tstring\n\
+             ATTRIBUTE\tLogin-Name\t1\tstring\n\
+             ATTRIBUTE\tCount\t2\tstring\n\
+             ATTRIBUTE\tCount\t3\tinteger\n",
+        )]);
+        assert!(dict.lookup("User-Name").is_none());
+        assert_eq!(dict.lookup("Login-Name").unwrap().0, AttributeKey::Standard(1));
+        let (key, def) = dict.lookup("Count").unwrap();
+        assert_eq!((key, def.data_type.as_str()), (AttributeKey::Standard(3), "integer"));
+    }
 
-            for (lineno, line) in content.lines().enumerate() {
-                let line = line.trim();
-                if line.is_empty() || line.starts_with('#') {
-                    continue;
-                }
+    #[test]
+    fn bundled_aliases_reach_internal_attributes() {
+        let dict = Dictionary::load_embedded().unwrap();
+        let (key, path, def) = dict.lookup_path("Cleartext-Password").unwrap();
+        let (target_key, target_path, target) = dict.lookup_path("Password.Cleartext").unwrap();
+        assert_eq!((key, path), (target_key, target_path));
+        assert_eq!(def.name, target.name);
 
-                if line.starts_with("$INCLUDE") {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if parts.len() == 2 {
-                        let include_path = path.parent().unwrap().join(parts[1]);
-                        parse_file(include_path, attributes, vendors, visited)?;
-                    }
-                    continue;
-                }
+        let mut packet = packet();
+        let err = packet.add_typed(&dict, "Cleartext-Password", AttributeValue::String("secret".into()));
+        assert!(matches!(err, Err(RadiusError::InternalAttribute { .. })));
+        assert!(packet.attrs_all(&dict, "Cleartext-Password").is_empty());
+    }
 
-                if line.starts_with("ATTRIBUTE") {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if parts.len() >= 4 {
-                        if parts[2].contains('.') {
-                            // eprintln!(
-                            //     "⚠️ Skipping unsupported dotted ATTRIBUTE code '{}' in file {:?} at line {}",
-                            //     parts[2], path, lineno + 1
-                            // );
-                            continue;
-                        }
-                        let name = parts[1].to_string();
-                        let code = match parse_number(parts[2]) {
-                            Ok(code) => code,
-                            Err(e) => {
-                                eprintln!(
-                                    "❌ Error: {} in file {:?} at line {}",
-                                    e, path, lineno + 1
-                                );
-                                continue;
-                            }
-                        };
-                        let data_type = parts[3].to_string();
-                        attributes.insert(
-                            code,
-                            RadiusAttributeDef {
-                                name,
-                                code,
-                                vendor: None,
-                                data_type,
-                            },
-                        );
-                    }
-                    continue;
-                }
+    const TREE: &str = "\
+ATTRIBUTE\tNet\t200\ttlv
+ATTRIBUTE\tSrc\t200.1\ttlv
+ATTRIBUTE\tIP\t.1\tipaddr
+ATTRIBUTE\tPort\t.2\tshort
+ATTRIBUTE\tDst\t200.2\ttlv\tclone=.Src
+BEGIN\tNet
+ATTRIBUTE\tLabel\t3\tstring
+END\tNet
+";
 
-                if line.starts_with("VENDOR") {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if parts.len() >= 3 {
-                        if parts[2].contains('.') {
-                            // eprintln!(
-                            //     "⚠️ Skipping unsupported dotted VENDOR ID '{}' in file {:?} at line {}",
-                            //     parts[2], path, lineno + 1
-                            // );
-                            continue;
-                        }
-                        let name = parts[1].to_string();
-                        let id = match parse_number(parts[2]) {
-                            Ok(id) => id,
-                            Err(e) => {
-                                eprintln!(
-                                    "❌ Error: {} in file {:?} at line {}",
-                                    e, path, lineno + 1
-                                );
-                                continue;
-                            }
-                        };
-                        vendors.insert(name, id);
-                    }
-                    continue;
-                }
+    #[test]
+    fn dotted_numbers_build_an_attribute_tree() {
+        let dict = Dictionary::parse_from_str(TREE).unwrap();
+        let path = |name: &str| dict.lookup_path(name).map(|(key, path, def)| (key, path.to_vec(), def.name.clone()));
+        let net = AttributeKey::Standard(200);
+        assert_eq!(path("Net.Src.IP"), Some((net, vec![1, 1], "IP".to_string())));
+        assert_eq!(path("Net.Src.Port"), Some((net, vec![1, 2], "Port".to_string())));
+        assert_eq!(path("Net.Dst.Port"), Some((net, vec![2, 2], "Port".to_string())));
+        assert_eq!(path("Net.Label"), Some((net, vec![3], "Label".to_string())));
+        assert_eq!(path("Label"), None);
+        assert_eq!(dict.child(net, &[2]).unwrap().children.len(), 2);
+    }
 
-                // Support other directives like BEGIN-VENDOR, VALUE, etc., as needed.
-            }
+    #[test]
+    fn nested_tlvs_encode_and_decode_along_the_tree() {
+        let dict = Dictionary::parse_from_str(TREE).unwrap();
+        let mut packet = packet();
+        packet.add_typed(&dict, "Net.Src.IP", AttributeValue::IpAddr(Ipv4Addr::new(192, 0, 2, 1))).unwrap();
+        assert_eq!(packet.attributes[0].value, [1, 8, 1, 6, 192, 0, 2, 1]);
+        packet.add_typed(&dict, "Net.Label", AttributeValue::String("uplink".into())).unwrap();
+
+        let tree = packet.get_tree(&dict, "Net").unwrap().unwrap();
+        assert_eq!(
+            tree.get("Src.IP").and_then(ValueTree::value),
+            Some(&AttributeValue::IpAddr(Ipv4Addr::new(192, 0, 2, 1)))
+        );
+        assert_eq!(
+            packet.get_typed(&dict, "Net.Label").unwrap(),
+            Some(AttributeValue::String("uplink".into()))
+        );
+        assert_eq!(packet.get_typed(&dict, "Net.Dst.IP").unwrap(), None);
+    }
+
+    const OPTIONS: &str = "\
+ATTRIBUTE\tUser-Password\t2\tstring\tencrypt=1
+ATTRIBUTE\tTunnel-Password\t69\tstring\thas_tag,encrypt=2
+ATTRIBUTE\tEAP-Message\t79\toctets\tconcat
+ATTRIBUTE\tHosts\t201\tipaddr\tarray
+ATTRIBUTE\tOdd\t202\tstring\tencrypt=9,sparkly,clone=Hosts
+FLAGS\tinternal
+ATTRIBUTE\tAuth-Type\t1000\tinteger
+FLAGS\t!internal,bogus
+ATTRIBUTE\tPublic\t203\tstring
+";
+
+    #[test]
+    fn parses_options_and_flags() {
+        let (dict, diagnostics) = Dictionary::parse_str_with_diagnostics(OPTIONS, &DictionaryFiles::new());
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.kind, d.line, d.message.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (DiagnosticKind::UnknownOption, 5, "unknown encrypt method '9', ignored"),
+                (DiagnosticKind::UnknownOption, 5, "unknown option 'sparkly', ignored"),
+                (DiagnosticKind::UnknownOption, 8, "unknown option 'bogus', ignored"),
+            ]
+        );
+
+        let flags = |name: &str| dict.lookup(name).unwrap().1.flags;
+        assert_eq!(flags("User-Password").encrypt, Some(Encryption::UserPassword));
+        let tunnel = flags("Tunnel-Password");
+        assert_eq!((tunnel.has_tag, tunnel.encrypt), (true, Some(Encryption::TunnelPassword)));
+        assert!(flags("EAP-Message").concat);
+        assert!(flags("Hosts").array);
+        assert_eq!(flags("Odd"), AttributeFlags::default());
+
+        let (key, def) = dict.lookup("Auth-Type").unwrap();
+        assert_eq!((key, def.flags.internal), (AttributeKey::Internal(1000), true));
+        let (key, def) = dict.lookup("Public").unwrap();
+        assert_eq!((key, def.flags.internal), (AttributeKey::Standard(203), false));
+    }
+
+    #[test]
+    fn packets_apply_attribute_options() {
+        let dict = Dictionary::parse_from_str(OPTIONS).unwrap();
+        let mut packet = packet();
+        let password = AttributeValue::String("hunter2".into());
+        assert!(matches!(
+            packet.add_typed(&dict, "User-Password", password.clone()),
+            Err(RadiusError::SecretRequired(_))
+        ));
+        packet.add_typed_with_secret(&dict, "User-Password", password.clone(), "s3cret").unwrap();
+        assert_eq!(packet.attributes[0].value.len(), 16);
+        assert_eq!(packet.get_typed_with_secret(&dict, "User-Password", "s3cret").unwrap(), Some(password.clone()));
+
+        packet.add_tagged(&dict, "Tunnel-Password", 3, password.clone(), Some("s3cret")).unwrap();
+        assert_eq!(packet.attributes[1].value[0], 3);
+        assert_eq!(packet.get_tagged(&dict, "Tunnel-Password", Some("s3cret")).unwrap(), Some((3, password)));
 
-            Ok(())
+        let eap = AttributeValue::Octets(vec![0x42; 600]);
+        packet.add_typed(&dict, "EAP-Message", eap.clone()).unwrap();
+        assert_eq!(packet.attributes.iter().filter(|a| a.typ == 79).count(), 3);
+        assert_eq!(packet.get_typed(&dict, "EAP-Message").unwrap(), Some(eap));
+
+        let hosts = [Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)].map(AttributeValue::IpAddr);
+        for host in &hosts {
+            packet.add_typed(&dict, "Hosts", host.clone()).unwrap();
         }
+        assert_eq!(packet.attributes.last().unwrap().value.len(), 8);
+        assert_eq!(packet.get_all_typed(&dict, "Hosts").unwrap(), hosts);
+    }
+
+    #[test]
+    fn includes_resolve_through_dictionary_files() {
+        let mut files = DictionaryFiles::new();
+        files.insert("dictionary", "$INCLUDE\tvendor/dictionary.acme\n$INCLUDE-\tdictionary.local\n");
+        files.insert(
+            "vendor/dictionary.acme",
+            "VENDOR\tAcme\t9999\n$INCLUDE\t./common/dictionary.acme\n$INCLUDE\t../dictionary\n",
+        );
+        files.insert(
+            "vendor/common/dictionary.acme",
+            "BEGIN-VENDOR\tAcme\nATTRIBUTE\tAcme-Level\t1\tinteger\nEND-VENDOR\tAcme\n",
+        );
+
+        let (dict, diagnostics) = Dictionary::load_with_diagnostics(&files, "dictionary").unwrap();
+        assert_eq!(diagnostics, []);
+        assert_eq!(dict.lookup("Acme.Acme-Level").unwrap().0, AttributeKey::Vendor(9999, 1));
+
+        files.insert("dictionary", "$INCLUDE\tdictionary.local\n");
+        let err = Dictionary::load_from_files(&files, "dictionary").unwrap_err();
+        assert!(err.to_string().contains("dictionary.local"), "{}", err);
+        assert!(Dictionary::load_from_files(&files, "dictionary.missing").is_err());
+    }
+
+    #[test]
+    fn parse_from_str_includes_the_bundled_dictionaries() {
+        let dict = Dictionary::parse_from_str("$INCLUDE\tdictionary.rfc2865\n").unwrap();
+        assert_eq!(dict.lookup("User-Name").unwrap().0, AttributeKey::Standard(1));
+        assert!(dict.lookup("Acct-Status-Type").is_none());
+
+        let dict = Dictionary::load_embedded().unwrap();
+        assert_eq!(dict.lookup("Acct-Status-Type").unwrap().0, AttributeKey::Standard(40));
+        assert!(dict.lookup("Microsoft.MS-CHAP-Challenge").is_some());
+    }
+
+    const STRUCTS: &str = "\
+ATTRIBUTE\tCapability\t210\tstruct
+MEMBER\tVersion\tbyte
+MEMBER\tKind\tbyte\tkey
+STRUCT\tRatio\tKind\t1
+MEMBER\tUp\tshort
+MEMBER\tDown\tshort
+STRUCT\tLabel\tKind\t2
+MEMBER\tText\tstring
+";
+
+    #[test]
+    fn struct_variants_follow_the_key_member() {
+        let dict = Dictionary::parse_from_str(STRUCTS).unwrap();
+        let mut packet = packet();
+        packet.attributes.push(RadiusAttribute::new(210, vec![4, 1, 0, 10, 0, 20]).unwrap());
+
+        let ratio = ValueTree::group()
+            .member("Up", AttributeValue::Short(10))
+            .member("Down", AttributeValue::Short(20));
+        let expected = ValueTree::group()
+            .member("Version", AttributeValue::Byte(4))
+            .member("Kind", AttributeValue::Byte(1))
+            .member("Ratio", ratio);
+        assert_eq!(packet.get_tree(&dict, "Capability").unwrap(), Some(expected));
+
+        packet.attributes[0].value = vec![4, 3, 0];
+        assert!(packet.get_tree(&dict, "Capability").is_err());
+    }
+
+    #[test]
+    fn struct_trees_round_trip() {
+        let dict = Dictionary::parse_from_str(STRUCTS).unwrap();
+        let mut packet = packet();
+        // Members in any order; the key is implied by the variant present.
+        let label = ValueTree::group().member("Text", AttributeValue::String("uplink".into()));
+        let tree = ValueTree::group().member("Label", label).member("Version", AttributeValue::Byte(4));
+        packet.add_tree(&dict, "Capability", &tree).unwrap();
+        assert_eq!(packet.attributes[0].value, b"\x04\x02uplink");
+
+        let decoded = packet.get_tree(&dict, "Capability").unwrap().unwrap();
+        assert_eq!(decoded.get("Kind").and_then(ValueTree::value), Some(&AttributeValue::Byte(2)));
+        assert_eq!(
+            decoded.get("Label.Text").and_then(ValueTree::value),
+            Some(&AttributeValue::String("uplink".into()))
+        );
+        packet.add_tree(&dict, "Capability", &decoded).unwrap();
+        assert_eq!(packet.attributes[1].value, packet.attributes[0].value);
+
+        let missing = ValueTree::group().member("Kind", AttributeValue::Byte(1));
+        assert!(matches!(packet.add_tree(&dict, "Capability", &missing), Err(RadiusError::MissingMember { .. })));
+        let unknown = ValueTree::group().member("Colour", AttributeValue::Byte(1));
+        assert!(matches!(packet.add_tree(&dict, "Capability", &unknown), Err(RadiusError::UnknownAttribute(_))));
+        assert!(matches!(
+            packet.add_typed(&dict, "Capability.Version", AttributeValue::Byte(4)),
+            Err(RadiusError::StructMember(_))
+        ));
+    }
 
-        parse_file(path.as_ref().to_path_buf(), &mut attributes, &mut vendors, &mut visited)?;
-        Ok(Dictionary { attributes, vendors })
+    #[test]
+    fn only_the_last_member_may_be_variable_length() {
+        let (dict, found) = diagnostics(&[(
+            "dictionary",
+            "ATTRIBUTE\tRecord\t211\tstruct\n\
+             MEMBER\tName\tstring\n\
+             MEMBER\tAge\tbyte\n\
+             MEMBER\tTag\tstring\tkey\n",
+        )]);
+        let main = Some(PathBuf::from("dictionary"));
+        assert_eq!(found, [(DiagnosticKind::Invalid, main.clone(), 3), (DiagnosticKind::Invalid, main, 4)]);
+        let record = dict.lookup("Record").unwrap().1;
+        assert_eq!(record.children.values().map(|m| m.name.as_str()).collect::<Vec<_>>(), ["Name"]);
     }
 }
@@ -0,0 +1,642 @@
+//! EAP over RADIUS (RFC 3579): EAP-Message reassembly, State-keyed
+//! conversations and pluggable EAP methods.
+
+use crate::error::RadiusError;
+use crate::handler::constant_time_eq;
+use crate::mschap::{encrypt_mppe_key, mppe_salts, ms_attribute, KnownPassword, MS_MPPE_RECV_KEY, MS_MPPE_SEND_KEY};
+use crate::packet::{chap_response, RadiusAttribute, RadiusCode, RadiusPacket};
+use rand::RngCore;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub mod aka;
+pub mod milenage;
+pub mod mschapv2;
+pub mod sim;
+pub mod simaka;
+pub mod vectors;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "tls")]
+pub mod peap;
+#[cfg(feature = "tls")]
+pub mod ttls;
+#[cfg(feature = "tls")]
+pub mod supplicant;
+
+pub const EAP_REQUEST: u8 = 1;
+pub const EAP_RESPONSE: u8 = 2;
+pub const EAP_SUCCESS: u8 = 3;
+pub const EAP_FAILURE: u8 = 4;
+
+pub const EAP_TYPE_IDENTITY: u8 = 1;
+pub const EAP_TYPE_NOTIFICATION: u8 = 2;
+pub const EAP_TYPE_NAK: u8 = 3;
+pub const EAP_TYPE_MD5_CHALLENGE: u8 = 4;
+pub const EAP_TYPE_TLS: u8 = 13;
+pub const EAP_TYPE_SIM: u8 = 18;
+pub const EAP_TYPE_TTLS: u8 = 21;
+pub const EAP_TYPE_AKA: u8 = 23;
+pub const EAP_TYPE_PEAP: u8 = 25;
+pub const EAP_TYPE_MSCHAPV2: u8 = 26;
+pub const EAP_TYPE_AKA_PRIME: u8 = 50;
+
+/// How long an unanswered Access-Challenge keeps its conversation alive.
+pub const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Looks up the known-good credential for an EAP identity.
+pub type PasswordLookup = Arc<dyn Fn(&str) -> Option<KnownPassword> + Send + Sync>;
+
+/// A single EAP packet (RFC 3748 §4).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EapPacket {
+    pub code: u8,
+    pub identifier: u8,
+    /// The method type; `None` for Success and Failure.
+    pub typ: Option<u8>,
+    pub data: Vec<u8>,
+}
+
+impl EapPacket {
+    pub fn request(identifier: u8, typ: u8, data: Vec<u8>) -> Self {
+        EapPacket { code: EAP_REQUEST, identifier, typ: Some(typ), data }
+    }
+
+    pub fn response(identifier: u8, typ: u8, data: Vec<u8>) -> Self {
+        EapPacket { code: EAP_RESPONSE, identifier, typ: Some(typ), data }
+    }
+
+    pub fn success(identifier: u8) -> Self {
+        EapPacket { code: EAP_SUCCESS, identifier, typ: None, data: vec![] }
+    }
+
+    pub fn failure(identifier: u8) -> Self {
+        EapPacket { code: EAP_FAILURE, identifier, typ: None, data: vec![] }
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, RadiusError> {
+        if buf.len() < 4 {
+            return Err(RadiusError::InvalidEap("packet too short".to_string()));
+        }
+
+        let code = buf[0];
+        let identifier = buf[1];
+        let length = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if length < 4 || length > buf.len() {
+            return Err(RadiusError::InvalidEap(format!(
+                "length mismatch: header says {}, but got {} bytes",
+                length,
+                buf.len()
+            )));
+        }
+
+        match code {
+            EAP_REQUEST | EAP_RESPONSE => {
+                if length < 5 {
+                    return Err(RadiusError::InvalidEap("request/response without a type".to_string()));
+                }
+                Ok(EapPacket {
+                    code,
+                    identifier,
+                    typ: Some(buf[4]),
+                    data: buf[5..length].to_vec(),
+                })
+            }
+            EAP_SUCCESS | EAP_FAILURE => Ok(EapPacket { code, identifier, typ: None, data: vec![] }),
+            _ => Err(RadiusError::InvalidEap(format!("unknown code {}", code))),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![self.code, self.identifier, 0, 0];
+        if let Some(typ) = self.typ {
+            buf.push(typ);
+            buf.extend_from_slice(&self.data);
+        }
+        let length = buf.len() as u16;
+        buf[2..4].copy_from_slice(&length.to_be_bytes());
+        buf
+    }
+}
+
+/// Key material exported by a successful method, named after EAP-MSK,
+/// EAP-EMSK and EAP-Session-Id in `dictionary.freeradius.internal.eap`.
+#[derive(Debug, Clone, Default)]
+pub struct EapKeys {
+    pub msk: Vec<u8>,
+    pub emsk: Vec<u8>,
+    pub session_id: Vec<u8>,
+    /// The identity authenticated inside a tunnel (PEAP, TTLS), which may
+    /// differ from the anonymous outer identity.
+    pub identity: Option<String>,
+}
+
+/// What a method wants to do after processing a response.
+#[derive(Debug)]
+pub enum EapStep {
+    /// Send another EAP-Request of the method's type with this type-data.
+    Continue(Vec<u8>),
+    /// Authentication succeeded.
+    Success(EapKeys),
+    /// Authentication failed, with a reason for the server log.
+    Failure(String),
+}
+
+/// An EAP method that can be registered with an [`EapServer`].
+pub trait EapMethod: Send + Sync {
+    fn eap_type(&self) -> u8;
+
+    /// Begins a conversation for `identity`, returning the per-conversation
+    /// state and the type-data of the first EAP-Request.
+    fn start(&self, identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError>;
+}
+
+/// Per-conversation state of an [`EapMethod`].
+pub trait EapSession: Send {
+    /// Consumes the peer's EAP-Response of this method's type.
+    fn process(&mut self, response: &EapPacket) -> EapStep;
+}
+
+/// Values kept between Access-Challenge round trips, keyed by the State
+/// attribute sent to the NAS. Entries not claimed within the timeout are
+/// discarded.
+pub struct EapSessionStore<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<Vec<u8>, (Instant, T)>>,
+}
+
+impl<T> EapSessionStore<T> {
+    pub fn new(ttl: Duration) -> Self {
+        EapSessionStore {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Stores `value` under a fresh random State and returns that State.
+    pub fn insert(&self, value: T) -> Vec<u8> {
+        let mut state = vec![0u8; 16];
+        rand::rng().fill_bytes(&mut state);
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (expires, _)| *expires > now);
+        entries.insert(state.clone(), (now + self.ttl, value));
+        state
+    }
+
+    /// Stores `value` under an existing State, replacing what was there.
+    pub fn insert_at(&self, state: &[u8], value: T) {
+        let expires = Instant::now() + self.ttl;
+        self.entries.lock().unwrap().insert(state.to_vec(), (expires, value));
+    }
+
+    /// Looks at the value for `state` without removing it, unless it has
+    /// expired.
+    pub fn peek<R>(&self, state: &[u8], f: impl FnOnce(&T) -> R) -> Option<R> {
+        let entries = self.entries.lock().unwrap();
+        let (expires, value) = entries.get(state)?;
+        (*expires > Instant::now()).then(|| f(value))
+    }
+
+    /// Removes and returns the value for `state`, unless it has expired.
+    pub fn take(&self, state: &[u8]) -> Option<T> {
+        let (expires, value) = self.entries.lock().unwrap().remove(state)?;
+        (expires > Instant::now()).then_some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct Conversation {
+    identity: String,
+    /// Identifier of the outstanding EAP-Request.
+    last_id: u8,
+    method: Option<(u8, Box<dyn EapSession>)>,
+}
+
+/// The RADIUS reply produced by [`EapServer::handle`].
+pub enum EapReply {
+    Challenge(RadiusPacket),
+    Accept {
+        reply: RadiusPacket,
+        identity: String,
+        keys: EapKeys,
+    },
+    Reject(RadiusPacket),
+    /// The request is not answered at all, so the NAS keeps the session
+    /// and retransmits; used for responses to a superseded EAP-Request.
+    Discard(String),
+}
+
+impl EapReply {
+    /// The packet to send, or [`RadiusError::Discarded`] for
+    /// [`EapReply::Discard`], which the listeners drop without a reply.
+    pub fn into_packet(self) -> Result<RadiusPacket, RadiusError> {
+        match self {
+            EapReply::Challenge(reply) | EapReply::Accept { reply, .. } | EapReply::Reject(reply) => Ok(reply),
+            EapReply::Discard(reason) => Err(RadiusError::Discarded(reason)),
+        }
+    }
+}
+
+/// Drives EAP conversations across Access-Request / Access-Challenge round
+/// trips and dispatches to the registered methods.
+pub struct EapServer {
+    methods: Vec<Arc<dyn EapMethod>>,
+    sessions: EapSessionStore<Conversation>,
+    /// The Access-Challenge sent for each answered State, keyed by that
+    /// State, so a retransmitted Access-Request gets the same reply.
+    challenges: EapSessionStore<(u8, RadiusPacket)>,
+}
+
+impl Default for EapServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EapServer {
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_SESSION_TIMEOUT)
+    }
+
+    pub fn with_timeout(ttl: Duration) -> Self {
+        EapServer {
+            methods: Vec::new(),
+            sessions: EapSessionStore::new(ttl),
+            challenges: EapSessionStore::new(ttl),
+        }
+    }
+
+    /// Adds a method. The first registered method is proposed to new peers;
+    /// the others are offered when the peer answers with a NAK.
+    pub fn register<M: EapMethod + 'static>(mut self, method: M) -> Self {
+        self.methods.push(Arc::new(method));
+        self
+    }
+
+    /// Number of conversations currently waiting for the peer.
+    pub fn pending(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Processes the EAP-Message in an Access-Request and builds the reply.
+    pub fn handle(&self, request: &RadiusPacket, secret: &str) -> EapReply {
+        let eap = match request.eap_message().map(|raw| EapPacket::from_bytes(&raw)) {
+            Some(Ok(eap)) if eap.code == EAP_RESPONSE => eap,
+            Some(Ok(_)) => return self.reject(request, 0, "EAP packet is not a response"),
+            Some(Err(e)) => return self.reject(request, 0, &e.to_string()),
+            None => return self.reject(request, 0, "missing EAP-Message"),
+        };
+
+        let mut conversation = match request.state() {
+            Some(state) => {
+                let cached = self.challenges.peek(state, |(id, reply)| (*id == eap.identifier).then(|| reply.clone()));
+                if let Some(mut reply) = cached.flatten() {
+                    println!("🔁 Resending Access-Challenge for a retransmitted EAP-Response");
+                    reply.identifier = request.identifier;
+                    reply.authenticator = request.authenticator;
+                    return EapReply::Challenge(reply);
+                }
+                // Only a response to the outstanding request may consume the
+                // conversation; anything else leaves it for the real peer.
+                match self.sessions.peek(state, |c| c.last_id) {
+                    Some(id) if id == eap.identifier => match self.sessions.take(state) {
+                        Some(c) => c,
+                        None => return self.reject(request, eap.identifier, "unknown or expired State"),
+                    },
+                    Some(_) => return self.discard("EAP identifier mismatch"),
+                    None => return self.reject(request, eap.identifier, "unknown or expired State"),
+                }
+            }
+            None => Conversation {
+                identity: String::new(),
+                last_id: eap.identifier,
+                method: None,
+            },
+        };
+
+        let typ = eap.typ.unwrap_or(0);
+        let step = match conversation.method.as_mut() {
+            None if typ == EAP_TYPE_IDENTITY => {
+                conversation.identity = String::from_utf8_lossy(&eap.data).to_string();
+                let preferred: Vec<u8> = self.methods.iter().map(|m| m.eap_type()).collect();
+                return self.start(request, &eap, conversation, &preferred);
+            }
+            Some((current, _)) if typ == EAP_TYPE_NAK => {
+                let current = *current;
+                let proposed: Vec<u8> = eap.data.iter().copied().filter(|t| *t != current).collect();
+                return self.start(request, &eap, conversation, &proposed);
+            }
+            Some((current, session)) if typ == *current => session.process(&eap),
+            _ => EapStep::Failure(format!("unexpected EAP type {}", typ)),
+        };
+
+        let method_type = conversation.method.as_ref().map(|(t, _)| *t).unwrap_or(0);
+        match step {
+            EapStep::Continue(data) => self.challenge(request, &eap, conversation, method_type, data),
+            EapStep::Success(keys) => {
+                let identity = keys.identity.clone().unwrap_or(conversation.identity);
+                self.accept(request, &eap, identity, keys, secret)
+            }
+            EapStep::Failure(reason) => self.reject(request, eap.identifier, &reason),
+        }
+    }
+
+    fn start(
+        &self,
+        request: &RadiusPacket,
+        eap: &EapPacket,
+        mut conversation: Conversation,
+        candidates: &[u8],
+    ) -> EapReply {
+        let Some(method) = candidates
+            .iter()
+            .find_map(|t| self.methods.iter().find(|m| m.eap_type() == *t))
+        else {
+            return self.reject(request, eap.identifier, "no acceptable EAP method");
+        };
+
+        match method.start(&conversation.identity) {
+            Ok((session, data)) => {
+                conversation.method = Some((method.eap_type(), session));
+                self.challenge(request, eap, conversation, method.eap_type(), data)
+            }
+            Err(e) => self.reject(request, eap.identifier, &e.to_string()),
+        }
+    }
+
+    fn challenge(
+        &self,
+        request: &RadiusPacket,
+        eap: &EapPacket,
+        mut conversation: Conversation,
+        method_type: u8,
+        data: Vec<u8>,
+    ) -> EapReply {
+        let id = eap.identifier.wrapping_add(1);
+        let next = EapPacket::request(id, method_type, data).to_bytes();
+        conversation.last_id = id;
+        let state = self.sessions.insert(conversation);
+        let reply = match request.reply_eap_challenge(&next, &state) {
+            Ok(reply) => reply,
+            Err(e) => return self.reject(request, eap.identifier, &e.to_string()),
+        };
+        if let Some(previous) = request.state() {
+            self.challenges.insert_at(previous, (eap.identifier, reply.clone()));
+        }
+        EapReply::Challenge(reply)
+    }
+
+    fn accept(&self, request: &RadiusPacket, eap: &EapPacket, identity: String, keys: EapKeys, secret: &str) -> EapReply {
+        let mut attributes = RadiusAttribute::eap_message(&EapPacket::success(eap.identifier).to_bytes());
+
+        // RFC 3748 / RFC 5216: the first 32 bytes of the MSK are the
+        // MS-MPPE-Recv-Key, the next 32 the MS-MPPE-Send-Key.
+        if keys.msk.len() >= 64 {
+            let (recv_salt, send_salt) = mppe_salts();
+            let recv = encrypt_mppe_key(&keys.msk[..32], secret, &request.authenticator, recv_salt);
+            let send = encrypt_mppe_key(&keys.msk[32..64], secret, &request.authenticator, send_salt);
+            attributes.push(ms_attribute(MS_MPPE_RECV_KEY, &recv));
+            attributes.push(ms_attribute(MS_MPPE_SEND_KEY, &send));
+        }
+
+        EapReply::Accept {
+            reply: request.reply_accept(attributes),
+            identity,
+            keys,
+        }
+    }
+
+    fn reject(&self, request: &RadiusPacket, identifier: u8, reason: &str) -> EapReply {
+        eprintln!("🚫 EAP authentication failed: {reason}");
+        EapReply::Reject(RadiusPacket {
+            code: RadiusCode::AccessReject,
+            identifier: request.identifier,
+            length: 0,
+            authenticator: request.authenticator,
+            attributes: RadiusAttribute::eap_message(&EapPacket::failure(identifier).to_bytes()),
+        })
+    }
+
+    fn discard(&self, reason: &str) -> EapReply {
+        eprintln!("🗑️ Discarding EAP-Response: {reason}");
+        EapReply::Discard(reason.to_string())
+    }
+}
+
+/// EAP-MD5-Challenge (RFC 3748 §5.4). Only suitable inside a protected
+/// tunnel or for testing, but handy as the simplest possible method.
+pub struct Md5Challenge {
+    lookup: PasswordLookup,
+}
+
+impl Md5Challenge {
+    pub fn new<F>(lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<KnownPassword> + Send + Sync + 'static,
+    {
+        Md5Challenge { lookup: Arc::new(lookup) }
+    }
+}
+
+impl EapMethod for Md5Challenge {
+    fn eap_type(&self) -> u8 {
+        EAP_TYPE_MD5_CHALLENGE
+    }
+
+    fn start(&self, identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+        let mut challenge = [0u8; 16];
+        rand::rng().fill_bytes(&mut challenge);
+
+        let session = Md5ChallengeSession {
+            password: (self.lookup)(identity),
+            challenge,
+        };
+        let mut data = vec![challenge.len() as u8];
+        data.extend_from_slice(&challenge);
+        Ok((Box::new(session), data))
+    }
+}
+
+struct Md5ChallengeSession {
+    password: Option<KnownPassword>,
+    challenge: [u8; 16],
+}
+
+impl EapSession for Md5ChallengeSession {
+    fn process(&mut self, response: &EapPacket) -> EapStep {
+        let Some(KnownPassword::Cleartext(password)) = &self.password else {
+            return EapStep::Failure("no cleartext password for EAP-MD5".to_string());
+        };
+        if response.data.len() < 17 || response.data[0] != 16 {
+            return EapStep::Failure("malformed EAP-MD5 response".to_string());
+        }
+
+        let expected = chap_response(response.identifier, password.as_bytes(), &self.challenge);
+        if constant_time_eq(&expected, &response.data[1..17]) {
+            EapStep::Success(EapKeys::default())
+        } else {
+            EapStep::Failure("EAP-MD5 response mismatch".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Continues once with a fixed request, then succeeds.
+    struct Echo;
+
+    struct EchoSession {
+        rounds: u8,
+    }
+
+    impl EapMethod for Echo {
+        fn eap_type(&self) -> u8 {
+            99
+        }
+
+        fn start(&self, _identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+            Ok((Box::new(EchoSession { rounds: 0 }), b"ping".to_vec()))
+        }
+    }
+
+    impl EapSession for EchoSession {
+        fn process(&mut self, _response: &EapPacket) -> EapStep {
+            self.rounds += 1;
+            if self.rounds == 1 { EapStep::Continue(b"ping".to_vec()) } else { EapStep::Success(EapKeys::default()) }
+        }
+    }
+
+    fn server() -> EapServer {
+        EapServer::new()
+            .register(Md5Challenge::new(|user| (user == "bob").then(|| KnownPassword::Cleartext("hunter2".into()))))
+            .register(Echo)
+    }
+
+    fn request(identifier: u8, state: Option<&[u8]>, eap: EapPacket) -> RadiusPacket {
+        let mut attributes = RadiusAttribute::eap_message(&eap.to_bytes());
+        if let Some(state) = state {
+            attributes.push(RadiusAttribute::state(state).unwrap());
+        }
+        RadiusPacket {
+            code: RadiusCode::AccessRequest,
+            identifier,
+            length: 0,
+            authenticator: [identifier; 16],
+            attributes,
+        }
+    }
+
+    fn challenge(reply: EapReply) -> (EapPacket, Vec<u8>) {
+        let EapReply::Challenge(reply) = reply else { panic!("expected an Access-Challenge") };
+        let eap = EapPacket::from_bytes(&reply.eap_message().unwrap()).unwrap();
+        (eap, reply.state().unwrap().to_vec())
+    }
+
+    fn identity(server: &EapServer) -> (EapPacket, Vec<u8>) {
+        challenge(server.handle(&request(1, None, EapPacket::response(0, EAP_TYPE_IDENTITY, b"bob".to_vec())), "s"))
+    }
+
+    #[test]
+    fn eap_message_is_fragmented_and_reassembled() {
+        let eap = EapPacket::request(7, EAP_TYPE_TLS, vec![0xab; 600]).to_bytes();
+        let attributes = RadiusAttribute::eap_message(&eap);
+        let lengths: Vec<usize> = attributes.iter().map(|a| a.value.len()).collect();
+        assert_eq!(lengths, [253, 253, 99]);
+
+        let packet = request(1, None, EapPacket::response(0, EAP_TYPE_IDENTITY, Vec::new()));
+        let packet = RadiusPacket { attributes, ..packet };
+        assert_eq!(packet.eap_message().unwrap(), eap);
+    }
+
+    #[test]
+    fn md5_conversation_is_accepted() {
+        let server = server();
+        let (eap, state) = identity(&server);
+        assert_eq!((eap.typ, eap.data[0]), (Some(EAP_TYPE_MD5_CHALLENGE), 16));
+
+        let mut data = vec![16];
+        data.extend(chap_response(eap.identifier, b"hunter2", &eap.data[1..17]));
+        let response = EapPacket::response(eap.identifier, EAP_TYPE_MD5_CHALLENGE, data);
+        assert!(matches!(server.handle(&request(2, Some(&state), response), "s"), EapReply::Accept { .. }));
+        assert_eq!(server.pending(), 0);
+    }
+
+    #[test]
+    fn nak_switches_to_the_proposed_method() {
+        let server = server();
+        let (eap, state) = identity(&server);
+        let nak = EapPacket::response(eap.identifier, EAP_TYPE_NAK, vec![99]);
+        let (eap, _) = challenge(server.handle(&request(2, Some(&state), nak), "s"));
+        assert_eq!((eap.typ, eap.data.as_slice()), (Some(99), &b"ping"[..]));
+    }
+
+    #[test]
+    fn nak_without_acceptable_method_is_rejected() {
+        let server = server();
+        let (eap, state) = identity(&server);
+        let nak = EapPacket::response(eap.identifier, EAP_TYPE_NAK, vec![EAP_TYPE_TLS]);
+        assert!(matches!(server.handle(&request(2, Some(&state), nak), "s"), EapReply::Reject(_)));
+    }
+
+    #[test]
+    fn retransmitted_response_gets_the_same_challenge() {
+        let server = server();
+        let (eap, state) = identity(&server);
+        let nak = EapPacket::response(eap.identifier, EAP_TYPE_NAK, vec![99]);
+        let first = challenge(server.handle(&request(2, Some(&state), nak.clone()), "s"));
+        let again = challenge(server.handle(&request(3, Some(&state), nak), "s"));
+        assert_eq!(first, again);
+        assert_eq!(server.pending(), 1);
+    }
+
+    #[test]
+    fn mismatched_identifier_is_discarded_without_ending_the_conversation() {
+        let server = server();
+        let (eap, state) = identity(&server);
+        let stale = EapPacket::response(eap.identifier.wrapping_sub(1), EAP_TYPE_NAK, vec![99]);
+        let reply = server.handle(&request(2, Some(&state), stale), "s");
+        assert!(matches!(reply.into_packet(), Err(RadiusError::Discarded(_))));
+        assert_eq!(server.pending(), 1);
+
+        let nak = EapPacket::response(eap.identifier, EAP_TYPE_NAK, vec![99]);
+        challenge(server.handle(&request(3, Some(&state), nak), "s"));
+    }
+
+    #[test]
+    fn expired_state_is_rejected() {
+        let server = EapServer::with_timeout(Duration::ZERO).register(Echo);
+        let (eap, state) = identity(&server);
+        let response = EapPacket::response(eap.identifier, 99, Vec::new());
+        let EapReply::Reject(reply) = server.handle(&request(9, Some(&state), response), "s") else {
+            panic!("expected an Access-Reject");
+        };
+        assert_eq!(reply.authenticator, [9; 16]);
+        assert_eq!(EapPacket::from_bytes(&reply.eap_message().unwrap()).unwrap().code, EAP_FAILURE);
+    }
+
+    #[test]
+    fn session_store_expires_entries() {
+        let store = EapSessionStore::new(Duration::from_secs(60));
+        let state = store.insert("conversation");
+        assert_eq!(store.peek(&state, |v| *v), Some("conversation"));
+        assert_eq!(store.take(&state), Some("conversation"));
+        assert_eq!(store.take(&state), None);
+
+        let expired = EapSessionStore::new(Duration::ZERO);
+        let state = expired.insert("conversation");
+        assert_eq!(expired.peek(&state, |v| *v), None);
+        assert_eq!(expired.take(&state), None);
+    }
+}
@@ -0,0 +1,312 @@
+//! EAP-AKA (RFC 4187) and EAP-AKA' (RFC 5448): full authentication with
+//! UMTS quintuplets from an [`AuthVectorSource`], including sequence number
+//! resynchronisation. Pseudonyms and fast re-authentication are not supported.
+
+use super::milenage::{UmtsResult, Usim, UsimError};
+use super::simaka::{
+    aka_master_key, aka_prime_ck_ik, aka_prime_keys, derive_keys, length_prefixed, read_length_prefixed, reserved,
+    verify_mac, MacAlgorithm, SimAkaKeys, SimAkaMessage, AT_ANY_ID_REQ, AT_AUTN, AT_AUTS, AT_CLIENT_ERROR_CODE,
+    AT_FULLAUTH_ID_REQ, AT_IDENTITY, AT_KDF, AT_KDF_INPUT, AT_PERMANENT_ID_REQ, AT_RAND, AT_RES,
+};
+use super::vectors::{imsi_from_identity, AuthVectorSource, Quintuplet};
+use super::{
+    EapKeys, EapMethod, EapPacket, EapSession, EapStep, EAP_REQUEST, EAP_RESPONSE, EAP_TYPE_AKA, EAP_TYPE_AKA_PRIME,
+    EAP_TYPE_IDENTITY, EAP_TYPE_NAK,
+};
+use crate::error::RadiusError;
+use std::sync::Arc;
+
+pub const SUBTYPE_CHALLENGE: u8 = 1;
+pub const SUBTYPE_AUTHENTICATION_REJECT: u8 = 2;
+pub const SUBTYPE_SYNCHRONIZATION_FAILURE: u8 = 4;
+pub const SUBTYPE_IDENTITY: u8 = 5;
+pub const SUBTYPE_NOTIFICATION: u8 = 12;
+pub const SUBTYPE_REAUTHENTICATION: u8 = 13;
+pub const SUBTYPE_CLIENT_ERROR: u8 = 14;
+
+/// The EAP-AKA' key derivation function defined in RFC 5448.
+pub const KDF_AKA_PRIME: u16 = 1;
+
+/// Resynchronisations allowed per conversation before giving up.
+const MAX_RESYNC: usize = 1;
+
+pub struct EapAka {
+    source: Arc<dyn AuthVectorSource>,
+    network_name: Option<String>,
+}
+
+impl EapAka {
+    /// EAP-AKA.
+    pub fn new(source: Arc<dyn AuthVectorSource>) -> Self {
+        EapAka {
+            source,
+            network_name: None,
+        }
+    }
+
+    /// EAP-AKA', binding keys to `network_name` (e.g. "WLAN").
+    pub fn prime(source: Arc<dyn AuthVectorSource>, network_name: &str) -> Self {
+        EapAka {
+            source,
+            network_name: Some(network_name.to_string()),
+        }
+    }
+}
+
+impl EapMethod for EapAka {
+    fn eap_type(&self) -> u8 {
+        if self.network_name.is_some() { EAP_TYPE_AKA_PRIME } else { EAP_TYPE_AKA }
+    }
+
+    fn start(&self, identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+        let session = AkaSession {
+            source: self.source.clone(),
+            network_name: self.network_name.clone(),
+            identity: identity.to_string(),
+            resyncs: 0,
+            stage: Stage::Identity,
+        };
+        // The Challenge's AT_MAC covers the EAP identifier, which is only
+        // known once a response arrives, so always ask for the identity here.
+        let request = SimAkaMessage::new(SUBTYPE_IDENTITY).with(AT_PERMANENT_ID_REQ, &reserved(&[]));
+        Ok((Box::new(session), request.to_bytes()))
+    }
+}
+
+enum Stage {
+    Identity,
+    Challenge { quintuplet: Quintuplet, keys: SimAkaKeys },
+    Done,
+}
+
+struct AkaSession {
+    source: Arc<dyn AuthVectorSource>,
+    network_name: Option<String>,
+    identity: String,
+    resyncs: usize,
+    stage: Stage,
+}
+
+impl EapSession for AkaSession {
+    fn process(&mut self, response: &EapPacket) -> EapStep {
+        let message = match SimAkaMessage::from_bytes(&response.data) {
+            Ok(message) => message,
+            Err(e) => return EapStep::Failure(e.to_string()),
+        };
+
+        match (std::mem::replace(&mut self.stage, Stage::Done), message.subtype) {
+            (Stage::Identity, SUBTYPE_IDENTITY) => {
+                if let Some(identity) = message.get(AT_IDENTITY).and_then(read_length_prefixed) {
+                    self.identity = String::from_utf8_lossy(identity).to_string();
+                }
+                self.challenge(response.identifier)
+            }
+            (Stage::Challenge { quintuplet, keys }, SUBTYPE_CHALLENGE) => {
+                if !verify_mac(response, self.mac(), &keys.k_aut, &[]) {
+                    return EapStep::Failure(format!("EAP-AKA MAC mismatch for {}", self.identity));
+                }
+                let res = message.get(AT_RES).and_then(|v| {
+                    let bits = u16::from_be_bytes([v[0], v[1]]) as usize;
+                    v.get(2..2 + bits / 8)
+                });
+                if res != Some(quintuplet.xres.as_slice()) {
+                    return EapStep::Failure(format!("EAP-AKA RES mismatch for {}", self.identity));
+                }
+                EapStep::Success(EapKeys {
+                    msk: keys.msk,
+                    emsk: keys.emsk,
+                    session_id: [&[self.eap_type()][..], &quintuplet.rand, &quintuplet.autn].concat(),
+                    identity: Some(self.identity.clone()),
+                })
+            }
+            (Stage::Challenge { quintuplet, .. }, SUBTYPE_SYNCHRONIZATION_FAILURE) => {
+                let Some(auts) = message.get(AT_AUTS).and_then(|v| v.get(..14)) else {
+                    return EapStep::Failure("Synchronization-Failure without AT_AUTS".to_string());
+                };
+                if self.resyncs >= MAX_RESYNC {
+                    return EapStep::Failure(format!("repeated EAP-AKA resynchronisation for {}", self.identity));
+                }
+                self.resyncs += 1;
+                let imsi = imsi_from_identity(&self.identity);
+                match self.source.resynchronise(imsi, &quintuplet.rand, auts.try_into().unwrap()) {
+                    Ok(()) => self.challenge(response.identifier),
+                    Err(e) => EapStep::Failure(e.to_string()),
+                }
+            }
+            (_, SUBTYPE_AUTHENTICATION_REJECT) => {
+                EapStep::Failure(format!("{} rejected the network authentication", self.identity))
+            }
+            (_, SUBTYPE_CLIENT_ERROR) => {
+                let code = message
+                    .get(AT_CLIENT_ERROR_CODE)
+                    .map(|v| u16::from_be_bytes([v[0], v[1]]))
+                    .unwrap_or(0);
+                EapStep::Failure(format!("peer reported client error {}", code))
+            }
+            (_, subtype) => EapStep::Failure(format!("unexpected EAP-AKA subtype {}", subtype)),
+        }
+    }
+}
+
+impl AkaSession {
+    fn eap_type(&self) -> u8 {
+        if self.network_name.is_some() { EAP_TYPE_AKA_PRIME } else { EAP_TYPE_AKA }
+    }
+
+    fn mac(&self) -> MacAlgorithm {
+        if self.network_name.is_some() { MacAlgorithm::HmacSha256 } else { MacAlgorithm::HmacSha1 }
+    }
+
+    fn challenge(&mut self, identifier: u8) -> EapStep {
+        let quintuplet = match self.source.quintuplet(imsi_from_identity(&self.identity)) {
+            Ok(q) => q,
+            Err(e) => return EapStep::Failure(e.to_string()),
+        };
+
+        let mut request = SimAkaMessage::new(SUBTYPE_CHALLENGE)
+            .with(AT_RAND, &reserved(&quintuplet.rand))
+            .with(AT_AUTN, &reserved(&quintuplet.autn));
+
+        let keys = match &self.network_name {
+            Some(name) => {
+                let (ck, ik) = aka_prime_ck_ik(&quintuplet.ck, &quintuplet.ik, name.as_bytes(), &quintuplet.autn[..6]);
+                request = request
+                    .with(AT_KDF_INPUT, &length_prefixed(name.len() as u16, name.as_bytes()))
+                    .with(AT_KDF, &KDF_AKA_PRIME.to_be_bytes());
+                aka_prime_keys(&ck, &ik, self.identity.as_bytes())
+            }
+            None => derive_keys(&aka_master_key(self.identity.as_bytes(), &quintuplet.ik, &quintuplet.ck)),
+        };
+
+        // EapServer numbers the next request after this response.
+        let data = request.sign(
+            EAP_REQUEST,
+            identifier.wrapping_add(1),
+            self.eap_type(),
+            self.mac(),
+            &keys.k_aut,
+            &[],
+        );
+        self.stage = Stage::Challenge { quintuplet, keys };
+        EapStep::Continue(data)
+    }
+}
+
+/// The peer side of EAP-AKA or EAP-AKA', backed by a simulated USIM.
+pub struct AkaPeer {
+    identity: String,
+    usim: Usim,
+    eap_type: u8,
+    keys: Option<SimAkaKeys>,
+}
+
+impl AkaPeer {
+    /// An EAP-AKA peer; `identity` is usually `0<IMSI>@realm`.
+    pub fn new(identity: &str, usim: Usim) -> Self {
+        AkaPeer {
+            identity: identity.to_string(),
+            usim,
+            eap_type: EAP_TYPE_AKA,
+            keys: None,
+        }
+    }
+
+    /// An EAP-AKA' peer; `identity` is usually `6<IMSI>@realm`.
+    pub fn prime(identity: &str, usim: Usim) -> Self {
+        AkaPeer {
+            eap_type: EAP_TYPE_AKA_PRIME,
+            ..Self::new(identity, usim)
+        }
+    }
+
+    pub fn usim(&self) -> &Usim {
+        &self.usim
+    }
+
+    /// Keys derived by the peer once the challenge was accepted.
+    pub fn keys(&self) -> Option<&SimAkaKeys> {
+        self.keys.as_ref()
+    }
+
+    /// Answers an EAP-Request, including the initial Identity request.
+    pub fn respond(&mut self, request: &EapPacket) -> Result<EapPacket, RadiusError> {
+        let id = request.identifier;
+        match request.typ {
+            Some(EAP_TYPE_IDENTITY) => {
+                return Ok(EapPacket::response(id, EAP_TYPE_IDENTITY, self.identity.as_bytes().to_vec()));
+            }
+            Some(typ) if typ == self.eap_type => {}
+            _ => return Ok(EapPacket::response(id, EAP_TYPE_NAK, vec![self.eap_type])),
+        }
+
+        let message = SimAkaMessage::from_bytes(&request.data)?;
+        let data = match message.subtype {
+            SUBTYPE_IDENTITY => {
+                let mut reply = SimAkaMessage::new(SUBTYPE_IDENTITY);
+                if [AT_PERMANENT_ID_REQ, AT_FULLAUTH_ID_REQ, AT_ANY_ID_REQ]
+                    .iter()
+                    .any(|t| message.get(*t).is_some())
+                {
+                    let identity = self.identity.as_bytes();
+                    reply = reply.with(AT_IDENTITY, &length_prefixed(identity.len() as u16, identity));
+                }
+                reply.to_bytes()
+            }
+            SUBTYPE_CHALLENGE => self.challenge(request, &message)?,
+            SUBTYPE_NOTIFICATION => SimAkaMessage::new(SUBTYPE_NOTIFICATION).to_bytes(),
+            subtype => return Err(RadiusError::InvalidEap(format!("unsupported EAP-AKA subtype {}", subtype))),
+        };
+        Ok(EapPacket::response(id, self.eap_type, data))
+    }
+
+    fn challenge(&mut self, request: &EapPacket, message: &SimAkaMessage) -> Result<Vec<u8>, RadiusError> {
+        let value = |typ, name| {
+            message
+                .get(typ)
+                .and_then(|v| v.get(2..18))
+                .map(|v| <[u8; 16]>::try_from(v).unwrap())
+                .ok_or_else(|| RadiusError::InvalidEap(format!("EAP-AKA Challenge without {}", name)))
+        };
+        let (rand, autn) = (value(AT_RAND, "AT_RAND")?, value(AT_AUTN, "AT_AUTN")?);
+        let client_error = || SimAkaMessage::new(SUBTYPE_CLIENT_ERROR).with(AT_CLIENT_ERROR_CODE, &[0, 0]).to_bytes();
+
+        let prime = self.eap_type == EAP_TYPE_AKA_PRIME;
+        let network_name = message.get(AT_KDF_INPUT).and_then(read_length_prefixed).unwrap_or_default();
+        if prime {
+            let kdf = message.get(AT_KDF).map(|v| u16::from_be_bytes([v[0], v[1]]));
+            if kdf != Some(KDF_AKA_PRIME) || network_name.is_empty() {
+                return Ok(client_error());
+            }
+            // RFC 5448 §3.1: AKA' vectors must have the AMF separation bit set.
+            if autn[6] & 0x80 == 0 {
+                return Ok(SimAkaMessage::new(SUBTYPE_AUTHENTICATION_REJECT).to_bytes());
+            }
+        }
+
+        let UmtsResult { res, ck, ik } = match self.usim.authenticate(&rand, &autn) {
+            Ok(result) => result,
+            Err(UsimError::MacFailure) => {
+                return Ok(SimAkaMessage::new(SUBTYPE_AUTHENTICATION_REJECT).to_bytes());
+            }
+            Err(UsimError::SyncFailure(auts)) => {
+                return Ok(SimAkaMessage::new(SUBTYPE_SYNCHRONIZATION_FAILURE).with(AT_AUTS, &auts).to_bytes());
+            }
+        };
+
+        let (keys, mac) = if prime {
+            let (ck, ik) = aka_prime_ck_ik(&ck, &ik, network_name, &autn[..6]);
+            (aka_prime_keys(&ck, &ik, self.identity.as_bytes()), MacAlgorithm::HmacSha256)
+        } else {
+            (derive_keys(&aka_master_key(self.identity.as_bytes(), &ik, &ck)), MacAlgorithm::HmacSha1)
+        };
+        if !verify_mac(request, mac, &keys.k_aut, &[]) {
+            return Ok(client_error());
+        }
+
+        let data = SimAkaMessage::new(SUBTYPE_CHALLENGE)
+            .with(AT_RES, &length_prefixed(res.len() as u16 * 8, &res))
+            .sign(EAP_RESPONSE, request.identifier, self.eap_type, mac, &keys.k_aut, &[]);
+        self.keys = Some(keys);
+        Ok(data)
+    }
+}
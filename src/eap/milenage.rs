@@ -0,0 +1,359 @@
+//! The 3GPP Milenage algorithm set (TS 35.206) and GSM-Milenage
+//! (TS 55.205, "COMP128-4"), used to generate authentication vectors for
+//! EAP-SIM and EAP-AKA and to play the SIM card in offline tests.
+//!
+//! COMP128-1/2/3 are not implemented; vectors for such SIMs can still be
+//! supplied as precomputed triplets (see [`vectors`](super::vectors)).
+
+use super::vectors::{Quintuplet, Triplet};
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+
+/// AMF with the separation bit set, as EAP-AKA' requires (RFC 5448 §3.1).
+pub const DEFAULT_AMF: [u8; 2] = [0x80, 0x00];
+
+/// Subscriber keys of one SIM/USIM: K and the operator variant OPc.
+#[derive(Clone)]
+pub struct Milenage {
+    aes: Aes128,
+    opc: [u8; 16],
+}
+
+impl std::fmt::Debug for Milenage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Milenage { .. }")
+    }
+}
+
+impl Milenage {
+    pub fn new(k: [u8; 16], opc: [u8; 16]) -> Self {
+        Milenage { aes: Aes128::new(&k.into()), opc }
+    }
+
+    /// Derives OPc from the operator's OP.
+    pub fn with_op(k: [u8; 16], op: [u8; 16]) -> Self {
+        let aes = Aes128::new(&k.into());
+        let opc = xor(&encrypt(&aes, &op), &op);
+        Milenage { aes, opc }
+    }
+
+    pub fn opc(&self) -> [u8; 16] {
+        self.opc
+    }
+
+    /// Network authentication code MAC-A.
+    pub fn f1(&self, rand: &[u8; 16], sqn: &[u8; 6], amf: &[u8; 2]) -> [u8; 8] {
+        self.out1(rand, sqn, amf)[..8].try_into().unwrap()
+    }
+
+    /// Resynchronisation authentication code MAC-S.
+    pub fn f1_star(&self, rand: &[u8; 16], sqn: &[u8; 6], amf: &[u8; 2]) -> [u8; 8] {
+        self.out1(rand, sqn, amf)[8..].try_into().unwrap()
+    }
+
+    /// Response RES.
+    pub fn f2(&self, rand: &[u8; 16]) -> [u8; 8] {
+        self.out(rand, 0, 1)[8..].try_into().unwrap()
+    }
+
+    /// Cipher key CK.
+    pub fn f3(&self, rand: &[u8; 16]) -> [u8; 16] {
+        self.out(rand, 4, 2)
+    }
+
+    /// Integrity key IK.
+    pub fn f4(&self, rand: &[u8; 16]) -> [u8; 16] {
+        self.out(rand, 8, 4)
+    }
+
+    /// Anonymity key AK.
+    pub fn f5(&self, rand: &[u8; 16]) -> [u8; 6] {
+        self.out(rand, 0, 1)[..6].try_into().unwrap()
+    }
+
+    /// Resynchronisation anonymity key AK*.
+    pub fn f5_star(&self, rand: &[u8; 16]) -> [u8; 6] {
+        self.out(rand, 12, 8)[..6].try_into().unwrap()
+    }
+
+    /// A UMTS authentication vector for sequence number `sqn` (48 bits).
+    pub fn quintuplet(&self, rand: [u8; 16], sqn: u64, amf: [u8; 2]) -> Quintuplet {
+        let sqn = sqn_bytes(sqn);
+        let mut autn = [0u8; 16];
+        autn[..6].copy_from_slice(&xor(&sqn, &self.f5(&rand)));
+        autn[6..8].copy_from_slice(&amf);
+        autn[8..].copy_from_slice(&self.f1(&rand, &sqn, &amf));
+
+        Quintuplet {
+            rand,
+            autn,
+            xres: self.f2(&rand).to_vec(),
+            ck: self.f3(&rand),
+            ik: self.f4(&rand),
+        }
+    }
+
+    /// A GSM triplet using the GSM-Milenage conversion functions c2/c3.
+    pub fn triplet(&self, rand: [u8; 16]) -> Triplet {
+        let res = self.f2(&rand);
+        let (ck, ik) = (self.f3(&rand), self.f4(&rand));
+
+        let mut sres = [0u8; 4];
+        for i in 0..4 {
+            sres[i] = res[i] ^ res[i + 4];
+        }
+        let mut kc = [0u8; 8];
+        for i in 0..8 {
+            kc[i] = ck[i] ^ ck[i + 8] ^ ik[i] ^ ik[i + 8];
+        }
+        Triplet { rand, sres, kc }
+    }
+
+    /// Recovers the card's sequence number from an AUTS sent with an
+    /// EAP-AKA Synchronization-Failure, if its MAC-S checks out.
+    pub fn resynchronise(&self, rand: &[u8; 16], auts: &[u8; 14]) -> Option<u64> {
+        let sqn: [u8; 6] = xor(&auts[..6].try_into().unwrap(), &self.f5_star(rand));
+        (self.f1_star(rand, &sqn, &[0, 0]) == auts[6..]).then(|| sqn_value(&sqn))
+    }
+
+    fn temp(&self, rand: &[u8; 16]) -> [u8; 16] {
+        encrypt(&self.aes, &xor(rand, &self.opc))
+    }
+
+    fn out1(&self, rand: &[u8; 16], sqn: &[u8; 6], amf: &[u8; 2]) -> [u8; 16] {
+        let mut in1 = [0u8; 16];
+        in1[..6].copy_from_slice(sqn);
+        in1[6..8].copy_from_slice(amf);
+        in1[8..14].copy_from_slice(sqn);
+        in1[14..].copy_from_slice(amf);
+
+        // r1 = 64 bits, c1 = 0
+        let rotated = rotate(&xor(&in1, &self.opc), 8);
+        xor(&encrypt(&self.aes, &xor(&self.temp(rand), &rotated)), &self.opc)
+    }
+
+    /// OUT2..OUT5: rotation by `r` bytes and constant c with value `c`.
+    fn out(&self, rand: &[u8; 16], r: usize, c: u8) -> [u8; 16] {
+        let mut block = rotate(&xor(&self.temp(rand), &self.opc), r);
+        block[15] ^= c;
+        xor(&encrypt(&self.aes, &block), &self.opc)
+    }
+}
+
+/// What the card makes of an AUTN it could not accept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsimError {
+    /// MAC-A did not verify: the network is not genuine.
+    MacFailure,
+    /// The sequence number was stale; carries the AUTS for resynchronisation.
+    SyncFailure([u8; 14]),
+}
+
+/// RES, CK and IK computed by the card for an accepted challenge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UmtsResult {
+    pub res: [u8; 8],
+    pub ck: [u8; 16],
+    pub ik: [u8; 16],
+}
+
+/// The card side of UMTS and GSM authentication, for simulated peers.
+#[derive(Debug, Clone)]
+pub struct Usim {
+    milenage: Milenage,
+    sqn: u64,
+}
+
+impl Usim {
+    /// A card that has accepted sequence numbers up to `sqn`.
+    pub fn new(milenage: Milenage, sqn: u64) -> Self {
+        Usim { milenage, sqn }
+    }
+
+    pub fn sqn(&self) -> u64 {
+        self.sqn
+    }
+
+    /// Verifies AUTN and runs the UMTS algorithms.
+    pub fn authenticate(&mut self, rand: &[u8; 16], autn: &[u8; 16]) -> Result<UmtsResult, UsimError> {
+        let m = &self.milenage;
+        let sqn: [u8; 6] = xor(&autn[..6].try_into().unwrap(), &m.f5(rand));
+        let amf: [u8; 2] = autn[6..8].try_into().unwrap();
+        if m.f1(rand, &sqn, &amf) != autn[8..] {
+            return Err(UsimError::MacFailure);
+        }
+
+        let received = sqn_value(&sqn);
+        if received <= self.sqn {
+            let ours = sqn_bytes(self.sqn);
+            let mut auts = [0u8; 14];
+            auts[..6].copy_from_slice(&xor(&ours, &m.f5_star(rand)));
+            auts[6..].copy_from_slice(&m.f1_star(rand, &ours, &[0, 0]));
+            return Err(UsimError::SyncFailure(auts));
+        }
+
+        self.sqn = received;
+        Ok(UmtsResult {
+            res: m.f2(rand),
+            ck: m.f3(rand),
+            ik: m.f4(rand),
+        })
+    }
+
+    /// GSM authentication: (SRES, Kc).
+    pub fn gsm(&self, rand: &[u8; 16]) -> ([u8; 4], [u8; 8]) {
+        let triplet = self.milenage.triplet(*rand);
+        (triplet.sres, triplet.kc)
+    }
+}
+
+fn sqn_bytes(sqn: u64) -> [u8; 6] {
+    sqn.to_be_bytes()[2..].try_into().unwrap()
+}
+
+fn sqn_value(sqn: &[u8; 6]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[2..].copy_from_slice(sqn);
+    u64::from_be_bytes(buf)
+}
+
+fn encrypt(aes: &Aes128, block: &[u8; 16]) -> [u8; 16] {
+    let mut block = (*block).into();
+    aes.encrypt_block(&mut block);
+    block.into()
+}
+
+fn xor<const N: usize>(a: &[u8; N], b: &[u8; N]) -> [u8; N] {
+    let mut out = *a;
+    for (o, b) in out.iter_mut().zip(b) {
+        *o ^= b;
+    }
+    out
+}
+
+/// Cyclic left rotation by `bytes` bytes.
+fn rotate(block: &[u8; 16], bytes: usize) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = block[(i + bytes) % 16];
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex::decode_array;
+
+    /// One test set from 3GPP TS 35.208 §4.3, with the GSM-Milenage SRES
+    /// and Kc from TS 55.205 §4.3 for the same inputs.
+    struct TestSet {
+        k: &'static str,
+        rand: &'static str,
+        sqn: &'static str,
+        amf: &'static str,
+        op: &'static str,
+        opc: &'static str,
+        f1: &'static str,
+        f1_star: &'static str,
+        f2: &'static str,
+        f3: &'static str,
+        f4: &'static str,
+        f5: &'static str,
+        f5_star: &'static str,
+        sres: &'static str,
+        kc: &'static str,
+    }
+
+    const TEST_SETS: [TestSet; 2] = [
+        TestSet {
+            k: "465b5ce8b199b49faa5f0a2ee238a6bc",
+            rand: "23553cbe9637a89d218ae64dae47bf35",
+            sqn: "ff9bb4d0b607",
+            amf: "b9b9",
+            op: "cdc202d5123e20f62b6d676ac72cb318",
+            opc: "cd63cb71954a9f4e48a5994e37a02baf",
+            f1: "4a9ffac354dfafb3",
+            f1_star: "01cfaf9ec4e871e9",
+            f2: "a54211d5e3ba50bf",
+            f3: "b40ba9a3c58b2a05bbf0d987b21bf8cb",
+            f4: "f769bcd751044604127672711c6d3441",
+            f5: "aa689c648370",
+            f5_star: "451e8beca43b",
+            sres: "46f8416a",
+            kc: "eae4be823af9a08b",
+        },
+        TestSet {
+            k: "0396eb317b6d1c36f19c1c84cd6ffd16",
+            rand: "c00d603103dcee52c4478119494202e8",
+            sqn: "fd8eef40df7d",
+            amf: "af17",
+            op: "ff53bade17df5d4e793073ce9d7579fa",
+            opc: "53c15671c60a4b731c55b4a441c0bde2",
+            f1: "5df5b31807e258b0",
+            f1_star: "a8c016e51ef4a343",
+            f2: "d3a628ed988620f0",
+            f3: "58c433ff7a7082acd424220f2b67c556",
+            f4: "21a8c1f929702adb3e738488b9f5c5da",
+            f5: "c47783995f72",
+            f5_star: "30f1197061c1",
+            sres: "4b20081d",
+            kc: "933b5481c192a8fb",
+        },
+    ];
+
+    fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        decode_array(hex).unwrap()
+    }
+
+    #[test]
+    fn ts_35_208_test_sets() {
+        for set in &TEST_SETS {
+            let m = Milenage::with_op(bytes(set.k), bytes(set.op));
+            assert_eq!(m.opc(), bytes(set.opc));
+
+            let (rand, sqn, amf) = (bytes(set.rand), bytes(set.sqn), bytes(set.amf));
+            assert_eq!(m.f1(&rand, &sqn, &amf), bytes(set.f1));
+            assert_eq!(m.f1_star(&rand, &sqn, &amf), bytes(set.f1_star));
+            assert_eq!(m.f2(&rand), bytes(set.f2));
+            assert_eq!(m.f3(&rand), bytes(set.f3));
+            assert_eq!(m.f4(&rand), bytes(set.f4));
+            assert_eq!(m.f5(&rand), bytes(set.f5));
+            assert_eq!(m.f5_star(&rand), bytes(set.f5_star));
+        }
+    }
+
+    #[test]
+    fn ts_55_205_gsm_milenage() {
+        for set in &TEST_SETS {
+            let m = Milenage::new(bytes(set.k), bytes(set.opc));
+            let triplet = m.triplet(bytes(set.rand));
+            assert_eq!(triplet.sres, bytes(set.sres));
+            assert_eq!(triplet.kc, bytes(set.kc));
+        }
+    }
+
+    #[test]
+    fn usim_accepts_fresh_vector_and_resynchronises_stale_one() {
+        let set = &TEST_SETS[0];
+        let m = Milenage::new(bytes(set.k), bytes(set.opc));
+        let rand = bytes(set.rand);
+        let mut usim = Usim::new(m.clone(), 41);
+
+        let fresh = m.quintuplet(rand, 42, DEFAULT_AMF);
+        let result = usim.authenticate(&rand, &fresh.autn).unwrap();
+        assert_eq!(result.res.to_vec(), fresh.xres);
+        assert_eq!((result.ck, result.ik), (fresh.ck, fresh.ik));
+        assert_eq!(usim.sqn(), 42);
+
+        let stale = m.quintuplet(rand, 7, DEFAULT_AMF);
+        let Err(UsimError::SyncFailure(auts)) = usim.authenticate(&rand, &stale.autn) else {
+            panic!("stale SQN was accepted");
+        };
+        assert_eq!(m.resynchronise(&rand, &auts), Some(42));
+
+        let mut forged = fresh.autn;
+        forged[15] ^= 1;
+        assert_eq!(usim.authenticate(&rand, &forged), Err(UsimError::MacFailure));
+    }
+}
@@ -0,0 +1,138 @@
+//! EAP-MSCHAPv2 (draft-kamath-pppext-eap-mschapv2), mainly used as the
+//! inner method of PEAP.
+
+use super::{EapKeys, EapMethod, EapPacket, EapSession, EapStep, PasswordLookup, EAP_TYPE_MSCHAPV2};
+use crate::error::RadiusError;
+use crate::mschap::{mppe_keys, KnownPassword, MsChapV2};
+use rand::RngCore;
+use std::sync::Arc;
+
+pub const OP_CHALLENGE: u8 = 1;
+pub const OP_RESPONSE: u8 = 2;
+pub const OP_SUCCESS: u8 = 3;
+pub const OP_FAILURE: u8 = 4;
+
+/// Builds an EAP-MSCHAPv2 type-data body: OpCode, MS-CHAPv2-ID, MS-Length
+/// and the opcode-specific payload.
+pub fn encode(opcode: u8, ms_id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut data = vec![opcode, ms_id];
+    data.extend_from_slice(&((payload.len() + 4) as u16).to_be_bytes());
+    data.extend_from_slice(payload);
+    data
+}
+
+pub struct EapMschapV2 {
+    lookup: PasswordLookup,
+    server_name: String,
+}
+
+impl EapMschapV2 {
+    pub fn new<F>(lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<KnownPassword> + Send + Sync + 'static,
+    {
+        Self::with_lookup(Arc::new(lookup))
+    }
+
+    pub fn with_lookup(lookup: PasswordLookup) -> Self {
+        EapMschapV2 {
+            lookup,
+            server_name: "radius-server".to_string(),
+        }
+    }
+
+    /// The Name sent in the challenge, identifying this authenticator.
+    pub fn server_name(mut self, name: &str) -> Self {
+        self.server_name = name.to_string();
+        self
+    }
+}
+
+impl EapMethod for EapMschapV2 {
+    fn eap_type(&self) -> u8 {
+        EAP_TYPE_MSCHAPV2
+    }
+
+    fn start(&self, identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+        let mut challenge = [0u8; 16];
+        rand::rng().fill_bytes(&mut challenge);
+        let ms_id = challenge[0];
+
+        let mut payload = vec![16];
+        payload.extend_from_slice(&challenge);
+        payload.extend_from_slice(self.server_name.as_bytes());
+
+        let session = MschapV2Session {
+            known: (self.lookup)(identity),
+            challenge,
+            ms_id,
+            stage: Stage::Challenged,
+        };
+        Ok((Box::new(session), encode(OP_CHALLENGE, ms_id, &payload)))
+    }
+}
+
+enum Stage {
+    Challenged,
+    SucceededPendingAck(EapKeys),
+    FailedPendingAck,
+}
+
+struct MschapV2Session {
+    known: Option<KnownPassword>,
+    challenge: [u8; 16],
+    ms_id: u8,
+    stage: Stage,
+}
+
+impl EapSession for MschapV2Session {
+    fn process(&mut self, response: &EapPacket) -> EapStep {
+        let data = &response.data;
+        let opcode = data.first().copied().unwrap_or(0);
+
+        match (std::mem::replace(&mut self.stage, Stage::FailedPendingAck), opcode) {
+            (Stage::Challenged, OP_RESPONSE) => self.check_response(data),
+            (Stage::SucceededPendingAck(keys), OP_SUCCESS) => EapStep::Success(keys),
+            (Stage::FailedPendingAck, OP_FAILURE) => {
+                EapStep::Failure("MS-CHAPv2 authentication failed".to_string())
+            }
+            (_, opcode) => EapStep::Failure(format!("unexpected EAP-MSCHAPv2 opcode {}", opcode)),
+        }
+    }
+}
+
+impl MschapV2Session {
+    fn check_response(&mut self, data: &[u8]) -> EapStep {
+        // OpCode, MS-CHAPv2-ID, MS-Length(2), Value-Size(1) = 49,
+        // Peer-Challenge(16), Reserved(8), NT-Response(24), Flags(1), Name
+        if data.len() < 54 || data[4] != 49 || data[1] != self.ms_id {
+            return EapStep::Failure("malformed EAP-MSCHAPv2 response".to_string());
+        }
+        let name = String::from_utf8_lossy(&data[54..]).to_string();
+        let mschap = MsChapV2 {
+            ident: self.ms_id,
+            auth_challenge: self.challenge,
+            peer_challenge: data[5..21].try_into().unwrap(),
+            nt_response: data[29..53].try_into().unwrap(),
+        };
+
+        let Some(known) = self.known.as_ref().filter(|k| mschap.verify(&name, k)) else {
+            let hex: String = self.challenge.iter().map(|b| format!("{:02X}", b)).collect();
+            let message = format!("E=691 R=0 C={} V=3 M=Authentication failed", hex);
+            self.stage = Stage::FailedPendingAck;
+            return EapStep::Continue(encode(OP_FAILURE, self.ms_id, message.as_bytes()));
+        };
+
+        // Same layout as hostapd: the server's send key followed by its
+        // receive key.
+        let (send, recv) = mppe_keys(&known.nt_hash(), &mschap.nt_response);
+        let keys = EapKeys {
+            msk: [send, recv].concat(),
+            ..EapKeys::default()
+        };
+
+        let message = format!("{} M=OK", mschap.authenticator_response(&name, known));
+        self.stage = Stage::SucceededPendingAck(keys);
+        EapStep::Continue(encode(OP_SUCCESS, self.ms_id, message.as_bytes()))
+    }
+}
@@ -0,0 +1,202 @@
+//! PEAPv0 (draft-kamath-pppext-peapv0) with a pluggable inner EAP method,
+//! EAP-MSCHAPv2 by default.
+
+use super::mschapv2::EapMschapV2;
+use super::tls::{TlsEvent, TlsTunnel, DEFAULT_FRAGMENT_SIZE, FLAG_START};
+use super::{
+    EapMethod, EapPacket, EapSession, EapStep, PasswordLookup, EAP_REQUEST, EAP_RESPONSE, EAP_TYPE_IDENTITY,
+    EAP_TYPE_PEAP,
+};
+use crate::error::RadiusError;
+use rustls::ServerConfig;
+use std::sync::Arc;
+
+/// EAP type of the PEAP Extensions method carrying the Result TLV.
+pub const EAP_TYPE_TLV: u8 = 33;
+
+const RESULT_SUCCESS: u16 = 1;
+const RESULT_FAILURE: u16 = 2;
+
+/// A full EAP-TLV packet with a mandatory Result TLV. Unlike other inner
+/// packets in PEAPv0, it keeps its EAP header.
+pub fn result_tlv(code: u8, identifier: u8, success: bool) -> Vec<u8> {
+    let status = if success { RESULT_SUCCESS } else { RESULT_FAILURE };
+    let mut data = vec![0x80, 0x03, 0x00, 0x02];
+    data.extend_from_slice(&status.to_be_bytes());
+    EapPacket { code, identifier, typ: Some(EAP_TYPE_TLV), data }.to_bytes()
+}
+
+/// Reads the status of a Result TLV, if `packet` is an EAP-TLV packet.
+pub fn parse_result_tlv(packet: &[u8]) -> Option<bool> {
+    let eap = EapPacket::from_bytes(packet).ok()?;
+    if eap.typ != Some(EAP_TYPE_TLV) || eap.data.len() < 6 {
+        return None;
+    }
+    let typ = u16::from_be_bytes([eap.data[0], eap.data[1]]) & 0x3fff;
+    let status = u16::from_be_bytes([eap.data[4], eap.data[5]]);
+    (typ == 3).then_some(status == RESULT_SUCCESS)
+}
+
+pub struct Peap {
+    config: Arc<ServerConfig>,
+    inner: Arc<dyn EapMethod>,
+    fragment_size: usize,
+}
+
+impl Peap {
+    /// PEAP with EAP-MSCHAPv2 inside, checking passwords with `lookup`.
+    pub fn new(config: Arc<ServerConfig>, lookup: PasswordLookup) -> Self {
+        Self::with_inner(config, EapMschapV2::with_lookup(lookup))
+    }
+
+    pub fn with_inner<M: EapMethod + 'static>(config: Arc<ServerConfig>, inner: M) -> Self {
+        Peap {
+            config,
+            inner: Arc::new(inner),
+            fragment_size: DEFAULT_FRAGMENT_SIZE,
+        }
+    }
+
+    pub fn fragment_size(mut self, size: usize) -> Self {
+        self.fragment_size = size;
+        self
+    }
+}
+
+impl EapMethod for Peap {
+    fn eap_type(&self) -> u8 {
+        EAP_TYPE_PEAP
+    }
+
+    fn start(&self, _identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+        let session = PeapSession {
+            tunnel: TlsTunnel::server(self.config.clone(), self.fragment_size)?,
+            inner: self.inner.clone(),
+            phase: Phase::Handshake,
+        };
+        Ok((Box::new(session), vec![FLAG_START]))
+    }
+}
+
+enum Phase {
+    Handshake,
+    Identity,
+    Inner {
+        identity: String,
+        session: Box<dyn EapSession>,
+    },
+    Result {
+        identity: String,
+        success: bool,
+    },
+    Done,
+}
+
+struct PeapSession {
+    tunnel: TlsTunnel,
+    inner: Arc<dyn EapMethod>,
+    phase: Phase,
+}
+
+impl EapSession for PeapSession {
+    fn process(&mut self, response: &EapPacket) -> EapStep {
+        match self.tunnel.receive(&response.data) {
+            Ok(TlsEvent::Send(data)) => EapStep::Continue(data),
+            Ok(TlsEvent::Acked | TlsEvent::Consumed) if self.tunnel.is_handshaking() => match self.tunnel.flush() {
+                Some(data) => EapStep::Continue(data),
+                None => EapStep::Failure("TLS handshake stalled".to_string()),
+            },
+            Ok(_) => {
+                let plaintext = self.tunnel.read_plaintext();
+                match self.tunnel_step(response.identifier, &plaintext) {
+                    Ok(Some(step)) => step,
+                    Ok(None) => match self.tunnel.flush() {
+                        Some(data) => EapStep::Continue(data),
+                        None => EapStep::Failure("PEAP tunnel stalled".to_string()),
+                    },
+                    Err(e) => EapStep::Failure(e),
+                }
+            }
+            Err(e) => EapStep::Failure(e),
+        }
+    }
+}
+
+impl PeapSession {
+    /// Advances the inner conversation. `Ok(None)` means something was
+    /// written into the tunnel and must be flushed to the peer.
+    fn tunnel_step(&mut self, identifier: u8, plaintext: &[u8]) -> Result<Option<EapStep>, String> {
+        match std::mem::replace(&mut self.phase, Phase::Done) {
+            Phase::Handshake => {
+                self.send_inner(EAP_TYPE_IDENTITY, &[])?;
+                self.phase = Phase::Identity;
+            }
+            Phase::Identity => {
+                let (typ, data) = split_inner(plaintext)?;
+                if typ != EAP_TYPE_IDENTITY {
+                    return Err(format!("expected inner identity, got EAP type {}", typ));
+                }
+                let identity = String::from_utf8_lossy(data).to_string();
+                let (session, first) = self.inner.start(&identity).map_err(|e| e.to_string())?;
+                self.send_inner(self.inner.eap_type(), &first)?;
+                self.phase = Phase::Inner { identity, session };
+            }
+            Phase::Inner { identity, mut session } => {
+                let (typ, data) = split_inner(plaintext)?;
+                if typ != self.inner.eap_type() {
+                    return Err(format!("unexpected inner EAP type {}", typ));
+                }
+                let inner_response = EapPacket::response(identifier, typ, data.to_vec());
+                match session.process(&inner_response) {
+                    EapStep::Continue(next) => {
+                        self.send_inner(typ, &next)?;
+                        self.phase = Phase::Inner { identity, session };
+                    }
+                    EapStep::Success(_) => {
+                        self.tunnel.write_plaintext(&result_tlv(EAP_REQUEST, identifier, true))?;
+                        self.phase = Phase::Result { identity, success: true };
+                    }
+                    EapStep::Failure(reason) => {
+                        eprintln!("🚫 PEAP inner authentication failed: {reason}");
+                        self.tunnel.write_plaintext(&result_tlv(EAP_REQUEST, identifier, false))?;
+                        self.phase = Phase::Result { identity, success: false };
+                    }
+                }
+            }
+            Phase::Result { identity, success } => {
+                let acknowledged = parse_result_tlv(plaintext)
+                    .ok_or_else(|| "expected a Result TLV from the peer".to_string())?;
+                if !(success && acknowledged) {
+                    return Ok(Some(EapStep::Failure("PEAP inner authentication failed".to_string())));
+                }
+                let mut keys = self.tunnel.keys(EAP_TYPE_PEAP, b"client EAP encryption")?;
+                keys.identity = Some(identity);
+                return Ok(Some(EapStep::Success(keys)));
+            }
+            Phase::Done => return Err("PEAP conversation already finished".to_string()),
+        }
+        Ok(None)
+    }
+
+    /// Sends an inner EAP-Request with its header stripped, as PEAPv0 does.
+    fn send_inner(&mut self, typ: u8, data: &[u8]) -> Result<(), String> {
+        let mut packet = vec![typ];
+        packet.extend_from_slice(data);
+        self.tunnel.write_plaintext(&packet)
+    }
+}
+
+/// Splits a header-less inner EAP-Response into type and type-data.
+fn split_inner(plaintext: &[u8]) -> Result<(u8, &[u8]), String> {
+    // Some peers keep the header on inner responses; accept both forms.
+    if plaintext.len() >= 5
+        && plaintext[0] == EAP_RESPONSE
+        && u16::from_be_bytes([plaintext[2], plaintext[3]]) as usize == plaintext.len()
+    {
+        return Ok((plaintext[4], &plaintext[5..]));
+    }
+    plaintext
+        .split_first()
+        .map(|(typ, data)| (*typ, data))
+        .ok_or_else(|| "empty inner EAP packet".to_string())
+}
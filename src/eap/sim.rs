@@ -0,0 +1,256 @@
+//! EAP-SIM (RFC 4186): full authentication with GSM triplets from an
+//! [`AuthVectorSource`]. Pseudonyms and fast re-authentication are not
+//! supported; the peer is always asked for its permanent identity.
+
+use super::milenage::Usim;
+use super::simaka::{
+    derive_keys, length_prefixed, read_length_prefixed, reserved, sim_master_key, verify_mac, MacAlgorithm,
+    SimAkaKeys, SimAkaMessage, AT_ANY_ID_REQ, AT_CLIENT_ERROR_CODE, AT_FULLAUTH_ID_REQ, AT_IDENTITY, AT_MAC,
+    AT_NONCE_MT, AT_PERMANENT_ID_REQ, AT_RAND, AT_SELECTED_VERSION, AT_VERSION_LIST,
+};
+use super::vectors::{imsi_from_identity, AuthVectorSource};
+use super::{
+    EapKeys, EapMethod, EapPacket, EapSession, EapStep, EAP_REQUEST, EAP_RESPONSE, EAP_TYPE_IDENTITY, EAP_TYPE_NAK,
+    EAP_TYPE_SIM,
+};
+use crate::error::RadiusError;
+use rand::RngCore;
+use std::sync::Arc;
+
+pub const SUBTYPE_START: u8 = 10;
+pub const SUBTYPE_CHALLENGE: u8 = 11;
+pub const SUBTYPE_NOTIFICATION: u8 = 12;
+pub const SUBTYPE_REAUTHENTICATION: u8 = 13;
+pub const SUBTYPE_CLIENT_ERROR: u8 = 14;
+
+/// The only EAP-SIM version defined.
+const VERSION: u16 = 1;
+
+pub struct EapSim {
+    source: Arc<dyn AuthVectorSource>,
+    triplets: usize,
+}
+
+impl EapSim {
+    pub fn new(source: Arc<dyn AuthVectorSource>) -> Self {
+        EapSim { source, triplets: 3 }
+    }
+
+    /// How many triplets each authentication uses: 2 or 3.
+    pub fn triplets(mut self, count: usize) -> Self {
+        self.triplets = count.clamp(2, 3);
+        self
+    }
+}
+
+impl EapMethod for EapSim {
+    fn eap_type(&self) -> u8 {
+        EAP_TYPE_SIM
+    }
+
+    fn start(&self, identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+        let session = SimSession {
+            source: self.source.clone(),
+            triplets: self.triplets,
+            identity: identity.to_string(),
+            stage: Stage::Start,
+        };
+        let start = SimAkaMessage::new(SUBTYPE_START)
+            .with(AT_VERSION_LIST, &length_prefixed(2, &VERSION.to_be_bytes()))
+            .with(AT_PERMANENT_ID_REQ, &reserved(&[]));
+        Ok((Box::new(session), start.to_bytes()))
+    }
+}
+
+enum Stage {
+    Start,
+    Challenge {
+        keys: SimAkaKeys,
+        sres: Vec<u8>,
+        session_id: Vec<u8>,
+    },
+    Done,
+}
+
+struct SimSession {
+    source: Arc<dyn AuthVectorSource>,
+    triplets: usize,
+    identity: String,
+    stage: Stage,
+}
+
+impl EapSession for SimSession {
+    fn process(&mut self, response: &EapPacket) -> EapStep {
+        let message = match SimAkaMessage::from_bytes(&response.data) {
+            Ok(message) => message,
+            Err(e) => return EapStep::Failure(e.to_string()),
+        };
+        if message.subtype == SUBTYPE_CLIENT_ERROR {
+            return EapStep::Failure(client_error(&message));
+        }
+
+        match (std::mem::replace(&mut self.stage, Stage::Done), message.subtype) {
+            (Stage::Start, SUBTYPE_START) => self.challenge(response.identifier, &message),
+            (Stage::Challenge { keys, sres, session_id }, SUBTYPE_CHALLENGE) => {
+                if !verify_mac(response, MacAlgorithm::HmacSha1, &keys.k_aut, &sres) {
+                    return EapStep::Failure(format!("EAP-SIM MAC mismatch for {}", self.identity));
+                }
+                EapStep::Success(EapKeys {
+                    msk: keys.msk,
+                    emsk: keys.emsk,
+                    session_id,
+                    identity: Some(self.identity.clone()),
+                })
+            }
+            (_, subtype) => EapStep::Failure(format!("unexpected EAP-SIM subtype {}", subtype)),
+        }
+    }
+}
+
+impl SimSession {
+    fn challenge(&mut self, identifier: u8, start: &SimAkaMessage) -> EapStep {
+        let Some(nonce_mt) = start.get(AT_NONCE_MT).filter(|v| v.len() == 18).map(|v| v[2..].to_vec()) else {
+            return EapStep::Failure("EAP-SIM Start response without AT_NONCE_MT".to_string());
+        };
+        if start.get(AT_SELECTED_VERSION).map(|v| &v[..2]) != Some(&VERSION.to_be_bytes()[..]) {
+            return EapStep::Failure("EAP-SIM peer selected an unsupported version".to_string());
+        }
+        if let Some(identity) = start.get(AT_IDENTITY).and_then(read_length_prefixed) {
+            self.identity = String::from_utf8_lossy(identity).to_string();
+        }
+
+        let triplets = match self.source.triplets(imsi_from_identity(&self.identity), self.triplets) {
+            Ok(triplets) => triplets,
+            Err(e) => return EapStep::Failure(e.to_string()),
+        };
+        let kcs: Vec<[u8; 8]> = triplets.iter().map(|t| t.kc).collect();
+        let rands: Vec<u8> = triplets.iter().flat_map(|t| t.rand).collect();
+        let sres: Vec<u8> = triplets.iter().flat_map(|t| t.sres).collect();
+
+        let mk = sim_master_key(self.identity.as_bytes(), &kcs, &nonce_mt, &VERSION.to_be_bytes(), VERSION);
+        let keys = derive_keys(&mk);
+
+        // EapServer numbers the next request after this response.
+        let data = SimAkaMessage::new(SUBTYPE_CHALLENGE).with(AT_RAND, &reserved(&rands)).sign(
+            EAP_REQUEST,
+            identifier.wrapping_add(1),
+            EAP_TYPE_SIM,
+            MacAlgorithm::HmacSha1,
+            &keys.k_aut,
+            &nonce_mt,
+        );
+
+        let session_id = [&[EAP_TYPE_SIM][..], &rands, &nonce_mt].concat();
+        self.stage = Stage::Challenge { keys, sres, session_id };
+        EapStep::Continue(data)
+    }
+}
+
+fn client_error(message: &SimAkaMessage) -> String {
+    let code = message
+        .get(AT_CLIENT_ERROR_CODE)
+        .map(|v| u16::from_be_bytes([v[0], v[1]]))
+        .unwrap_or(0);
+    format!("peer reported client error {}", code)
+}
+
+/// The peer side of EAP-SIM, backed by a simulated card.
+pub struct SimPeer {
+    identity: String,
+    usim: Usim,
+    nonce_mt: [u8; 16],
+    version_list: Vec<u8>,
+    keys: Option<SimAkaKeys>,
+}
+
+impl SimPeer {
+    /// `identity` should be a permanent identity such as `1<IMSI>@realm`.
+    pub fn new(identity: &str, usim: Usim) -> Self {
+        let mut nonce_mt = [0u8; 16];
+        rand::rng().fill_bytes(&mut nonce_mt);
+        SimPeer {
+            identity: identity.to_string(),
+            usim,
+            nonce_mt,
+            version_list: Vec::new(),
+            keys: None,
+        }
+    }
+
+    /// Keys derived by the peer once the challenge was accepted.
+    pub fn keys(&self) -> Option<&SimAkaKeys> {
+        self.keys.as_ref()
+    }
+
+    /// Answers an EAP-Request, including the initial Identity request.
+    pub fn respond(&mut self, request: &EapPacket) -> Result<EapPacket, RadiusError> {
+        let id = request.identifier;
+        match request.typ {
+            Some(EAP_TYPE_IDENTITY) => {
+                return Ok(EapPacket::response(id, EAP_TYPE_IDENTITY, self.identity.as_bytes().to_vec()));
+            }
+            Some(EAP_TYPE_SIM) => {}
+            _ => return Ok(EapPacket::response(id, EAP_TYPE_NAK, vec![EAP_TYPE_SIM])),
+        }
+
+        let message = SimAkaMessage::from_bytes(&request.data)?;
+        let reply = match message.subtype {
+            SUBTYPE_START => {
+                self.version_list = message
+                    .get(AT_VERSION_LIST)
+                    .and_then(read_length_prefixed)
+                    .ok_or_else(|| RadiusError::InvalidEap("EAP-SIM Start without AT_VERSION_LIST".to_string()))?
+                    .to_vec();
+                if !self.version_list.chunks(2).any(|v| v == VERSION.to_be_bytes()) {
+                    return Err(RadiusError::Eap("server does not offer EAP-SIM version 1".to_string()));
+                }
+
+                let mut reply = SimAkaMessage::new(SUBTYPE_START)
+                    .with(AT_NONCE_MT, &reserved(&self.nonce_mt))
+                    .with(AT_SELECTED_VERSION, &VERSION.to_be_bytes());
+                if [AT_PERMANENT_ID_REQ, AT_FULLAUTH_ID_REQ, AT_ANY_ID_REQ]
+                    .iter()
+                    .any(|t| message.get(*t).is_some())
+                {
+                    let identity = self.identity.as_bytes();
+                    reply = reply.with(AT_IDENTITY, &length_prefixed(identity.len() as u16, identity));
+                }
+                reply.to_bytes()
+            }
+            SUBTYPE_CHALLENGE => {
+                let rands = message
+                    .get(AT_RAND)
+                    .filter(|v| v.len() > 2)
+                    .ok_or_else(|| RadiusError::InvalidEap("EAP-SIM Challenge without AT_RAND".to_string()))?;
+                let (mut kcs, mut sres) = (Vec::new(), Vec::new());
+                for rand in rands[2..].chunks_exact(16) {
+                    let (s, kc) = self.usim.gsm(rand.try_into().unwrap());
+                    sres.extend_from_slice(&s);
+                    kcs.push(kc);
+                }
+
+                let mk = sim_master_key(self.identity.as_bytes(), &kcs, &self.nonce_mt, &self.version_list, VERSION);
+                let keys = derive_keys(&mk);
+                if message.get(AT_MAC).is_none()
+                    || !verify_mac(request, MacAlgorithm::HmacSha1, &keys.k_aut, &self.nonce_mt)
+                {
+                    SimAkaMessage::new(SUBTYPE_CLIENT_ERROR).with(AT_CLIENT_ERROR_CODE, &[0, 0]).to_bytes()
+                } else {
+                    let data = SimAkaMessage::new(SUBTYPE_CHALLENGE).sign(
+                        EAP_RESPONSE,
+                        id,
+                        EAP_TYPE_SIM,
+                        MacAlgorithm::HmacSha1,
+                        &keys.k_aut,
+                        &sres,
+                    );
+                    self.keys = Some(keys);
+                    data
+                }
+            }
+            SUBTYPE_NOTIFICATION => SimAkaMessage::new(SUBTYPE_NOTIFICATION).to_bytes(),
+            subtype => return Err(RadiusError::InvalidEap(format!("unsupported EAP-SIM subtype {}", subtype))),
+        };
+        Ok(EapPacket::response(id, EAP_TYPE_SIM, reply))
+    }
+}
@@ -0,0 +1,281 @@
+//! Message format and key derivation shared by EAP-SIM (RFC 4186),
+//! EAP-AKA (RFC 4187) and EAP-AKA' (RFC 5448).
+
+use super::EapPacket;
+use crate::error::RadiusError;
+use ring::hmac;
+use sha1::digest::generic_array::GenericArray;
+use sha1::{Digest, Sha1};
+
+pub const AT_RAND: u8 = 1;
+pub const AT_AUTN: u8 = 2;
+pub const AT_RES: u8 = 3;
+pub const AT_AUTS: u8 = 4;
+pub const AT_PADDING: u8 = 6;
+pub const AT_NONCE_MT: u8 = 7;
+pub const AT_PERMANENT_ID_REQ: u8 = 10;
+pub const AT_MAC: u8 = 11;
+pub const AT_NOTIFICATION: u8 = 12;
+pub const AT_ANY_ID_REQ: u8 = 13;
+pub const AT_IDENTITY: u8 = 14;
+pub const AT_VERSION_LIST: u8 = 15;
+pub const AT_SELECTED_VERSION: u8 = 16;
+pub const AT_FULLAUTH_ID_REQ: u8 = 17;
+pub const AT_COUNTER: u8 = 19;
+pub const AT_CLIENT_ERROR_CODE: u8 = 22;
+pub const AT_KDF_INPUT: u8 = 23;
+pub const AT_KDF: u8 = 24;
+pub const AT_CHECKCODE: u8 = 134;
+pub const AT_RESULT_IND: u8 = 135;
+
+/// An EAP-SIM/AKA type-data body: Subtype, two reserved octets and
+/// attributes whose length is counted in 4-octet units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimAkaMessage {
+    pub subtype: u8,
+    pub attributes: Vec<(u8, Vec<u8>)>,
+}
+
+impl SimAkaMessage {
+    pub fn new(subtype: u8) -> Self {
+        SimAkaMessage {
+            subtype,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Adds an attribute. `value` is everything after the Type and Length
+    /// octets and is zero-padded to a 4-octet boundary.
+    pub fn with(mut self, typ: u8, value: &[u8]) -> Self {
+        let mut value = value.to_vec();
+        value.resize((value.len() + 2).div_ceil(4) * 4 - 2, 0);
+        self.attributes.push((typ, value));
+        self
+    }
+
+    pub fn get(&self, typ: u8) -> Option<&[u8]> {
+        self.attributes.iter().find(|(t, _)| *t == typ).map(|(_, v)| v.as_slice())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![self.subtype, 0, 0];
+        for (typ, value) in &self.attributes {
+            buf.push(*typ);
+            buf.push(((value.len() + 2) / 4) as u8);
+            buf.extend_from_slice(value);
+        }
+        buf
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RadiusError> {
+        if data.len() < 3 {
+            return Err(RadiusError::InvalidEap("truncated EAP-SIM/AKA packet".to_string()));
+        }
+        let mut message = SimAkaMessage::new(data[0]);
+        for (offset, len) in attribute_offsets(data).map_err(RadiusError::InvalidEap)? {
+            message.attributes.push((data[offset], data[offset + 2..offset + len].to_vec()));
+        }
+        Ok(message)
+    }
+
+    /// Appends AT_MAC and returns the type-data of the request or response
+    /// it will be sent in. The MAC covers that whole EAP packet followed by
+    /// `extra` (NONCE_MT or the SRES values for EAP-SIM).
+    pub fn sign(self, code: u8, identifier: u8, typ: u8, mac: MacAlgorithm, k_aut: &[u8], extra: &[u8]) -> Vec<u8> {
+        let mut data = self.with(AT_MAC, &[0u8; 18]).to_bytes();
+        let mut input = EapPacket {
+            code,
+            identifier,
+            typ: Some(typ),
+            data: data.clone(),
+        }
+        .to_bytes();
+        input.extend_from_slice(extra);
+
+        let at = data.len() - 16;
+        data[at..].copy_from_slice(&mac.compute(k_aut, &input));
+        data
+    }
+}
+
+/// (offset, length in octets) of each attribute in a type-data body.
+fn attribute_offsets(data: &[u8]) -> Result<Vec<(usize, usize)>, String> {
+    let mut offsets = Vec::new();
+    let mut offset = 3;
+    while offset < data.len() {
+        let len = *data.get(offset + 1).ok_or("truncated EAP-SIM/AKA attribute")? as usize * 4;
+        if len == 0 || offset + len > data.len() {
+            return Err(format!("invalid length for EAP-SIM/AKA attribute {}", data[offset]));
+        }
+        offsets.push((offset, len));
+        offset += len;
+    }
+    Ok(offsets)
+}
+
+/// Checks the AT_MAC of a received packet, computed as in
+/// [`SimAkaMessage::sign`].
+pub fn verify_mac(packet: &EapPacket, mac: MacAlgorithm, k_aut: &[u8], extra: &[u8]) -> bool {
+    let Ok(offsets) = attribute_offsets(&packet.data) else {
+        return false;
+    };
+    let Some((offset, _)) = offsets
+        .into_iter()
+        .find(|(o, len)| packet.data[*o] == AT_MAC && *len == 20)
+    else {
+        return false;
+    };
+
+    let mut zeroed = packet.clone();
+    zeroed.data[offset + 4..offset + 20].fill(0);
+    let mut input = zeroed.to_bytes();
+    input.extend_from_slice(extra);
+
+    let expected = mac.compute(k_aut, &input);
+    let received = &packet.data[offset + 4..offset + 20];
+    expected.iter().zip(received).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Attribute value starting with two reserved octets (AT_RAND, AT_MAC, ...).
+pub fn reserved(value: &[u8]) -> Vec<u8> {
+    [&[0, 0][..], value].concat()
+}
+
+/// Attribute value starting with a 2-octet actual length (AT_IDENTITY,
+/// AT_VERSION_LIST, AT_KDF_INPUT; AT_RES counts bits).
+pub fn length_prefixed(length: u16, value: &[u8]) -> Vec<u8> {
+    [&length.to_be_bytes()[..], value].concat()
+}
+
+/// Reads a value written by [`length_prefixed`] with the length in octets.
+pub fn read_length_prefixed(value: &[u8]) -> Option<&[u8]> {
+    let len = u16::from_be_bytes([*value.first()?, *value.get(1)?]) as usize;
+    value.get(2..2 + len)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacAlgorithm {
+    /// HMAC-SHA1-128, used by EAP-SIM and EAP-AKA.
+    HmacSha1,
+    /// HMAC-SHA-256-128, used by EAP-AKA'.
+    HmacSha256,
+}
+
+impl MacAlgorithm {
+    pub fn compute(self, key: &[u8], data: &[u8]) -> [u8; 16] {
+        let algorithm = match self {
+            MacAlgorithm::HmacSha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            MacAlgorithm::HmacSha256 => hmac::HMAC_SHA256,
+        };
+        let tag = hmac::sign(&hmac::Key::new(algorithm, key), data);
+        tag.as_ref()[..16].try_into().unwrap()
+    }
+}
+
+/// Keys derived from the master key of a full authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimAkaKeys {
+    pub k_encr: Vec<u8>,
+    pub k_aut: Vec<u8>,
+    pub msk: Vec<u8>,
+    pub emsk: Vec<u8>,
+}
+
+/// EAP-SIM master key: SHA1(Identity | n*Kc | NONCE_MT | Version List | Selected Version).
+pub fn sim_master_key(identity: &[u8], kcs: &[[u8; 8]], nonce_mt: &[u8], version_list: &[u8], selected: u16) -> [u8; 20] {
+    let mut sha = Sha1::new();
+    sha.update(identity);
+    for kc in kcs {
+        sha.update(kc);
+    }
+    sha.update(nonce_mt);
+    sha.update(version_list);
+    sha.update(selected.to_be_bytes());
+    sha.finalize().into()
+}
+
+/// EAP-AKA master key: SHA1(Identity | IK | CK).
+pub fn aka_master_key(identity: &[u8], ik: &[u8; 16], ck: &[u8; 16]) -> [u8; 20] {
+    let mut sha = Sha1::new();
+    sha.update(identity);
+    sha.update(ik);
+    sha.update(ck);
+    sha.finalize().into()
+}
+
+/// The FIPS 186-2 pseudo-random function (with change notice 1, as
+/// specified in RFC 4186 Appendix B), producing `len` bytes.
+pub fn fips186_prf(mk: &[u8; 20], len: usize) -> Vec<u8> {
+    let mut xkey = *mk;
+    let mut out = Vec::with_capacity(len.div_ceil(40) * 40);
+
+    while out.len() < len {
+        // w = G(t, XKEY): the SHA-1 compression function over XKEY padded
+        // with zeros, starting from the standard initial state.
+        let mut block = [0u8; 64];
+        block[..20].copy_from_slice(&xkey);
+        let mut state = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+        sha1::compress(&mut state, &[GenericArray::clone_from_slice(&block)]);
+        let w: Vec<u8> = state.iter().flat_map(|s| s.to_be_bytes()).collect();
+
+        // XKEY = (1 + XKEY + w) mod 2^160
+        let mut carry = 1u16;
+        for i in (0..20).rev() {
+            let sum = xkey[i] as u16 + w[i] as u16 + carry;
+            xkey[i] = sum as u8;
+            carry = sum >> 8;
+        }
+        out.extend_from_slice(&w);
+    }
+    out.truncate(len);
+    out
+}
+
+/// K_encr, K_aut, MSK and EMSK for EAP-SIM and EAP-AKA.
+pub fn derive_keys(mk: &[u8; 20]) -> SimAkaKeys {
+    let prf = fips186_prf(mk, 160);
+    SimAkaKeys {
+        k_encr: prf[..16].to_vec(),
+        k_aut: prf[16..32].to_vec(),
+        msk: prf[32..96].to_vec(),
+        emsk: prf[96..160].to_vec(),
+    }
+}
+
+/// CK' and IK' of EAP-AKA' (RFC 5448 §3.3), binding the keys to the
+/// access network name and SQN xor AK (the first six octets of AUTN).
+pub fn aka_prime_ck_ik(ck: &[u8; 16], ik: &[u8; 16], network_name: &[u8], sqn_xor_ak: &[u8]) -> ([u8; 16], [u8; 16]) {
+    let mut s = vec![0x20];
+    s.extend_from_slice(network_name);
+    s.extend_from_slice(&(network_name.len() as u16).to_be_bytes());
+    s.extend_from_slice(sqn_xor_ak);
+    s.extend_from_slice(&(sqn_xor_ak.len() as u16).to_be_bytes());
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &[&ck[..], &ik[..]].concat());
+    let out = hmac::sign(&key, &s);
+    (out.as_ref()[..16].try_into().unwrap(), out.as_ref()[16..].try_into().unwrap())
+}
+
+/// K_encr, K_aut, MSK and EMSK for EAP-AKA' via PRF'(IK'|CK', "EAP-AKA'"|Identity).
+/// K_re (fast re-authentication) is skipped.
+pub fn aka_prime_keys(ck_prime: &[u8; 16], ik_prime: &[u8; 16], identity: &[u8]) -> SimAkaKeys {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &[&ik_prime[..], &ck_prime[..]].concat());
+    let s = [&b"EAP-AKA'"[..], identity].concat();
+
+    let mut out = Vec::with_capacity(224);
+    let mut t = Vec::new();
+    for n in 1u8..=7 {
+        let mut input = t.clone();
+        input.extend_from_slice(&s);
+        input.push(n);
+        t = hmac::sign(&key, &input).as_ref().to_vec();
+        out.extend_from_slice(&t);
+    }
+
+    SimAkaKeys {
+        k_encr: out[..16].to_vec(),
+        k_aut: out[16..48].to_vec(),
+        msk: out[80..144].to_vec(),
+        emsk: out[144..208].to_vec(),
+    }
+}
@@ -0,0 +1,249 @@
+//! An in-process EAP peer for exercising EAP-TLS, PEAP and TTLS against an
+//! [`EapServer`] without a NAS, a network or an external supplicant.
+
+use super::mschapv2::{encode, OP_CHALLENGE, OP_FAILURE, OP_RESPONSE, OP_SUCCESS};
+use super::peap::{parse_result_tlv, result_tlv};
+use super::tls::{TlsEvent, TlsTunnel, DEFAULT_FRAGMENT_SIZE, FLAG_START};
+use super::ttls::{Avp, AVP_USER_NAME, AVP_USER_PASSWORD};
+use super::{
+    EapKeys, EapPacket, EapReply, EapServer, EAP_RESPONSE, EAP_TYPE_IDENTITY, EAP_TYPE_MSCHAPV2, EAP_TYPE_NAK,
+    EAP_TYPE_PEAP, EAP_TYPE_TLS, EAP_TYPE_TTLS,
+};
+use crate::error::RadiusError;
+use crate::mschap::MsChapV2;
+use crate::packet::{RadiusAttribute, RadiusCode, RadiusPacket};
+use rand::RngCore;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection};
+use std::sync::Arc;
+
+/// Round trips after which a conversation is considered stuck.
+const MAX_ROUNDS: usize = 64;
+
+/// The method the simulated peer insists on, with its inner credentials.
+#[derive(Debug, Clone)]
+pub enum PeerMethod {
+    Tls,
+    Peap { username: String, password: String },
+    Ttls { username: String, password: String },
+}
+
+impl PeerMethod {
+    fn eap_type(&self) -> u8 {
+        match self {
+            PeerMethod::Tls => EAP_TYPE_TLS,
+            PeerMethod::Peap { .. } => EAP_TYPE_PEAP,
+            PeerMethod::Ttls { .. } => EAP_TYPE_TTLS,
+        }
+    }
+
+    fn key_label(&self) -> &'static [u8] {
+        match self {
+            PeerMethod::Ttls { .. } => b"ttls keying material",
+            _ => b"client EAP encryption",
+        }
+    }
+}
+
+/// How a simulated conversation ended.
+#[derive(Debug)]
+pub struct SupplicantOutcome {
+    pub accepted: bool,
+    /// Keys the server put in its Access-Accept.
+    pub server_keys: Option<EapKeys>,
+    /// Keys the peer derived on its side; they must match the server's.
+    pub peer_keys: Option<EapKeys>,
+    pub resumed: bool,
+    pub round_trips: usize,
+}
+
+pub struct Supplicant {
+    identity: String,
+    method: PeerMethod,
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+    fragment_size: usize,
+}
+
+impl Supplicant {
+    /// A peer using `config` (see [`client_config`](super::tls::client_config))
+    /// that expects the server certificate to be valid for `server_name`.
+    /// Reuse the same `config` to get TLS session resumption.
+    pub fn new(identity: &str, method: PeerMethod, config: Arc<ClientConfig>, server_name: &str) -> Result<Self, RadiusError> {
+        let server_name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| RadiusError::Config(format!("Invalid server name '{}': {}", server_name, e)))?;
+        Ok(Supplicant {
+            identity: identity.to_string(),
+            method,
+            config,
+            server_name,
+            fragment_size: DEFAULT_FRAGMENT_SIZE,
+        })
+    }
+
+    pub fn fragment_size(mut self, size: usize) -> Self {
+        self.fragment_size = size;
+        self
+    }
+
+    /// Runs a complete conversation, wrapping each EAP response in an
+    /// Access-Request as a NAS would.
+    pub fn authenticate(&self, server: &EapServer, secret: &str) -> Result<SupplicantOutcome, RadiusError> {
+        let mut peer = Peer {
+            supplicant: self,
+            tunnel: None,
+            sent_avps: false,
+        };
+        let mut state: Option<Vec<u8>> = None;
+        let mut response = EapPacket::response(0, EAP_TYPE_IDENTITY, self.identity.as_bytes().to_vec());
+
+        for round in 1..=MAX_ROUNDS {
+            let mut authenticator = [0u8; 16];
+            rand::rng().fill_bytes(&mut authenticator);
+
+            let mut attributes = vec![RadiusAttribute::user_name(&self.identity)?];
+            attributes.extend(RadiusAttribute::eap_message(&response.to_bytes()));
+            if let Some(state) = &state {
+                attributes.push(RadiusAttribute::state(state)?);
+            }
+            let request = RadiusPacket {
+                code: RadiusCode::AccessRequest,
+                identifier: round as u8,
+                length: 0,
+                authenticator,
+                attributes,
+            };
+
+            match server.handle(&request, secret) {
+                EapReply::Challenge(reply) => {
+                    state = reply.state().map(|s| s.to_vec());
+                    let raw = reply
+                        .eap_message()
+                        .ok_or_else(|| RadiusError::InvalidEap("Access-Challenge without EAP-Message".to_string()))?;
+                    response = peer.respond(&EapPacket::from_bytes(&raw)?).map_err(RadiusError::Eap)?;
+                }
+                EapReply::Accept { keys, .. } => {
+                    let tunnel = peer.tunnel.as_ref();
+                    return Ok(SupplicantOutcome {
+                        accepted: true,
+                        server_keys: Some(keys),
+                        peer_keys: tunnel
+                            .map(|t| t.keys(self.method.eap_type(), self.method.key_label()))
+                            .transpose()
+                            .map_err(RadiusError::Eap)?,
+                        resumed: tunnel.is_some_and(|t| t.resumed()),
+                        round_trips: round,
+                    });
+                }
+                EapReply::Discard(reason) => return Err(RadiusError::Discarded(reason)),
+                EapReply::Reject(_) => {
+                    return Ok(SupplicantOutcome {
+                        accepted: false,
+                        server_keys: None,
+                        peer_keys: None,
+                        resumed: false,
+                        round_trips: round,
+                    });
+                }
+            }
+        }
+
+        Err(RadiusError::Eap(format!("no result after {} round trips", MAX_ROUNDS)))
+    }
+}
+
+struct Peer<'a> {
+    supplicant: &'a Supplicant,
+    tunnel: Option<TlsTunnel>,
+    sent_avps: bool,
+}
+
+impl Peer<'_> {
+    fn respond(&mut self, request: &EapPacket) -> Result<EapPacket, String> {
+        let method = self.supplicant.method.eap_type();
+        let id = request.identifier;
+
+        if request.typ != Some(method) {
+            return Ok(EapPacket::response(id, EAP_TYPE_NAK, vec![method]));
+        }
+
+        if request.data.first().is_some_and(|f| f & FLAG_START != 0) {
+            let conn = ClientConnection::new(self.supplicant.config.clone(), self.supplicant.server_name.clone())
+                .map_err(|e| format!("TLS error: {}", e))?;
+            self.tunnel = Some(TlsTunnel::new(conn.into(), self.supplicant.fragment_size));
+            self.sent_avps = false;
+        }
+        let tunnel = self.tunnel.as_mut().ok_or("TLS data before Start")?;
+
+        let data = match tunnel.receive(&request.data)? {
+            TlsEvent::Send(data) => data,
+            TlsEvent::Acked | TlsEvent::Consumed => {
+                if !tunnel.is_handshaking() {
+                    self.tunnel_phase(id)?;
+                }
+                let tunnel = self.tunnel.as_mut().unwrap();
+                tunnel.flush().unwrap_or_else(|| vec![0])
+            }
+        };
+        Ok(EapPacket::response(id, method, data))
+    }
+
+    /// Answers whatever the server sent inside the established tunnel.
+    fn tunnel_phase(&mut self, id: u8) -> Result<(), String> {
+        let tunnel = self.tunnel.as_mut().unwrap();
+        let plaintext = tunnel.read_plaintext();
+
+        match &self.supplicant.method {
+            // Only the TLS 1.3 commitment message is expected here.
+            PeerMethod::Tls => Ok(()),
+            PeerMethod::Ttls { username, password } => {
+                if self.sent_avps {
+                    return Ok(());
+                }
+                self.sent_avps = true;
+                let mut padded = password.as_bytes().to_vec();
+                padded.resize(padded.len().div_ceil(16).max(1) * 16, 0);
+
+                let mut avps = Avp::new(AVP_USER_NAME, username.as_bytes()).to_bytes();
+                avps.extend(Avp::new(AVP_USER_PASSWORD, &padded).to_bytes());
+                tunnel.write_plaintext(&avps)
+            }
+            PeerMethod::Peap { username, password } => {
+                if plaintext.is_empty() {
+                    return Ok(());
+                }
+                if let Some(success) = parse_result_tlv(&plaintext) {
+                    return tunnel.write_plaintext(&result_tlv(EAP_RESPONSE, id, success));
+                }
+
+                let (typ, data) = plaintext.split_first().unwrap();
+                let reply = match (*typ, data.first().copied()) {
+                    (EAP_TYPE_IDENTITY, _) => [&[EAP_TYPE_IDENTITY][..], username.as_bytes()].concat(),
+                    (EAP_TYPE_MSCHAPV2, Some(OP_CHALLENGE)) if data.len() >= 21 => {
+                        let ms_id = data[1];
+                        let mut peer_challenge = [0u8; 16];
+                        rand::rng().fill_bytes(&mut peer_challenge);
+                        let mschap = MsChapV2::respond(
+                            ms_id,
+                            data[5..21].try_into().unwrap(),
+                            peer_challenge,
+                            username,
+                            password,
+                        );
+
+                        let mut payload = vec![49];
+                        payload.extend_from_slice(&peer_challenge);
+                        payload.extend_from_slice(&[0u8; 8]);
+                        payload.extend_from_slice(&mschap.nt_response);
+                        payload.push(0);
+                        payload.extend_from_slice(username.as_bytes());
+                        [&[EAP_TYPE_MSCHAPV2][..], &encode(OP_RESPONSE, ms_id, &payload)].concat()
+                    }
+                    (EAP_TYPE_MSCHAPV2, Some(op @ (OP_SUCCESS | OP_FAILURE))) => vec![EAP_TYPE_MSCHAPV2, op],
+                    (typ, _) => return Err(format!("unsupported inner EAP type {}", typ)),
+                };
+                tunnel.write_plaintext(&reply)
+            }
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! EAP-TLS (RFC 5216, RFC 9190 for TLS 1.3) and the TLS record transport
+//! shared with PEAP and EAP-TTLS.
+
+use super::{EapKeys, EapMethod, EapPacket, EapSession, EapStep, EAP_TYPE_TLS};
+use crate::error::RadiusError;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::ring::default_provider;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, Connection, DigitallySignedStruct, ProtocolVersion, RootCertStore, ServerConfig,
+    ServerConnection, SignatureScheme,
+};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+pub const FLAG_LENGTH: u8 = 0x80;
+pub const FLAG_MORE: u8 = 0x40;
+pub const FLAG_START: u8 = 0x20;
+
+/// TLS bytes carried per EAP fragment. Keeps each Access-Challenge well
+/// under the 4096-byte RADIUS limit.
+pub const DEFAULT_FRAGMENT_SIZE: usize = 1000;
+
+/// Upper bound on a reassembled TLS message from the peer.
+const MAX_TLS_MESSAGE: usize = 64 * 1024;
+
+/// Builds a TLS 1.2/1.3 server configuration from PEM files' contents.
+///
+/// With `client_ca_pem`, peers must present a certificate issued by one of
+/// those CAs (EAP-TLS); without it no client certificate is requested
+/// (PEAP, TTLS). Sessions are cached in memory so peers can resume.
+pub fn server_config(
+    cert_chain_pem: &[u8],
+    key_pem: &[u8],
+    client_ca_pem: Option<&[u8]>,
+) -> Result<Arc<ServerConfig>, RadiusError> {
+    let provider = Arc::new(default_provider());
+    let chain = load_certs(cert_chain_pem)?;
+    let key = load_key(key_pem)?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(rustls::ALL_VERSIONS)
+        .map_err(|e| RadiusError::Config(format!("TLS configuration error: {}", e)))?;
+
+    let builder = match client_ca_pem {
+        Some(ca_pem) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(load_roots(ca_pem)?), provider)
+                .build()
+                .map_err(|e| RadiusError::Config(format!("Invalid client CA: {}", e)))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(chain, key)
+        .map_err(|e| RadiusError::Config(format!("Invalid server certificate: {}", e)))?;
+    config.session_storage = rustls::server::ServerSessionMemoryCache::new(1024);
+    Ok(Arc::new(config))
+}
+
+/// Builds a TLS client configuration trusting `ca_pem`, optionally with a
+/// client certificate. Used by the [`supplicant`](super::supplicant).
+pub fn client_config(ca_pem: &[u8], client_cert: Option<(&[u8], &[u8])>) -> Result<Arc<ClientConfig>, RadiusError> {
+    let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_protocol_versions(rustls::ALL_VERSIONS)
+        .map_err(|e| RadiusError::Config(format!("TLS configuration error: {}", e)))?
+        .with_root_certificates(load_roots(ca_pem)?);
+
+    let config = match client_cert {
+        Some((cert_pem, key_pem)) => {
+            let key = load_key(key_pem)?;
+            builder
+                .with_client_auth_cert(load_certs(cert_pem)?, key)
+                .map_err(|e| RadiusError::Config(format!("Invalid client certificate: {}", e)))?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// Whether `config` refuses peers without a certificate, found out by
+/// running an in-memory handshake against it with a client that has none.
+/// rustls does not expose the configured client verifier directly.
+fn requires_client_cert(config: &Arc<ServerConfig>) -> Result<bool, RadiusError> {
+    let provider = Arc::new(default_provider());
+    let probe = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(rustls::ALL_VERSIONS)
+        .map_err(|e| RadiusError::Config(format!("TLS configuration error: {}", e)))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyServer(provider)))
+        .with_no_client_auth();
+    let name = ServerName::try_from("probe.invalid").unwrap();
+    let tls_error = |e: rustls::Error| RadiusError::Config(format!("TLS error: {}", e));
+    let mut client = ClientConnection::new(Arc::new(probe), name).map_err(tls_error)?;
+    let mut server = ServerConnection::new(config.clone()).map_err(tls_error)?;
+
+    for _ in 0..8 {
+        let mut records = Vec::new();
+        while client.wants_write() {
+            client.write_tls(&mut records).map_err(|e| RadiusError::Config(format!("TLS write error: {}", e)))?;
+        }
+        let mut input = records.as_slice();
+        while !input.is_empty() {
+            server.read_tls(&mut input).map_err(|e| RadiusError::Config(format!("TLS read error: {}", e)))?;
+            if server.process_new_packets().is_err() {
+                return Ok(true);
+            }
+        }
+        if !server.is_handshaking() {
+            return Ok(false);
+        }
+
+        let mut records = Vec::new();
+        while server.wants_write() {
+            server.write_tls(&mut records).map_err(|e| RadiusError::Config(format!("TLS write error: {}", e)))?;
+        }
+        let mut input = records.as_slice();
+        while !input.is_empty() {
+            client.read_tls(&mut input).map_err(|e| RadiusError::Config(format!("TLS read error: {}", e)))?;
+            client.process_new_packets().map_err(tls_error)?;
+        }
+    }
+    Err(RadiusError::Config("TLS handshake stalled while checking the client verifier".to_string()))
+}
+
+/// Accepts any server certificate with a valid handshake signature. Only
+/// used by [`requires_client_cert`], which never sends data.
+#[derive(Debug)]
+struct AnyServer(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyServer {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn load_certs(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>, RadiusError> {
+    let certs = CertificateDer::pem_slice_iter(pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RadiusError::Config(format!("Invalid certificate PEM: {}", e)))?;
+    if certs.is_empty() {
+        return Err(RadiusError::Config("No certificates found in PEM".to_string()));
+    }
+    Ok(certs)
+}
+
+fn load_key(pem: &[u8]) -> Result<PrivateKeyDer<'static>, RadiusError> {
+    PrivateKeyDer::from_pem_slice(pem).map_err(|e| RadiusError::Config(format!("Invalid private key: {}", e)))
+}
+
+fn load_roots(pem: &[u8]) -> Result<RootCertStore, RadiusError> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(pem)? {
+        roots.add(cert).map_err(|e| RadiusError::Config(format!("Invalid CA certificate: {}", e)))?;
+    }
+    Ok(roots)
+}
+
+/// What [`TlsTunnel::receive`] made of a peer message.
+pub(crate) enum TlsEvent {
+    /// Reply with this type-data (an ACK or our next fragment).
+    Send(Vec<u8>),
+    /// A complete TLS message was handed to rustls (or a Start arrived).
+    Consumed,
+    /// The peer acknowledged our last fragment and nothing is queued.
+    Acked,
+}
+
+/// Carries TLS records over EAP-TLS style framing (Flags, optional
+/// TLS Message Length, data), fragmenting and reassembling as needed.
+/// Works for either end of the connection.
+pub(crate) struct TlsTunnel {
+    conn: Connection,
+    fragment_size: usize,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    sent: usize,
+    client_random: Option<[u8; 32]>,
+    server_random: Option<[u8; 32]>,
+}
+
+impl TlsTunnel {
+    pub(crate) fn new(conn: Connection, fragment_size: usize) -> Self {
+        TlsTunnel {
+            conn,
+            fragment_size: fragment_size.max(64),
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            sent: 0,
+            client_random: None,
+            server_random: None,
+        }
+    }
+
+    pub(crate) fn server(config: Arc<ServerConfig>, fragment_size: usize) -> Result<Self, RadiusError> {
+        let conn = ServerConnection::new(config).map_err(|e| RadiusError::Eap(format!("TLS error: {}", e)))?;
+        Ok(Self::new(conn.into(), fragment_size))
+    }
+
+    pub(crate) fn receive(&mut self, data: &[u8]) -> Result<TlsEvent, String> {
+        let Some((&flags, mut rest)) = data.split_first() else {
+            return Err("empty TLS fragment".to_string());
+        };
+        if flags & FLAG_START != 0 {
+            return Ok(TlsEvent::Consumed);
+        }
+        if flags & FLAG_LENGTH != 0 {
+            if rest.len() < 4 {
+                return Err("truncated TLS Message Length".to_string());
+            }
+            rest = &rest[4..];
+        }
+
+        if rest.is_empty() && flags & FLAG_MORE == 0 && self.incoming.is_empty() {
+            if self.sent < self.outgoing.len() {
+                return Ok(TlsEvent::Send(self.next_fragment()));
+            }
+            self.outgoing.clear();
+            self.sent = 0;
+            return Ok(TlsEvent::Acked);
+        }
+
+        if self.sent < self.outgoing.len() {
+            return Err("peer sent data before acknowledging our fragments".to_string());
+        }
+        self.outgoing.clear();
+        self.sent = 0;
+
+        self.incoming.extend_from_slice(rest);
+        if self.incoming.len() > MAX_TLS_MESSAGE {
+            return Err("TLS message too large".to_string());
+        }
+        if flags & FLAG_MORE != 0 {
+            return Ok(TlsEvent::Send(vec![0]));
+        }
+
+        let message = std::mem::take(&mut self.incoming);
+        self.capture_randoms(&message);
+
+        let mut input = message.as_slice();
+        while !input.is_empty() {
+            self.conn.read_tls(&mut input).map_err(|e| format!("TLS read error: {}", e))?;
+            self.conn.process_new_packets().map_err(|e| format!("TLS error: {}", e))?;
+        }
+        Ok(TlsEvent::Consumed)
+    }
+
+    /// Collects what rustls has to send and returns its first fragment.
+    pub(crate) fn flush(&mut self) -> Option<Vec<u8>> {
+        let start = self.outgoing.len();
+        while self.conn.wants_write() {
+            if self.conn.write_tls(&mut self.outgoing).is_err() {
+                break;
+            }
+        }
+        let written = self.outgoing[start..].to_vec();
+        self.capture_randoms(&written);
+
+        (self.sent < self.outgoing.len()).then(|| self.next_fragment())
+    }
+
+    fn next_fragment(&mut self) -> Vec<u8> {
+        let total = self.outgoing.len();
+        let end = (self.sent + self.fragment_size).min(total);
+
+        let mut out = vec![0];
+        if end < total {
+            out[0] |= FLAG_MORE;
+        }
+        if self.sent == 0 && end < total {
+            out[0] |= FLAG_LENGTH;
+            out.extend_from_slice(&(total as u32).to_be_bytes());
+        }
+        out.extend_from_slice(&self.outgoing[self.sent..end]);
+        self.sent = end;
+        out
+    }
+
+    pub(crate) fn is_handshaking(&self) -> bool {
+        self.conn.is_handshaking()
+    }
+
+    pub(crate) fn is_tls13(&self) -> bool {
+        self.conn.protocol_version() == Some(ProtocolVersion::TLSv1_3)
+    }
+
+    /// Whether the peer authenticated with a certificate, in this handshake
+    /// or the one being resumed.
+    pub(crate) fn has_peer_certificate(&self) -> bool {
+        self.conn.peer_certificates().is_some_and(|chain| !chain.is_empty())
+    }
+
+    pub(crate) fn resumed(&self) -> bool {
+        self.conn.handshake_kind() == Some(rustls::HandshakeKind::Resumed)
+    }
+
+    pub(crate) fn write_plaintext(&mut self, data: &[u8]) -> Result<(), String> {
+        self.conn.writer().write_all(data).map_err(|e| format!("TLS write error: {}", e))
+    }
+
+    pub(crate) fn read_plaintext(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = self.conn.reader().read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+        out
+    }
+
+    /// Derives MSK/EMSK and Session-Id for `eap_type`: RFC 5216-style PRF
+    /// output under `tls12_label` for TLS 1.2, or the RFC 9190 exporter
+    /// with the EAP type as context for TLS 1.3.
+    pub(crate) fn keys(&self, eap_type: u8, tls12_label: &[u8]) -> Result<EapKeys, String> {
+        let export = |label: &[u8], context: Option<&[u8]>, len: usize| {
+            self.conn
+                .export_keying_material(vec![0u8; len], label, context)
+                .map_err(|e| format!("TLS exporter failed: {}", e))
+        };
+
+        let (material, session_id) = if self.is_tls13() {
+            let material = export(b"EXPORTER_EAP_TLS_Key_Material", Some(&[eap_type]), 128)?;
+            let method_id = export(b"EXPORTER_EAP_TLS_Method-Id", Some(&[]), 64)?;
+            (material, [&[eap_type][..], &method_id].concat())
+        } else {
+            let material = export(tls12_label, None, 128)?;
+            let session_id = match (self.client_random, self.server_random) {
+                (Some(c), Some(s)) => [&[eap_type][..], &c, &s].concat(),
+                _ => Vec::new(),
+            };
+            (material, session_id)
+        };
+
+        Ok(EapKeys {
+            msk: material[..64].to_vec(),
+            emsk: material[64..].to_vec(),
+            session_id,
+            identity: None,
+        })
+    }
+
+    /// Remembers the Hello randoms as they pass by, for the TLS 1.2
+    /// Session-Id (rustls does not expose them).
+    fn capture_randoms(&mut self, records: &[u8]) {
+        // Record header (5) + handshake header (4) + legacy version (2).
+        if records.len() < 43 || records[0] != 22 {
+            return;
+        }
+        let random: [u8; 32] = records[11..43].try_into().unwrap();
+        match records[5] {
+            1 if self.client_random.is_none() => self.client_random = Some(random),
+            2 if self.server_random.is_none() => self.server_random = Some(random),
+            _ => {}
+        }
+    }
+}
+
+/// EAP-TLS: mutual certificate authentication. Pass a configuration built
+/// with a client CA (see [`server_config`]).
+pub struct EapTls {
+    config: Arc<ServerConfig>,
+    fragment_size: usize,
+}
+
+impl EapTls {
+    /// Fails if `config` would let a peer without a certificate through.
+    pub fn new(config: Arc<ServerConfig>) -> Result<Self, RadiusError> {
+        if !requires_client_cert(&config)? {
+            return Err(RadiusError::Config(
+                "EAP-TLS needs a server configuration that requires client certificates".to_string(),
+            ));
+        }
+        Ok(EapTls {
+            config,
+            fragment_size: DEFAULT_FRAGMENT_SIZE,
+        })
+    }
+
+    pub fn fragment_size(mut self, size: usize) -> Self {
+        self.fragment_size = size;
+        self
+    }
+}
+
+impl EapMethod for EapTls {
+    fn eap_type(&self) -> u8 {
+        EAP_TYPE_TLS
+    }
+
+    fn start(&self, _identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+        let session = EapTlsSession {
+            tunnel: TlsTunnel::server(self.config.clone(), self.fragment_size)?,
+            committed: false,
+        };
+        Ok((Box::new(session), vec![FLAG_START]))
+    }
+}
+
+struct EapTlsSession {
+    tunnel: TlsTunnel,
+    committed: bool,
+}
+
+impl EapSession for EapTlsSession {
+    fn process(&mut self, response: &EapPacket) -> EapStep {
+        let event = match self.tunnel.receive(&response.data) {
+            Ok(event) => event,
+            Err(e) => return EapStep::Failure(e),
+        };
+
+        match event {
+            TlsEvent::Send(data) => EapStep::Continue(data),
+            TlsEvent::Acked if !self.tunnel.is_handshaking() => self.finish(),
+            TlsEvent::Acked => EapStep::Failure("unexpected ACK during TLS handshake".to_string()),
+            TlsEvent::Consumed => {
+                // RFC 9190 §2.5: with TLS 1.3 the server signals the end of
+                // the handshake with a single 0x00 of application data.
+                if !self.tunnel.is_handshaking() && self.tunnel.is_tls13() && !self.committed {
+                    self.committed = true;
+                    if let Err(e) = self.tunnel.write_plaintext(&[0]) {
+                        return EapStep::Failure(e);
+                    }
+                }
+                match self.tunnel.flush() {
+                    Some(data) => EapStep::Continue(data),
+                    None if !self.tunnel.is_handshaking() => self.finish(),
+                    None => EapStep::Failure("TLS handshake stalled".to_string()),
+                }
+            }
+        }
+    }
+}
+
+impl EapTlsSession {
+    fn finish(&self) -> EapStep {
+        if !self.tunnel.has_peer_certificate() {
+            return EapStep::Failure("peer did not present a client certificate".to_string());
+        }
+        match self.tunnel.keys(EAP_TYPE_TLS, b"client EAP encryption") {
+            Ok(keys) => EapStep::Success(keys),
+            Err(e) => EapStep::Failure(e),
+        }
+    }
+}
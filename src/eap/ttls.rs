@@ -0,0 +1,185 @@
+//! EAP-TTLSv0 (RFC 5281) with PAP as the inner method.
+
+use super::tls::{TlsEvent, TlsTunnel, DEFAULT_FRAGMENT_SIZE, FLAG_START};
+use super::{EapMethod, EapPacket, EapSession, EapStep, PasswordLookup, EAP_TYPE_TTLS};
+use crate::error::RadiusError;
+use crate::handler::constant_time_eq;
+use crate::mschap::KnownPassword;
+use rustls::ServerConfig;
+use std::sync::Arc;
+
+pub const AVP_USER_NAME: u32 = 1;
+pub const AVP_USER_PASSWORD: u32 = 2;
+
+const AVP_FLAG_VENDOR: u8 = 0x80;
+const AVP_FLAG_MANDATORY: u8 = 0x40;
+
+/// A Diameter-encoded AVP as carried inside the TTLS tunnel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Avp {
+    pub code: u32,
+    pub vendor: Option<u32>,
+    pub mandatory: bool,
+    pub data: Vec<u8>,
+}
+
+impl Avp {
+    pub fn new(code: u32, data: &[u8]) -> Self {
+        Avp {
+            code,
+            vendor: None,
+            mandatory: true,
+            data: data.to_vec(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header_len = if self.vendor.is_some() { 12 } else { 8 };
+        let length = (header_len + self.data.len()) as u32;
+
+        let mut flags = 0;
+        if self.vendor.is_some() {
+            flags |= AVP_FLAG_VENDOR;
+        }
+        if self.mandatory {
+            flags |= AVP_FLAG_MANDATORY;
+        }
+
+        let mut buf = self.code.to_be_bytes().to_vec();
+        buf.push(flags);
+        buf.extend_from_slice(&length.to_be_bytes()[1..]);
+        if let Some(vendor) = self.vendor {
+            buf.extend_from_slice(&vendor.to_be_bytes());
+        }
+        buf.extend_from_slice(&self.data);
+        buf.resize(buf.len().div_ceil(4) * 4, 0);
+        buf
+    }
+
+    /// Decodes a sequence of AVPs, each padded to a 4-byte boundary.
+    pub fn parse_all(mut buf: &[u8]) -> Result<Vec<Avp>, RadiusError> {
+        let mut avps = Vec::new();
+        while !buf.is_empty() {
+            if buf.len() < 8 {
+                return Err(RadiusError::InvalidEap("truncated AVP header".to_string()));
+            }
+            let code = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+            let flags = buf[4];
+            let length = u32::from_be_bytes([0, buf[5], buf[6], buf[7]]) as usize;
+
+            let header_len = if flags & AVP_FLAG_VENDOR != 0 { 12 } else { 8 };
+            if length < header_len || length > buf.len() {
+                return Err(RadiusError::InvalidEap(format!("invalid AVP length {} for code {}", length, code)));
+            }
+            let vendor = (flags & AVP_FLAG_VENDOR != 0).then(|| u32::from_be_bytes(buf[8..12].try_into().unwrap()));
+
+            avps.push(Avp {
+                code,
+                vendor,
+                mandatory: flags & AVP_FLAG_MANDATORY != 0,
+                data: buf[header_len..length].to_vec(),
+            });
+            let padded = length.div_ceil(4) * 4;
+            buf = &buf[padded.min(buf.len())..];
+        }
+        Ok(avps)
+    }
+}
+
+pub struct Ttls {
+    config: Arc<ServerConfig>,
+    lookup: PasswordLookup,
+    fragment_size: usize,
+}
+
+impl Ttls {
+    /// TTLS with inner PAP, checking the tunneled User-Password against
+    /// the cleartext password returned by `lookup`.
+    pub fn new(config: Arc<ServerConfig>, lookup: PasswordLookup) -> Self {
+        Ttls {
+            config,
+            lookup,
+            fragment_size: DEFAULT_FRAGMENT_SIZE,
+        }
+    }
+
+    pub fn fragment_size(mut self, size: usize) -> Self {
+        self.fragment_size = size;
+        self
+    }
+}
+
+impl EapMethod for Ttls {
+    fn eap_type(&self) -> u8 {
+        EAP_TYPE_TTLS
+    }
+
+    fn start(&self, _identity: &str) -> Result<(Box<dyn EapSession>, Vec<u8>), RadiusError> {
+        let session = TtlsSession {
+            tunnel: TlsTunnel::server(self.config.clone(), self.fragment_size)?,
+            lookup: self.lookup.clone(),
+        };
+        Ok((Box::new(session), vec![FLAG_START]))
+    }
+}
+
+struct TtlsSession {
+    tunnel: TlsTunnel,
+    lookup: PasswordLookup,
+}
+
+impl EapSession for TtlsSession {
+    fn process(&mut self, response: &EapPacket) -> EapStep {
+        match self.tunnel.receive(&response.data) {
+            Ok(TlsEvent::Send(data)) => EapStep::Continue(data),
+            Ok(_) if self.tunnel.is_handshaking() => match self.tunnel.flush() {
+                Some(data) => EapStep::Continue(data),
+                None => EapStep::Failure("TLS handshake stalled".to_string()),
+            },
+            Ok(_) => {
+                let plaintext = self.tunnel.read_plaintext();
+                if plaintext.is_empty() {
+                    // Handshake just finished: deliver our last flight, or
+                    // prompt the peer for its AVPs with an empty request.
+                    return EapStep::Continue(self.tunnel.flush().unwrap_or_else(|| vec![0]));
+                }
+                self.check_pap(&plaintext)
+            }
+            Err(e) => EapStep::Failure(e),
+        }
+    }
+}
+
+impl TtlsSession {
+    fn check_pap(&self, plaintext: &[u8]) -> EapStep {
+        let avps = match Avp::parse_all(plaintext) {
+            Ok(avps) => avps,
+            Err(e) => return EapStep::Failure(e.to_string()),
+        };
+        let find = |code| avps.iter().find(|a| a.code == code && a.vendor.is_none());
+
+        let (Some(user), Some(password)) = (find(AVP_USER_NAME), find(AVP_USER_PASSWORD)) else {
+            return EapStep::Failure("TTLS tunnel carried no PAP credentials".to_string());
+        };
+        let username = String::from_utf8_lossy(&user.data).to_string();
+
+        // The peer pads the tunneled password with NULs to 16 bytes.
+        let mut password = password.data.clone();
+        while password.last() == Some(&0) {
+            password.pop();
+        }
+
+        match (self.lookup)(&username) {
+            Some(KnownPassword::Cleartext(known)) if constant_time_eq(known.as_bytes(), &password) => {
+                match self.tunnel.keys(EAP_TYPE_TTLS, b"ttls keying material") {
+                    Ok(mut keys) => {
+                        keys.identity = Some(username);
+                        EapStep::Success(keys)
+                    }
+                    Err(e) => EapStep::Failure(e),
+                }
+            }
+            _ => EapStep::Failure(format!("TTLS PAP authentication failed for {}", username)),
+        }
+    }
+}
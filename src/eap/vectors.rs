@@ -0,0 +1,240 @@
+//! Sources of GSM triplets and UMTS quintuplets for EAP-SIM and EAP-AKA.
+//!
+//! [`FileVectorSource`] reads a whitespace-separated file, one entry per line:
+//!
+//! ```text
+//! # IMSI            kind        values (hex)
+//! 234150999999999   triplet     RAND SRES Kc
+//! 234150999999999   quintuplet  RAND AUTN XRES CK IK
+//! 234150999999998   milenage    ki=<K> opc=<OPc> [op=<OP>] [amf=8000] [sqn=<48-bit hex>]
+//! ```
+//!
+//! Stored triplets and quintuplets are handed out in rotation; `milenage`
+//! entries generate fresh vectors from the subscriber keys.
+
+use super::milenage::{Milenage, DEFAULT_AMF};
+use crate::error::RadiusError;
+use crate::hex::{self, decode_array};
+use rand::RngCore;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// RAND, SRES and Kc of one GSM authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Triplet {
+    pub rand: [u8; 16],
+    pub sres: [u8; 4],
+    pub kc: [u8; 8],
+}
+
+/// RAND, AUTN, XRES, CK and IK of one UMTS authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quintuplet {
+    pub rand: [u8; 16],
+    pub autn: [u8; 16],
+    pub xres: Vec<u8>,
+    pub ck: [u8; 16],
+    pub ik: [u8; 16],
+}
+
+/// Where EAP-SIM and EAP-AKA get their authentication vectors, typically
+/// an HLR/HSS gateway. Lookups are keyed by IMSI (see [`imsi_from_identity`]).
+pub trait AuthVectorSource: Send + Sync {
+    /// `count` triplets with distinct RANDs.
+    fn triplets(&self, imsi: &str, count: usize) -> Result<Vec<Triplet>, RadiusError>;
+
+    fn quintuplet(&self, imsi: &str) -> Result<Quintuplet, RadiusError>;
+
+    /// Called when the card reports a stale sequence number; a following
+    /// [`quintuplet`](Self::quintuplet) call should then succeed.
+    fn resynchronise(&self, imsi: &str, _rand: &[u8; 16], _auts: &[u8; 14]) -> Result<(), RadiusError> {
+        Err(RadiusError::Eap(format!("cannot resynchronise sequence number for {}", imsi)))
+    }
+}
+
+/// Strips the realm and the method hint (`1` SIM, `0` AKA, `6` AKA') from a
+/// permanent identity such as `1234150999999999@wlan.mnc015.mcc234.3gppnetwork.org`.
+pub fn imsi_from_identity(identity: &str) -> &str {
+    let user = identity.split('@').next().unwrap_or(identity);
+    match user.strip_prefix(['0', '1', '6']) {
+        Some(imsi) if !imsi.is_empty() && imsi.bytes().all(|b| b.is_ascii_digit()) => imsi,
+        _ => user,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Subscriber {
+    triplets: Vec<Triplet>,
+    quintuplets: Vec<Quintuplet>,
+    milenage: Option<(Milenage, [u8; 2])>,
+    sqn: u64,
+    next: usize,
+}
+
+/// Authentication vectors read from a local file.
+#[derive(Debug, Default)]
+pub struct FileVectorSource {
+    subscribers: Mutex<HashMap<String, Subscriber>>,
+}
+
+impl FileVectorSource {
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, RadiusError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|source| RadiusError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        })?;
+        Self::parse_from_str(&content).map_err(|e| e.in_file(path))
+    }
+
+    pub fn parse_from_str(content: &str) -> Result<Self, RadiusError> {
+        let error = |line: usize, message: String| RadiusError::ConfigParse { file: None, line, message };
+        let mut subscribers: HashMap<String, Subscriber> = HashMap::new();
+
+        for (lineno, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [imsi, kind, values @ ..] = fields.as_slice() else {
+                return Err(error(lineno + 1, "Expected 'IMSI kind values...'".to_string()));
+            };
+            let subscriber = subscribers.entry(imsi.to_string()).or_default();
+
+            let result = match *kind {
+                "triplet" => parse_triplet(values).map(|t| subscriber.triplets.push(t)),
+                "quintuplet" => parse_quintuplet(values).map(|q| subscriber.quintuplets.push(q)),
+                "milenage" => parse_milenage(values).map(|(m, amf, sqn)| {
+                    subscriber.milenage = Some((m, amf));
+                    subscriber.sqn = sqn;
+                }),
+                other => Err(format!("Unknown entry kind '{}'", other)),
+            };
+            result.map_err(|e| error(lineno + 1, e))?;
+        }
+
+        Ok(FileVectorSource {
+            subscribers: Mutex::new(subscribers),
+        })
+    }
+
+    /// Number of subscribers known.
+    pub fn len(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl AuthVectorSource for FileVectorSource {
+    fn triplets(&self, imsi: &str, count: usize) -> Result<Vec<Triplet>, RadiusError> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let sub = subscribers.get_mut(imsi).ok_or_else(|| RadiusError::Eap(format!("Unknown IMSI {}", imsi)))?;
+
+        if let Some((milenage, _)) = &sub.milenage {
+            return Ok((0..count).map(|_| milenage.triplet(random_rand())).collect());
+        }
+        if sub.triplets.len() < count {
+            return Err(RadiusError::Eap(format!("Need {} triplets for {}, have {}", count, imsi, sub.triplets.len())));
+        }
+        let start = sub.next;
+        sub.next += count;
+        Ok((0..count)
+            .map(|i| sub.triplets[(start + i) % sub.triplets.len()].clone())
+            .collect())
+    }
+
+    fn quintuplet(&self, imsi: &str) -> Result<Quintuplet, RadiusError> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let sub = subscribers.get_mut(imsi).ok_or_else(|| RadiusError::Eap(format!("Unknown IMSI {}", imsi)))?;
+
+        if let Some((milenage, amf)) = &sub.milenage {
+            // Step SQN by 32, leaving the low five bits (IND) at zero.
+            sub.sqn += 32;
+            return Ok(milenage.quintuplet(random_rand(), sub.sqn, *amf));
+        }
+        if sub.quintuplets.is_empty() {
+            return Err(RadiusError::Eap(format!("No quintuplets for {}", imsi)));
+        }
+        sub.next += 1;
+        Ok(sub.quintuplets[(sub.next - 1) % sub.quintuplets.len()].clone())
+    }
+
+    fn resynchronise(&self, imsi: &str, rand: &[u8; 16], auts: &[u8; 14]) -> Result<(), RadiusError> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let sub = subscribers.get_mut(imsi).ok_or_else(|| RadiusError::Eap(format!("Unknown IMSI {}", imsi)))?;
+        let Some((milenage, _)) = &sub.milenage else {
+            return Err(RadiusError::Eap(format!("cannot resynchronise stored quintuplets for {}", imsi)));
+        };
+
+        let sqn = milenage
+            .resynchronise(rand, auts)
+            .ok_or_else(|| RadiusError::Eap(format!("AUTS from {} failed verification", imsi)))?;
+        sub.sqn = sqn;
+        Ok(())
+    }
+}
+
+fn random_rand() -> [u8; 16] {
+    let mut rand = [0u8; 16];
+    rand::rng().fill_bytes(&mut rand);
+    rand
+}
+
+fn parse_triplet(values: &[&str]) -> Result<Triplet, String> {
+    let [rand, sres, kc] = values else {
+        return Err("Expected RAND SRES Kc".to_string());
+    };
+    Ok(Triplet {
+        rand: decode_array(rand)?,
+        sres: decode_array(sres)?,
+        kc: decode_array(kc)?,
+    })
+}
+
+fn parse_quintuplet(values: &[&str]) -> Result<Quintuplet, String> {
+    let [rand, autn, xres, ck, ik] = values else {
+        return Err("Expected RAND AUTN XRES CK IK".to_string());
+    };
+    let xres = hex::decode(xres)?;
+    if !(4..=16).contains(&xres.len()) {
+        return Err(format!("XRES must be 4 to 16 bytes, got {}", xres.len()));
+    }
+    Ok(Quintuplet {
+        rand: decode_array(rand)?,
+        autn: decode_array(autn)?,
+        xres,
+        ck: decode_array(ck)?,
+        ik: decode_array(ik)?,
+    })
+}
+
+fn parse_milenage(values: &[&str]) -> Result<(Milenage, [u8; 2], u64), String> {
+    let (mut ki, mut opc, mut op, mut amf, mut sqn) = (None, None, None, DEFAULT_AMF, 0);
+    for value in values {
+        let (key, hex) = value
+            .split_once('=')
+            .ok_or_else(|| format!("Expected 'key=hex', got '{}'", value))?;
+        match key {
+            "ki" => ki = Some(decode_array(hex)?),
+            "opc" => opc = Some(decode_array(hex)?),
+            "op" => op = Some(decode_array(hex)?),
+            "amf" => amf = decode_array(hex)?,
+            "sqn" => sqn = u64::from_str_radix(hex, 16).map_err(|e| format!("Invalid sqn '{}': {}", hex, e))?,
+            other => return Err(format!("Unknown milenage option '{}'", other)),
+        }
+    }
+
+    let ki = ki.ok_or("Missing ki")?;
+    let milenage = match (opc, op) {
+        (Some(opc), _) => Milenage::new(ki, opc),
+        (None, Some(op)) => Milenage::with_op(ki, op),
+        (None, None) => return Err("Missing opc or op".to_string()),
+    };
+    Ok((milenage, amf, sqn))
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Errors from packet decoding, dictionary loading and the listeners.
+#[derive(Debug)]
+pub enum RadiusError {
+    /// The datagram is shorter than the 20-byte RADIUS header.
+    TruncatedHeader { len: usize },
+    /// An attribute header starts at `offset` but the packet ends before it.
+    TruncatedAttribute { offset: usize },
+    /// The attribute at `offset` has a Length below 2 or past the packet end.
+    BadAttributeLength { offset: usize, len: usize },
+    /// The header's Length field does not fit the received datagram.
+    LengthMismatch { header: usize, actual: usize },
+    /// The header's Length field is below 20 or above 4096.
+    BadPacketLength { len: usize },
+    /// An encoded packet would exceed the 4096-byte maximum.
+    PacketTooLarge { len: usize },
+    /// An attribute value is longer than the 253 bytes one attribute holds.
+    AttributeTooLong { typ: u8, len: usize },
+    /// An attribute value could not be decoded.
+    InvalidAttribute { typ: u8, reason: String },
+    /// A value's wire length does not match its dictionary type.
+    ValueLength {
+        data_type: &'static str,
+        expected: String,
+        actual: usize,
+    },
+    /// The dictionary has no attribute of this name.
+    UnknownAttribute(String),
+    /// A server-internal attribute (`FLAGS internal`, or a code above 255)
+    /// was used in a packet.
+    InternalAttribute { name: String, code: u32 },
+    /// An encrypted attribute was read or written without the shared secret.
+    SecretRequired(String),
+    /// A struct value was encoded without one of its members.
+    MissingMember { attribute: String, member: String },
+    /// A struct member was read or written on its own; use the tree of the
+    /// struct instead.
+    StructMember(String),
+    /// A value of one type was stored in an attribute of another.
+    TypeMismatch {
+        attribute: String,
+        expected: String,
+        found: &'static str,
+    },
+    /// A dictionary line could not be parsed. `file` is `None` for
+    /// dictionaries parsed from a string.
+    DictionaryParse {
+        file: Option<PathBuf>,
+        line: usize,
+        message: String,
+    },
+    /// A definition conflicts with the attributes already in the dictionary.
+    InvalidDefinition { attribute: String, reason: String },
+    /// A line of a `clients.conf` or authentication vector file could not
+    /// be parsed. `file` is `None` when parsed from a string.
+    ConfigParse {
+        file: Option<PathBuf>,
+        line: usize,
+        message: String,
+    },
+    /// An EAP packet could not be decoded.
+    InvalidEap(String),
+    /// An EAP method could not go on, e.g. no authentication vectors for
+    /// the peer.
+    Eap(String),
+    /// A setting that cannot be used: a bad key, certificate or network.
+    Config(String),
+    /// A Request or Response Authenticator did not match the shared secret.
+    AuthenticatorMismatch,
+    Io { path: Option<PathBuf>, source: io::Error },
+    /// A request handler gave up on a packet.
+    Handler(String),
+    /// A request handler chose not to answer, e.g. a stale EAP-Response.
+    /// The listener drops the request instead of rejecting it.
+    Discarded(String),
+}
+
+impl fmt::Display for RadiusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RadiusError::TruncatedHeader { len } => write!(f, "packet too short: {len} bytes"),
+            RadiusError::TruncatedAttribute { offset } => write!(f, "truncated attribute header at offset {offset}"),
+            RadiusError::BadAttributeLength { offset, len } => {
+                write!(f, "invalid attribute length at offset {offset}: len = {len}")
+            }
+            RadiusError::LengthMismatch { header, actual } => {
+                write!(f, "length mismatch: header says {header}, but got {actual} bytes")
+            }
+            RadiusError::BadPacketLength { len } => write!(f, "invalid packet length {len}"),
+            RadiusError::PacketTooLarge { len } => write!(f, "packet of {len} bytes exceeds the 4096-byte maximum"),
+            RadiusError::AttributeTooLong { typ, len } => {
+                write!(f, "attribute {typ} value of {len} bytes exceeds the 253-byte maximum")
+            }
+            RadiusError::InvalidAttribute { typ, reason } => write!(f, "invalid attribute {typ}: {reason}"),
+            RadiusError::ValueLength { data_type, expected, actual } => {
+                write!(f, "{data_type} value must be {expected} bytes, got {actual}")
+            }
+            RadiusError::UnknownAttribute(name) => write!(f, "unknown attribute '{name}'"),
+            RadiusError::InternalAttribute { name, code } => {
+                write!(f, "{name} ({code}) is internal to the server and cannot be sent")
+            }
+            RadiusError::SecretRequired(name) => write!(f, "{name} is encrypted and needs the shared secret"),
+            RadiusError::MissingMember { attribute, member } => write!(f, "{attribute} needs a value for {member}"),
+            RadiusError::StructMember(name) => write!(f, "{name} is a struct member, use get_tree/add_tree"),
+            RadiusError::TypeMismatch { attribute, expected, found } => {
+                write!(f, "{attribute} is of type {expected}, not {found}")
+            }
+            RadiusError::DictionaryParse { file: Some(file), line, message } => {
+                write!(f, "{message} in {} at line {line}", file.display())
+            }
+            RadiusError::DictionaryParse { file: None, line, message } => write!(f, "{message} at line {line}"),
+            RadiusError::InvalidDefinition { attribute, reason } => write!(f, "invalid definition of {attribute}: {reason}"),
+            RadiusError::ConfigParse { file: Some(file), line, message } => {
+                write!(f, "{message} in {} at line {line}", file.display())
+            }
+            RadiusError::ConfigParse { file: None, line, message } => write!(f, "{message} at line {line}"),
+            RadiusError::InvalidEap(reason) => write!(f, "invalid EAP packet: {reason}"),
+            RadiusError::Eap(reason) => write!(f, "EAP failed: {reason}"),
+            RadiusError::Config(reason) => write!(f, "invalid configuration: {reason}"),
+            RadiusError::AuthenticatorMismatch => write!(f, "authenticator mismatch"),
+            RadiusError::Io { path: Some(path), source } => write!(f, "{}: {source}", path.display()),
+            RadiusError::Io { path: None, source } => write!(f, "I/O error: {source}"),
+            RadiusError::Handler(message) => write!(f, "handler failed: {message}"),
+            RadiusError::Discarded(reason) => write!(f, "discarded: {reason}"),
+        }
+    }
+}
+
+impl RadiusError {
+    /// Fills in the file name of a parse error from a file read as a string.
+    pub(crate) fn in_file(self, path: &Path) -> Self {
+        match self {
+            RadiusError::DictionaryParse { file: None, line, message } => RadiusError::DictionaryParse {
+                file: Some(path.to_path_buf()),
+                line,
+                message,
+            },
+            RadiusError::ConfigParse { file: None, line, message } => RadiusError::ConfigParse {
+                file: Some(path.to_path_buf()),
+                line,
+                message,
+            },
+            e => e,
+        }
+    }
+}
+
+impl std::error::Error for RadiusError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RadiusError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RadiusError {
+    fn from(source: io::Error) -> Self {
+        RadiusError::Io { path: None, source }
+    }
+}
+
+/// Lets handlers keep using `String` errors (and `?` on them).
+impl From<String> for RadiusError {
+    fn from(message: String) -> Self {
+        RadiusError::Handler(message)
+    }
+}
+
+impl From<&str> for RadiusError {
+    fn from(message: &str) -> Self {
+        RadiusError::Handler(message.to_string())
+    }
+}
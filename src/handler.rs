@@ -1,32 +1,49 @@
 use std::sync::Arc;
 use crate::{
-    dictionary::Dictionary,
-    packet::{RadiusAttribute, RadiusPacket},
+    client::{ClientRegistry, RadiusClient},
+    dictionary::{AttributeKey, Dictionary},
+    packet::{
+        chap_response, decode_vendor_specific, is_extended_type, RadiusAttribute, RadiusCode, RadiusPacket, RadiusPacketRef, ATTR_CHAP_CHALLENGE, ATTR_CHAP_PASSWORD,
+        ATTR_MESSAGE_AUTHENTICATOR, ATTR_USER_PASSWORD, ATTR_VENDOR_SPECIFIC, MAX_PACKET_LEN,
+    },
+    DropReason, RadiusError, ServerOptions,
 };
-use md5;
+use std::sync::atomic::Ordering;
 use tokio::net::UdpSocket;
+use tokio::sync::Semaphore;
 
 /// Builds a RADIUS response packet with the proper Response Authenticator.
+///
+/// Access-Accept, Access-Reject and Access-Challenge replies always get a
+/// Message-Authenticator (RFC 3579 §3.2), placed first in the attribute list
+/// as recommended after BlastRADIUS.
 pub fn build_response_with_auth(
     mut packet: RadiusPacket,
     request_authenticator: [u8; 16],
     secret: &str,
-) -> RadiusPacket {
-    let mut buf = Vec::new();
-    buf.push(packet.code);
-    buf.push(packet.identifier);
-    buf.extend_from_slice(&[0x00, 0x00]); // placeholder for length
-    buf.extend_from_slice(&request_authenticator);
-
-    for attr in &packet.attributes {
-        buf.push(attr.typ);
-        buf.push(attr.len);
-        buf.extend_from_slice(&attr.value);
+) -> Result<RadiusPacket, RadiusError> {
+    let signed = matches!(
+        packet.code,
+        RadiusCode::AccessAccept | RadiusCode::AccessReject | RadiusCode::AccessChallenge
+    );
+    if signed {
+        packet.attributes.retain(|a| a.typ != ATTR_MESSAGE_AUTHENTICATOR);
+        packet.attributes.insert(0, RadiusAttribute::fixed(ATTR_MESSAGE_AUTHENTICATOR, vec![0u8; 16]));
     }
 
+    // The Response Authenticator is computed over the packet with the
+    // Request Authenticator in its place.
+    packet.authenticator = request_authenticator;
+    let mut buf = packet.to_bytes()?;
     let length = buf.len() as u16;
-    buf[2] = (length >> 8) as u8;
-    buf[3] = (length & 0xFF) as u8;
+
+    if signed {
+        // Computed with the Request Authenticator in place and the
+        // Message-Authenticator value zeroed, which is what `buf` holds now.
+        let mac = hmac_md5(secret.as_bytes(), &buf);
+        buf[22..38].copy_from_slice(&mac);
+        packet.attributes[0].value = mac.to_vec();
+    }
 
     buf.extend_from_slice(secret.as_bytes());
 
@@ -37,45 +54,150 @@ pub fn build_response_with_auth(
     packet.length = length;
     packet.authenticator = authenticator;
 
-    packet
+    Ok(packet)
+}
+
+/// HMAC-MD5 (RFC 2104), as used by Message-Authenticator.
+pub fn hmac_md5(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..16].copy_from_slice(&md5::compute(key).0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Vec::with_capacity(64 + data.len());
+    inner.extend(block.iter().map(|b| b ^ 0x36));
+    inner.extend_from_slice(data);
+    let inner_hash = md5::compute(&inner);
+
+    let mut outer = Vec::with_capacity(64 + 16);
+    outer.extend(block.iter().map(|b| b ^ 0x5c));
+    outer.extend_from_slice(&inner_hash.0);
+    md5::compute(&outer).0
+}
+
+/// Checks the Message-Authenticator of a received Access-Request (or
+/// Status-Server) packet in its raw wire form.
+///
+/// Returns `None` when the attribute is absent, otherwise whether it matches.
+pub fn verify_message_authenticator(raw: &[u8], secret: &str) -> Option<bool> {
+    if raw.len() < 20 {
+        return None;
+    }
+    let length = (u16::from_be_bytes([raw[2], raw[3]]) as usize).min(raw.len());
+
+    let mut i = 20;
+    let mut found = None;
+    while i + 2 <= length {
+        let typ = raw[i];
+        let len = raw[i + 1] as usize;
+        if len < 2 || i + len > length {
+            return Some(false);
+        }
+        if typ == ATTR_MESSAGE_AUTHENTICATOR {
+            if len != 18 {
+                return Some(false);
+            }
+            found = Some(i + 2);
+            break;
+        }
+        i += len;
+    }
+
+    let offset = found?;
+    let mut data = raw[..length].to_vec();
+    let received: [u8; 16] = data[offset..offset + 16].try_into().unwrap();
+    data[offset..offset + 16].fill(0);
+
+    let expected = hmac_md5(secret.as_bytes(), &data);
+    Some(constant_time_eq(&expected, &received))
+}
+
+/// Adds (or refreshes) the Message-Authenticator on an outgoing
+/// Access-Request, using the packet's own Request Authenticator.
+pub fn sign_access_request(mut packet: RadiusPacket, secret: &str) -> Result<RadiusPacket, RadiusError> {
+    packet.attributes.retain(|a| a.typ != ATTR_MESSAGE_AUTHENTICATOR);
+    packet.attributes.insert(0, RadiusAttribute::fixed(ATTR_MESSAGE_AUTHENTICATOR, vec![0u8; 16]));
+
+    let mac = hmac_md5(secret.as_bytes(), &packet.to_bytes()?);
+    packet.attributes[0].value = mac.to_vec();
+    Ok(packet)
+}
+
+/// Checks the CHAP-Password of an Access-Request against the user's known
+/// cleartext password. The challenge is taken from CHAP-Challenge, or from
+/// the Request Authenticator when the NAS did not send one.
+pub fn verify_chap(packet: &RadiusPacket, known_cleartext: &str) -> bool {
+    let Some(chap) = packet.attributes.iter().find(|a| a.typ == ATTR_CHAP_PASSWORD) else {
+        return false;
+    };
+    if chap.value.len() != 17 {
+        return false;
+    }
+
+    let challenge = packet
+        .attributes
+        .iter()
+        .find(|a| a.typ == ATTR_CHAP_CHALLENGE)
+        .map(|a| a.value.as_slice())
+        .unwrap_or(&packet.authenticator);
+
+    let expected = chap_response(chap.value[0], known_cleartext.as_bytes(), challenge);
+    constant_time_eq(&expected, &chap.value[1..])
 }
 
-/// Handles an incoming RADIUS packet and returns a response packet.
-pub fn handle(packet: RadiusPacket, dict: Arc<Dictionary>) -> Result<RadiusPacket, String> {
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Handles an incoming RADIUS packet and returns a response packet signed
+/// with the client's shared `secret`.
+pub fn handle(packet: RadiusPacket, dict: Arc<Dictionary>, secret: &str) -> Result<RadiusPacket, RadiusError> {
     println!("🔍 Handling RADIUS packet ID: {}", packet.identifier);
 
     for attr in &packet.attributes {
-        let attr_code = attr.typ as u32;
-        match dict.attributes.get(&attr_code) {
-            Some(def) => {
-                let name = &def.name;
-                match std::str::from_utf8(&attr.value) {
-                    Ok(s) => println!("→ {}: {}", name, s.trim()),
-                    Err(_) => println!("→ {}: {:?}", name, attr.value),
-                }
-            }
+        if attr.typ == ATTR_USER_PASSWORD {
+            println!("→ User-Password: <hidden>");
+            continue;
+        }
+        if is_extended_type(attr.typ) {
+            continue;
+        }
+        if attr.typ == ATTR_VENDOR_SPECIFIC {
+            println!("→ Vendor-Specific: {}", decode_vendor_specific(&attr.value, Some(&dict)));
+            continue;
+        }
+        let key = AttributeKey::Standard(attr.typ as u32);
+        match dict.get(key) {
+            Some(def) => println!("→ {}: {}", def.name, dict.format_value(key, &attr.value)),
             None => println!("→ Unknown Attribute Type {}: {:?}", attr.typ, attr.value),
         }
     }
 
-    let mut attributes = vec![
-        RadiusAttribute::reply_message("Access granted via Rust RADIUS server."),
+    for attr in packet.extended_attributes()? {
+        let key = AttributeKey::Extended(attr.typ, attr.ext_type);
+        match dict.get(key) {
+            Some(def) => println!("→ {}: {}", def.name, dict.format_value(key, &attr.value)),
+            None => println!("→ Unknown Attribute Type {}.{}: {:?}", attr.typ, attr.ext_type, attr.value),
+        }
+    }
+
+    let mut attributes = RadiusAttribute::reply_messages("Access granted via Rust RADIUS server.");
+    attributes.extend([
         RadiusAttribute::session_timeout(3600),
         RadiusAttribute::idle_timeout(300),
         RadiusAttribute::wispr_bandwidth_max_up(512_000),
         RadiusAttribute::wispr_bandwidth_max_down(1_000_000),
-    ];
+    ]);
 
     // Optional: Echo back username if present
-    if let Some(user_attr) = packet.attributes.iter().find(|a| a.typ == 1) {
-        if let Ok(username) = std::str::from_utf8(&user_attr.value) {
-            attributes.push(RadiusAttribute::user_name(username));
-        }
+    if let Some(username) = packet.username() {
+        attributes.push(RadiusAttribute::user_name(&username)?);
     }
 
     let accept = RadiusPacket::access_accept(packet.identifier, attributes);
-    let response = build_response_with_auth(accept, packet.authenticator, "test123");
-    Ok(response)
+    build_response_with_auth(accept, packet.authenticator, secret)
 }
 
 
@@ -102,7 +224,7 @@ data.extend_from_slice(&[0u8; 16]); // ✅ 16 zero bytes as per RFC
 
 /// Builds an Accounting-Response packet
 pub fn build_accounting_response(identifier: u8, request_auth: [u8; 16], secret: &str) -> Vec<u8> {
-    let mut buf = vec![5, identifier, 0x00, 0x14]; // Code=5, length=20
+    let mut buf = vec![RadiusCode::AccountingResponse.into(), identifier, 0x00, 0x14]; // length=20
     let mut temp = buf.clone();
     temp.extend_from_slice(&request_auth);
     temp.extend_from_slice(secret.as_bytes());
@@ -112,43 +234,190 @@ pub fn build_accounting_response(identifier: u8, request_auth: [u8; 16], secret:
     buf
 }
 
-/// Serves incoming Accounting-Request packets and responds
+/// Serves incoming Accounting-Request packets and responds. Like
+/// [`serve_async`](crate::serve_async), the dictionary is accepted but unused.
 pub async fn serve_accounting_async<F, Fut>(
     addr: &str,
-    dict: Arc<Dictionary>,
+    _dict: Arc<Dictionary>,
     secret: &str,
     handler: F,
-) -> Result<(), Box<dyn std::error::Error>>
+) -> Result<(), RadiusError>
 where
     F: Fn(RadiusPacket) -> Fut + Send + Sync + 'static,
-    Fut: std::future::Future<Output = Result<(), String>> + Send,
+    Fut: std::future::Future<Output = Result<(), RadiusError>> + Send + 'static,
 {
-    let socket = UdpSocket::bind(addr).await?;
+    serve_accounting_async_with_options(addr, secret, ServerOptions::default(), handler).await
+}
+
+/// Same as [`serve_accounting_async`], but each request is handled on its own
+/// task, bounded by `options.max_in_flight`.
+pub async fn serve_accounting_async_with_options<F, Fut>(
+    addr: &str,
+    secret: &str,
+    options: ServerOptions,
+    handler: F,
+) -> Result<(), RadiusError>
+where
+    F: Fn(RadiusPacket) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<(), RadiusError>> + Send + 'static,
+{
+    let clients = Arc::new(ClientRegistry::catch_all(secret));
+    serve_accounting_with_clients(addr, clients, options, move |packet, _client| handler(packet)).await
+}
+
+/// Serves Accounting-Requests from the NASes in `clients`, verifying each
+/// against that client's secret. Packets from unknown addresses are dropped.
+pub async fn serve_accounting_with_clients<F, Fut>(
+    addr: &str,
+    clients: Arc<ClientRegistry>,
+    options: ServerOptions,
+    handler: F,
+) -> Result<(), RadiusError>
+where
+    F: Fn(RadiusPacket, Arc<RadiusClient>) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<(), RadiusError>> + Send + 'static,
+{
+    let socket = Arc::new(UdpSocket::bind(addr).await?);
     println!("📡 Accounting server listening on {addr}");
 
-    let mut buf = [0u8; 1024];
+    let handler = Arc::new(handler);
+    let slots = Arc::new(Semaphore::new(options.max_in_flight.max(1)));
+    let options = Arc::new(options);
+
+    let mut buf = [0u8; MAX_PACKET_LEN];
     loop {
-        let (len, src) = socket.recv_from(&mut buf).await?;
+        let permit = slots.clone().acquire_owned().await.expect("semaphore is never closed");
+        let (len, src) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                options.recv_failed(e);
+                continue;
+            }
+        };
+        options.stats.received.fetch_add(1, Ordering::Relaxed);
         let raw_packet = &buf[..len];
 
-        let packet = match RadiusPacket::from_bytes(raw_packet) {
+        let Some(client) = clients.lookup(src.ip()) else {
+            options.drop_packet(src, DropReason::UnknownClient);
+            continue;
+        };
+
+        let raw = match RadiusPacketRef::parse(raw_packet) {
             Ok(p) => p,
             Err(e) => {
-                eprintln!("❌ Failed to parse: {e}");
+                options.drop_packet(src, DropReason::Malformed(e));
                 continue;
             }
         };
 
-        if !verify_accounting_request_authenticator(raw_packet, secret, packet.authenticator) {
-            eprintln!("🚫 Invalid accounting authenticator.");
+        if raw.code() != RadiusCode::AccountingRequest {
+            options.drop_packet(src, DropReason::UnexpectedCode(raw.code()));
             continue;
         }
 
-        if let Err(e) = handler(packet.clone()).await {
-            eprintln!("⚠️  Handler error: {e}");
+        if !verify_accounting_request_authenticator(raw.as_bytes(), &client.secret, raw.authenticator()) {
+            options.drop_packet(src, DropReason::BadAuthenticator);
+            continue;
         }
 
-        let response = build_accounting_response(packet.identifier, packet.authenticator, secret);
-        socket.send_to(&response, src).await?;
+        let packet = raw.to_packet();
+        let socket = socket.clone();
+        let handler = handler.clone();
+        let options = options.clone();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            let identifier = packet.identifier;
+            let request_auth = packet.authenticator;
+            let secret = client.secret.clone();
+
+            if let Err(e) = handler(packet, client).await {
+                eprintln!("⚠️  Handler error: {e}");
+            }
+
+            let response = build_accounting_response(identifier, request_auth, &secret);
+            if let Err(e) = socket.send_to(&response, src).await {
+                options.drop_packet(src, DropReason::SendFailed(e.into()));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::ATTR_USER_NAME;
+
+    fn request(attributes: Vec<RadiusAttribute>) -> RadiusPacket {
+        RadiusPacket {
+            code: RadiusCode::AccessRequest,
+            identifier: 1,
+            length: 0,
+            authenticator: [0x5a; 16],
+            attributes,
+        }
+    }
+
+    #[test]
+    fn hmac_md5_matches_rfc_2104_vector() {
+        let mac = hmac_md5(&[0x0b; 16], b"Hi There");
+        assert_eq!(mac, 0x9294727a3638bb1c13f48ef8158bfc9d_u128.to_be_bytes());
+    }
+
+    #[test]
+    fn signed_request_verifies_until_tampered() {
+        let packet = request(vec![RadiusAttribute::new(ATTR_USER_NAME, b"alice".to_vec()).unwrap()]);
+        assert_eq!(verify_message_authenticator(&packet.to_bytes().unwrap(), "secret"), None);
+
+        let mut raw = sign_access_request(packet, "secret").unwrap().to_bytes().unwrap();
+        assert_eq!(verify_message_authenticator(&raw, "secret"), Some(true));
+        assert_eq!(verify_message_authenticator(&raw, "other"), Some(false));
+
+        *raw.last_mut().unwrap() ^= 1;
+        assert_eq!(verify_message_authenticator(&raw, "secret"), Some(false));
+    }
+
+    #[test]
+    fn signs_responses_over_the_request_authenticator() {
+        let reply = RadiusPacket {
+            code: RadiusCode::AccessAccept,
+            identifier: 1,
+            length: 0,
+            authenticator: [0; 16],
+            attributes: Vec::new(),
+        };
+        let reply = build_response_with_auth(reply, [0x5a; 16], "secret").unwrap();
+        assert_eq!(reply.attributes[0].typ, ATTR_MESSAGE_AUTHENTICATOR);
+
+        let mut raw = reply.to_bytes().unwrap();
+        raw[4..20].copy_from_slice(&[0x5a; 16]);
+        assert_eq!(verify_message_authenticator(&raw, "secret"), Some(true));
+    }
+
+    #[test]
+    fn chap_response_matches_md5_of_id_password_challenge() {
+        let challenge: Vec<u8> = (0..16).collect();
+        let response = chap_response(7, b"hunter2", &challenge);
+        assert_eq!(response, 0x624f9dcb5f700137837398d391a7b1a8_u128.to_be_bytes());
+    }
+
+    #[test]
+    fn verifies_chap_with_chap_challenge() {
+        let challenge: Vec<u8> = (0..16).collect();
+        let packet = request(vec![
+            RadiusAttribute::chap_password(7, "hunter2", &challenge),
+            RadiusAttribute::chap_challenge(&challenge).unwrap(),
+        ]);
+        assert!(verify_chap(&packet, "hunter2"));
+        assert!(!verify_chap(&packet, "hunter3"));
+    }
+
+    #[test]
+    fn verifies_chap_against_request_authenticator() {
+        let mut value = vec![0x2a];
+        value.extend(0x064598c2332c0cbb6191c5110525f93d_u128.to_be_bytes());
+        let packet = request(vec![RadiusAttribute::new(ATTR_CHAP_PASSWORD, value).unwrap()]);
+        assert!(verify_chap(&packet, "hunter2"));
+        assert!(!verify_chap(&request(Vec::new()), "hunter2"));
     }
 }
@@ -0,0 +1,19 @@
+//! Hex decoding shared by the credential and vector file parsers.
+
+/// Decodes a string of hex digit pairs.
+pub(crate) fn decode(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("Invalid hex '{}'", hex));
+    }
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+/// Decodes a hex string of exactly `N` bytes.
+pub(crate) fn decode_array<const N: usize>(hex: &str) -> Result<[u8; N], String> {
+    decode(hex)?
+        .try_into()
+        .map_err(|_| format!("Expected {} hex bytes, got '{}'", N, hex))
+}
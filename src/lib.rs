@@ -1,40 +1,330 @@
 pub mod packet;
 pub mod dictionary;
 pub mod handler;
+pub mod client;
+pub mod mschap;
+pub mod eap;
+pub mod error;
+pub mod value;
+pub mod upgrade;
+mod hex;
+use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::net::UdpSocket;
-use crate::{dictionary::Dictionary, packet::RadiusPacket, handler::build_response_with_auth};
+use tokio::sync::Semaphore;
+use crate::{
+    client::{ClientRegistry, RadiusClient},
+    dictionary::Dictionary,
+    handler::{build_response_with_auth, verify_message_authenticator},
+    packet::{RadiusCode, RadiusPacket, RadiusPacketRef, ATTR_EAP_MESSAGE, MAX_PACKET_LEN},
+};
 
+pub use error::RadiusError;
+
+/// Default number of requests a listener will handle at the same time.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 256;
+
+/// Why a listener discarded a datagram instead of answering it.
+#[derive(Debug)]
+pub enum DropReason {
+    /// The source address does not belong to any configured client.
+    UnknownClient,
+    /// The datagram could not be parsed as a RADIUS packet.
+    Malformed(RadiusError),
+    /// The Request Authenticator did not match the shared secret.
+    BadAuthenticator,
+    /// The Message-Authenticator attribute did not match the shared secret.
+    BadMessageAuthenticator,
+    /// The client policy or an EAP-Message required a Message-Authenticator
+    /// and none was present.
+    MissingMessageAuthenticator,
+    /// The packet type is not served on this listener, e.g. an
+    /// Accounting-Request sent to the authentication port.
+    UnexpectedCode(RadiusCode),
+    /// The reply could not be encoded (e.g. it exceeds 4096 bytes) or
+    /// written to the socket.
+    SendFailed(RadiusError),
+    /// The handler returned [`RadiusError::Discarded`].
+    Discarded(String),
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DropReason::UnknownClient => write!(f, "unknown client"),
+            DropReason::Malformed(e) => write!(f, "malformed packet: {e}"),
+            DropReason::BadAuthenticator => write!(f, "invalid request authenticator"),
+            DropReason::BadMessageAuthenticator => write!(f, "invalid Message-Authenticator"),
+            DropReason::MissingMessageAuthenticator => write!(f, "missing Message-Authenticator"),
+            DropReason::UnexpectedCode(code) => write!(f, "unexpected {code} on this port"),
+            DropReason::SendFailed(e) => write!(f, "failed to send reply: {e}"),
+            DropReason::Discarded(reason) => write!(f, "discarded by handler: {reason}"),
+        }
+    }
+}
+
+/// Counters updated by a running listener.
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    pub received: AtomicU64,
+    pub unknown_client: AtomicU64,
+    pub malformed: AtomicU64,
+    pub bad_authenticator: AtomicU64,
+    pub bad_message_authenticator: AtomicU64,
+    pub unexpected_code: AtomicU64,
+    pub send_failed: AtomicU64,
+    pub discarded: AtomicU64,
+    /// Errors reading from the socket, e.g. `ConnectionReset` after an
+    /// ICMP port-unreachable on Windows. The listener keeps running.
+    pub recv_failed: AtomicU64,
+}
+
+impl ServerStats {
+    /// Total number of datagrams dropped for any reason.
+    pub fn dropped(&self) -> u64 {
+        self.unknown_client.load(Ordering::Relaxed)
+            + self.malformed.load(Ordering::Relaxed)
+            + self.bad_authenticator.load(Ordering::Relaxed)
+            + self.bad_message_authenticator.load(Ordering::Relaxed)
+            + self.unexpected_code.load(Ordering::Relaxed)
+            + self.send_failed.load(Ordering::Relaxed)
+            + self.discarded.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, reason: &DropReason) {
+        let counter = match reason {
+            DropReason::UnknownClient => &self.unknown_client,
+            DropReason::Malformed(_) => &self.malformed,
+            DropReason::BadAuthenticator => &self.bad_authenticator,
+            DropReason::BadMessageAuthenticator | DropReason::MissingMessageAuthenticator => {
+                &self.bad_message_authenticator
+            }
+            DropReason::UnexpectedCode(_) => &self.unexpected_code,
+            DropReason::SendFailed(_) => &self.send_failed,
+            DropReason::Discarded(_) => &self.discarded,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Callback invoked for every dropped datagram with its source address.
+pub type DropHook = Arc<dyn Fn(SocketAddr, &DropReason) + Send + Sync>;
+
+/// Runtime settings shared by the access and accounting listeners.
+#[derive(Clone)]
+pub struct ServerOptions {
+    /// Upper bound on handler tasks running concurrently. When every slot is
+    /// taken the listener stops reading from the socket until one frees up,
+    /// so excess load queues in the kernel buffer instead of in memory.
+    pub max_in_flight: usize,
+    /// Counters for received and dropped packets. Keep a clone of the `Arc`
+    /// to read them while the server is running.
+    pub stats: Arc<ServerStats>,
+    /// Optional callback notified whenever a packet is dropped.
+    pub on_drop: Option<DropHook>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            stats: Arc::new(ServerStats::default()),
+            on_drop: None,
+        }
+    }
+}
+
+impl fmt::Debug for ServerOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerOptions")
+            .field("max_in_flight", &self.max_in_flight)
+            .field("stats", &self.stats)
+            .field("on_drop", &self.on_drop.is_some())
+            .finish()
+    }
+}
+
+impl ServerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_in_flight(mut self, limit: usize) -> Self {
+        self.max_in_flight = limit.max(1);
+        self
+    }
+
+    pub fn stats(mut self, stats: Arc<ServerStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    pub fn on_drop<H>(mut self, hook: H) -> Self
+    where
+        H: Fn(SocketAddr, &DropReason) + Send + Sync + 'static,
+    {
+        self.on_drop = Some(Arc::new(hook));
+        self
+    }
+
+    /// Logs, counts and reports a dropped datagram.
+    pub(crate) fn drop_packet(&self, src: SocketAddr, reason: DropReason) {
+        eprintln!("🚫 Dropping packet from {src}: {reason}");
+        self.stats.record(&reason);
+        if let Some(hook) = &self.on_drop {
+            hook(src, &reason);
+        }
+    }
+
+    /// Logs and counts a failed socket read.
+    pub(crate) fn recv_failed(&self, err: std::io::Error) {
+        eprintln!("⚠️ Failed to receive a datagram: {err}");
+        self.stats.recv_failed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Serves Access-Requests from any address with one shared secret. The
+/// dictionary is not used by the listener; it is kept for compatibility,
+/// and handlers that decode attributes capture their own `Arc`.
 pub async fn serve_async<F, Fut>(
     addr: &str,
-    dict: Arc<Dictionary>,
+    _dict: Arc<Dictionary>,
     secret: &str,
     handler: F,
-) -> Result<(), Box<dyn std::error::Error>>
+) -> Result<(), RadiusError>
 where
     F: Fn(RadiusPacket) -> Fut + Send + Sync + 'static,
-    Fut: std::future::Future<Output = Result<RadiusPacket, String>> + Send,
+    Fut: std::future::Future<Output = Result<RadiusPacket, RadiusError>> + Send + 'static,
 {
-    use tokio::net::UdpSocket;
+    serve_async_with_options(addr, secret, ServerOptions::default(), handler).await
+}
 
-    let socket = UdpSocket::bind(addr).await?;
-    let mut buf = [0u8; 1024];
+/// Same as [`serve_async`], but each request is handled on its own task,
+/// bounded by `options.max_in_flight`.
+pub async fn serve_async_with_options<F, Fut>(
+    addr: &str,
+    secret: &str,
+    options: ServerOptions,
+    handler: F,
+) -> Result<(), RadiusError>
+where
+    F: Fn(RadiusPacket) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<RadiusPacket, RadiusError>> + Send + 'static,
+{
+    let clients = Arc::new(ClientRegistry::catch_all(secret));
+    serve_with_clients(addr, clients, options, move |packet, _client| handler(packet)).await
+}
+
+/// Serves Access-Requests from the NASes in `clients`, each with its own
+/// shared secret. Packets from addresses not in the registry are dropped.
+/// The handler receives the matched client alongside the request.
+///
+/// A Message-Authenticator, when present, must be valid. It is mandatory
+/// for clients with `require_message_authenticator` set and for any
+/// request carrying an EAP-Message.
+///
+/// Status-Server packets (RFC 5997) are answered with an Access-Accept
+/// without calling the handler; packets of any other non-access type are
+/// dropped.
+pub async fn serve_with_clients<F, Fut>(
+    addr: &str,
+    clients: Arc<ClientRegistry>,
+    options: ServerOptions,
+    handler: F,
+) -> Result<(), RadiusError>
+where
+    F: Fn(RadiusPacket, Arc<RadiusClient>) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<RadiusPacket, RadiusError>> + Send + 'static,
+{
+    let socket = Arc::new(UdpSocket::bind(addr).await?);
+    let handler = Arc::new(handler);
+    let slots = Arc::new(Semaphore::new(options.max_in_flight.max(1)));
+    let options = Arc::new(options);
+    let mut buf = [0u8; MAX_PACKET_LEN];
 
     loop {
-        let (len, src) = socket.recv_from(&mut buf).await?;
-        let req = RadiusPacket::from_bytes(&buf[..len])?;
+        let permit = slots.clone().acquire_owned().await.expect("semaphore is never closed");
+        let (len, src) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                options.recv_failed(e);
+                continue;
+            }
+        };
+        options.stats.received.fetch_add(1, Ordering::Relaxed);
 
-        let fut = handler(req.clone());
-        let response_result = fut.await;
+        let Some(client) = clients.lookup(src.ip()) else {
+            options.drop_packet(src, DropReason::UnknownClient);
+            continue;
+        };
 
-        let response = match response_result {
-            Ok(reply_packet) => build_response_with_auth(reply_packet, req.authenticator, secret),
-            Err(err) => {
-                eprintln!("❌ Error from handler: {err}");
-                build_response_with_auth(req.reply_reject("Internal Error"), req.authenticator, secret)
+        // Checks run on the borrowed view; the owned packet is only built
+        // for requests that get to the handler.
+        let raw = match RadiusPacketRef::parse(&buf[..len]) {
+            Ok(p) => p,
+            Err(e) => {
+                options.drop_packet(src, DropReason::Malformed(e));
+                continue;
             }
         };
 
-        socket.send_to(&response.to_bytes(), src).await?;
+        if !matches!(raw.code(), RadiusCode::AccessRequest | RadiusCode::StatusServer) {
+            options.drop_packet(src, DropReason::UnexpectedCode(raw.code()));
+            continue;
+        }
+
+        match verify_message_authenticator(raw.as_bytes(), &client.secret) {
+            Some(true) => {}
+            Some(false) => {
+                options.drop_packet(src, DropReason::BadMessageAuthenticator);
+                continue;
+            }
+            None => {
+                let has_eap = raw.find(ATTR_EAP_MESSAGE).is_some();
+                let status = raw.code() == RadiusCode::StatusServer;
+                if client.requires_message_authenticator() || has_eap || status {
+                    options.drop_packet(src, DropReason::MissingMessageAuthenticator);
+                    continue;
+                }
+            }
+        }
+
+        let (code, identifier, authenticator) = (raw.code(), raw.identifier(), raw.authenticator());
+        let req = raw.to_packet();
+        let socket = socket.clone();
+        let handler = handler.clone();
+        let options = options.clone();
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            let secret = client.secret.clone();
+
+            let reply = match code {
+                RadiusCode::StatusServer => Ok(req.reply_accept(Vec::new())),
+                _ => handler(req, client).await,
+            };
+            if let Err(RadiusError::Discarded(reason)) = reply {
+                options.drop_packet(src, DropReason::Discarded(reason));
+                return;
+            }
+            let response = reply
+                .and_then(|reply_packet| build_response_with_auth(reply_packet, authenticator, &secret))
+                .or_else(|err| {
+                    eprintln!("❌ Error from handler: {err}");
+                    let reject = RadiusPacket::access_reject(identifier, "Internal Error");
+                    build_response_with_auth(reject, authenticator, &secret)
+                })
+                .and_then(|response| response.to_bytes());
+
+            let sent = match response {
+                Ok(bytes) => socket.send_to(&bytes, src).await.map_err(RadiusError::from),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                options.drop_packet(src, DropReason::SendFailed(e));
+            }
+        });
     }
 }
@@ -1,9 +1,6 @@
-mod dictionary;
-mod packet;
-mod handler;
-
-use crate::dictionary::Dictionary;
-use crate::packet::RadiusPacket;
+use radius_server::dictionary::Dictionary;
+use radius_server::handler;
+use radius_server::packet::{RadiusPacket, MAX_PACKET_LEN};
 use tokio::net::UdpSocket;
 use std::sync::Arc;
 
@@ -14,19 +11,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let dictionary = Dictionary::load_from_file(dict_path)?;
     let dictionary = Arc::new(dictionary); // shareable across threads
 
+    // 🔑 Shared secret of the NASes talking to this server
+    let secret = std::env::var("RADIUS_SECRET").map_err(|_| "set RADIUS_SECRET to the shared secret")?;
+    let mut failed: u64 = 0;
+
     let socket = UdpSocket::bind("0.0.0.0:1812").await?;
-    let mut buf = [0u8; 1024];
+    let mut buf = [0u8; MAX_PACKET_LEN];
 
     loop {
-        let (len, addr) = socket.recv_from(&mut buf).await?;
+        let (len, addr) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("⚠️ Failed to receive a datagram: {e}");
+                continue;
+            }
+        };
 
         // ⛳️ Parse packet
-        let packet = RadiusPacket::from_bytes(&buf[..len])?;
+        let packet = match RadiusPacket::from_bytes(&buf[..len]) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("🚫 Dropping packet from {addr}: {e}");
+                continue;
+            }
+        };
 
         // ✅ Pass dictionary reference to handler
-        let response = handler::handle(packet, Arc::clone(&dictionary))?;
+        let response = match handler::handle(packet, Arc::clone(&dictionary), &secret).and_then(|r| r.to_bytes()) {
+            Ok(response) => response,
+            Err(e) => {
+                failed += 1;
+                eprintln!("❌ Error handling packet from {addr}: {e} ({failed} failed so far)");
+                continue;
+            }
+        };
 
         // 🔁 Send response
-        socket.send_to(&response.to_bytes(), addr).await?;
+        if let Err(e) = socket.send_to(&response, addr).await {
+            failed += 1;
+            eprintln!("⚠️ Failed to send reply to {addr}: {e} ({failed} failed so far)");
+        }
     }
 }
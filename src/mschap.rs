@@ -0,0 +1,423 @@
+//! MS-CHAPv2 (RFC 2759) over RADIUS using the Microsoft VSAs from RFC 2548,
+//! including MS-MPPE key derivation (RFC 3079).
+
+use crate::error::RadiusError;
+use crate::handler::constant_time_eq;
+use crate::hex;
+use crate::packet::{RadiusAttribute, RadiusPacket, ATTR_VENDOR_SPECIFIC};
+use des::cipher::{BlockEncrypt, KeyInit, generic_array::GenericArray};
+use des::Des;
+use md4::{Digest, Md4};
+use rand::RngCore;
+use sha1::Sha1;
+
+pub const VENDOR_MICROSOFT: u32 = 311;
+
+pub const MS_CHAP_ERROR: u8 = 2;
+pub const MS_MPPE_ENCRYPTION_POLICY: u8 = 7;
+pub const MS_MPPE_ENCRYPTION_TYPES: u8 = 8;
+pub const MS_CHAP_CHALLENGE: u8 = 11;
+pub const MS_MPPE_SEND_KEY: u8 = 16;
+pub const MS_MPPE_RECV_KEY: u8 = 17;
+pub const MS_CHAP2_RESPONSE: u8 = 25;
+pub const MS_CHAP2_SUCCESS: u8 = 26;
+
+const AUTH_MAGIC1: &[u8] = b"Magic server to client signing constant";
+const AUTH_MAGIC2: &[u8] = b"Pad to make it do more than one iteration";
+const MPPE_MAGIC1: &[u8] = b"This is the MPPE Master Key";
+const MPPE_MAGIC2: &[u8] =
+    b"On the client side, this is the send key; on the server side, it is the receive key.";
+const MPPE_MAGIC3: &[u8] =
+    b"On the client side, this is the receive key; on the server side, it is the send key.";
+
+/// A "known good" credential for a user, mirroring `Password.Cleartext` and
+/// `Password.NT` from `dictionary.freeradius.internal.password`.
+#[derive(Debug, Clone)]
+pub enum KnownPassword {
+    Cleartext(String),
+    Nt([u8; 16]),
+}
+
+impl KnownPassword {
+    /// Parses an NT hash written as 32 hex digits, optionally `0x`-prefixed.
+    pub fn nt_from_hex(hex: &str) -> Result<Self, RadiusError> {
+        let hex = hex.trim().trim_start_matches("0x");
+        hex::decode_array(hex).map(KnownPassword::Nt).map_err(RadiusError::Config)
+    }
+
+    pub fn nt_hash(&self) -> [u8; 16] {
+        match self {
+            KnownPassword::Cleartext(password) => nt_password_hash(password),
+            KnownPassword::Nt(hash) => *hash,
+        }
+    }
+}
+
+/// MD4 of the UTF-16LE password.
+pub fn nt_password_hash(password: &str) -> [u8; 16] {
+    let unicode: Vec<u8> = password.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+    Md4::digest(&unicode).into()
+}
+
+pub fn challenge_hash(peer_challenge: &[u8; 16], auth_challenge: &[u8; 16], username: &str) -> [u8; 8] {
+    let mut sha = Sha1::new();
+    sha.update(peer_challenge);
+    sha.update(auth_challenge);
+    sha.update(username.as_bytes());
+    let digest = sha.finalize();
+    digest[..8].try_into().unwrap()
+}
+
+/// DES-encrypts the 8-byte challenge under three keys cut from the
+/// zero-padded 21-byte NT hash.
+pub fn challenge_response(challenge: &[u8; 8], nt_hash: &[u8; 16]) -> [u8; 24] {
+    let mut padded = [0u8; 21];
+    padded[..16].copy_from_slice(nt_hash);
+
+    let mut response = [0u8; 24];
+    for (i, chunk) in padded.chunks(7).enumerate() {
+        let cipher = Des::new_from_slice(&des_key(chunk.try_into().unwrap())).unwrap();
+        let mut block = GenericArray::clone_from_slice(challenge);
+        cipher.encrypt_block(&mut block);
+        response[i * 8..i * 8 + 8].copy_from_slice(&block);
+    }
+    response
+}
+
+pub fn generate_nt_response(
+    auth_challenge: &[u8; 16],
+    peer_challenge: &[u8; 16],
+    username: &str,
+    nt_hash: &[u8; 16],
+) -> [u8; 24] {
+    let challenge = challenge_hash(peer_challenge, auth_challenge, username);
+    challenge_response(&challenge, nt_hash)
+}
+
+/// The `S=<40 hex digits>` string the peer uses to authenticate the server.
+pub fn authenticator_response(
+    nt_hash: &[u8; 16],
+    nt_response: &[u8; 24],
+    peer_challenge: &[u8; 16],
+    auth_challenge: &[u8; 16],
+    username: &str,
+) -> String {
+    let hash_hash = Md4::digest(nt_hash);
+
+    let mut sha = Sha1::new();
+    sha.update(hash_hash);
+    sha.update(nt_response);
+    sha.update(AUTH_MAGIC1);
+    let digest = sha.finalize();
+
+    let challenge = challenge_hash(peer_challenge, auth_challenge, username);
+    let mut sha = Sha1::new();
+    sha.update(digest);
+    sha.update(challenge);
+    sha.update(AUTH_MAGIC2);
+    let digest = sha.finalize();
+
+    let hex: String = digest.iter().map(|b| format!("{:02X}", b)).collect();
+    format!("S={}", hex)
+}
+
+/// Derives the 128-bit MPPE keys as seen by the server, returned as
+/// `(send_key, recv_key)`.
+pub fn mppe_keys(nt_hash: &[u8; 16], nt_response: &[u8; 24]) -> ([u8; 16], [u8; 16]) {
+    let hash_hash = Md4::digest(nt_hash);
+
+    let mut sha = Sha1::new();
+    sha.update(hash_hash);
+    sha.update(nt_response);
+    sha.update(MPPE_MAGIC1);
+    let master_key: [u8; 16] = sha.finalize()[..16].try_into().unwrap();
+
+    let start_key = |magic: &[u8]| -> [u8; 16] {
+        let mut sha = Sha1::new();
+        sha.update(master_key);
+        sha.update([0u8; 40]);
+        sha.update(magic);
+        sha.update([0xf2u8; 40]);
+        sha.finalize()[..16].try_into().unwrap()
+    };
+
+    (start_key(MPPE_MAGIC3), start_key(MPPE_MAGIC2))
+}
+
+/// A fresh random (Recv-Key, Send-Key) salt pair for one reply. RFC 2548
+/// §2.4.2 wants the high bit set and a different salt for each key, so
+/// the low bit of the second octet is 0 for Recv-Key and 1 for Send-Key.
+pub fn mppe_salts() -> ([u8; 2], [u8; 2]) {
+    let mut salt = [0u8; 4];
+    rand::rng().fill_bytes(&mut salt);
+    ([salt[0] | 0x80, salt[1] & !1], [salt[2] | 0x80, salt[3] | 1])
+}
+
+/// Encrypts an MS-MPPE-Send-Key / Recv-Key value (RFC 2548 §2.4.2). `salt`
+/// must be unique per key within a packet (see [`mppe_salts`]); its high
+/// bit is forced on.
+pub fn encrypt_mppe_key(key: &[u8], secret: &str, request_authenticator: &[u8; 16], salt: [u8; 2]) -> Vec<u8> {
+    let salt = [salt[0] | 0x80, salt[1]];
+
+    let mut plain = vec![key.len() as u8];
+    plain.extend_from_slice(key);
+    plain.resize(plain.len().div_ceil(16) * 16, 0);
+
+    let mut out = salt.to_vec();
+    let mut previous: Vec<u8> = [request_authenticator.as_slice(), &salt].concat();
+    for block in plain.chunks(16) {
+        let mut data = secret.as_bytes().to_vec();
+        data.extend_from_slice(&previous);
+        let b = md5::compute(&data);
+
+        let cipher: Vec<u8> = block.iter().zip(b.0.iter()).map(|(p, k)| p ^ k).collect();
+        out.extend_from_slice(&cipher);
+        previous = cipher;
+    }
+    out
+}
+
+/// Reverses [`encrypt_mppe_key`], as a NAS would. A malformed value is
+/// reported against the Vendor-Specific attribute carrying it.
+pub fn decrypt_mppe_key(value: &[u8], secret: &str, request_authenticator: &[u8; 16]) -> Result<Vec<u8>, RadiusError> {
+    let invalid = |reason: String| RadiusError::InvalidAttribute { typ: ATTR_VENDOR_SPECIFIC, reason };
+    if value.len() < 18 || !(value.len() - 2).is_multiple_of(16) {
+        return Err(invalid(format!("Invalid MS-MPPE key length: {}", value.len())));
+    }
+
+    let (salt, cipher) = value.split_at(2);
+    let mut plain = Vec::with_capacity(cipher.len());
+    let mut previous: Vec<u8> = [request_authenticator.as_slice(), salt].concat();
+    for block in cipher.chunks(16) {
+        let mut data = secret.as_bytes().to_vec();
+        data.extend_from_slice(&previous);
+        let b = md5::compute(&data);
+
+        plain.extend(block.iter().zip(b.0.iter()).map(|(c, k)| c ^ k));
+        previous = block.to_vec();
+    }
+
+    let key_len = plain[0] as usize;
+    if key_len + 1 > plain.len() {
+        return Err(invalid(format!("MS-MPPE key length {} exceeds payload", key_len)));
+    }
+    Ok(plain[1..1 + key_len].to_vec())
+}
+
+/// Builds a Microsoft Vendor-Specific attribute.
+pub fn ms_attribute(vendor_type: u8, data: &[u8]) -> RadiusAttribute {
+    let mut payload = vec![vendor_type, (data.len() + 2) as u8];
+    payload.extend_from_slice(data);
+    RadiusAttribute::vendor_specific(VENDOR_MICROSOFT, payload).expect("MS-CHAP attributes fit in one VSA")
+}
+
+/// Finds the first Microsoft VSA of `vendor_type` in the packet.
+pub fn find_ms_attribute(packet: &RadiusPacket, vendor_type: u8) -> Option<&[u8]> {
+    packet.attributes.iter().filter(|a| a.typ == 26).find_map(|a| {
+        let value = &a.value;
+        if value.len() < 6 || value[0..4] != VENDOR_MICROSOFT.to_be_bytes() || value[4] != vendor_type {
+            return None;
+        }
+        let len = value[5] as usize;
+        value.get(6..4 + len.max(2))
+    })
+}
+
+/// The MS-CHAPv2 exchange carried in an Access-Request.
+#[derive(Debug, Clone)]
+pub struct MsChapV2 {
+    pub ident: u8,
+    pub auth_challenge: [u8; 16],
+    pub peer_challenge: [u8; 16],
+    pub nt_response: [u8; 24],
+}
+
+impl MsChapV2 {
+    /// Extracts MS-CHAP-Challenge and MS-CHAP2-Response from the packet.
+    pub fn from_packet(packet: &RadiusPacket) -> Option<Self> {
+        let challenge = find_ms_attribute(packet, MS_CHAP_CHALLENGE)?;
+        let response = find_ms_attribute(packet, MS_CHAP2_RESPONSE)?;
+        if challenge.len() != 16 || response.len() != 50 {
+            return None;
+        }
+
+        Some(MsChapV2 {
+            ident: response[0],
+            auth_challenge: challenge.try_into().unwrap(),
+            peer_challenge: response[2..18].try_into().unwrap(),
+            nt_response: response[26..50].try_into().unwrap(),
+        })
+    }
+
+    /// Computes the peer's side of the exchange, as a supplicant would.
+    pub fn respond(ident: u8, auth_challenge: [u8; 16], peer_challenge: [u8; 16], username: &str, password: &str) -> Self {
+        let nt_hash = nt_password_hash(password);
+        MsChapV2 {
+            ident,
+            auth_challenge,
+            peer_challenge,
+            nt_response: generate_nt_response(&auth_challenge, &peer_challenge, strip_domain(username), &nt_hash),
+        }
+    }
+
+    /// The MS-CHAP-Challenge and MS-CHAP2-Response attributes for an
+    /// Access-Request.
+    pub fn request_attributes(&self) -> Vec<RadiusAttribute> {
+        let mut response = vec![self.ident, 0];
+        response.extend_from_slice(&self.peer_challenge);
+        response.extend_from_slice(&[0u8; 8]);
+        response.extend_from_slice(&self.nt_response);
+
+        vec![
+            ms_attribute(MS_CHAP_CHALLENGE, &self.auth_challenge),
+            ms_attribute(MS_CHAP2_RESPONSE, &response),
+        ]
+    }
+
+    pub fn verify(&self, username: &str, known: &KnownPassword) -> bool {
+        let expected = generate_nt_response(
+            &self.auth_challenge,
+            &self.peer_challenge,
+            strip_domain(username),
+            &known.nt_hash(),
+        );
+        constant_time_eq(&expected, &self.nt_response)
+    }
+
+    pub fn authenticator_response(&self, username: &str, known: &KnownPassword) -> String {
+        authenticator_response(
+            &known.nt_hash(),
+            &self.nt_response,
+            &self.peer_challenge,
+            &self.auth_challenge,
+            strip_domain(username),
+        )
+    }
+
+    /// MS-CHAP2-Success plus encrypted MS-MPPE keys and policy for an
+    /// Access-Accept answering a request with `request_authenticator`.
+    pub fn success_attributes(
+        &self,
+        username: &str,
+        known: &KnownPassword,
+        secret: &str,
+        request_authenticator: &[u8; 16],
+    ) -> Vec<RadiusAttribute> {
+        let mut success = vec![self.ident];
+        success.extend_from_slice(self.authenticator_response(username, known).as_bytes());
+
+        let (send_key, recv_key) = mppe_keys(&known.nt_hash(), &self.nt_response);
+        let (recv_salt, send_salt) = mppe_salts();
+
+        vec![
+            ms_attribute(MS_CHAP2_SUCCESS, &success),
+            ms_attribute(MS_MPPE_RECV_KEY, &encrypt_mppe_key(&recv_key, secret, request_authenticator, recv_salt)),
+            ms_attribute(MS_MPPE_SEND_KEY, &encrypt_mppe_key(&send_key, secret, request_authenticator, send_salt)),
+            // Encryption allowed (1) using 128-bit keys (4).
+            ms_attribute(MS_MPPE_ENCRYPTION_POLICY, &1u32.to_be_bytes()),
+            ms_attribute(MS_MPPE_ENCRYPTION_TYPES, &4u32.to_be_bytes()),
+        ]
+    }
+
+    /// MS-CHAP-Error for an Access-Reject: authentication failure (691),
+    /// no retry.
+    pub fn error_attribute(&self) -> RadiusAttribute {
+        let mut error = vec![self.ident];
+        error.extend_from_slice(b"E=691 R=0 V=3");
+        ms_attribute(MS_CHAP_ERROR, &error)
+    }
+}
+
+/// Validates an MS-CHAPv2 Access-Request against the user's known password.
+///
+/// On success returns the attributes to put in the Access-Accept; on
+/// failure the attributes for the Access-Reject. `Err(vec![])` means the
+/// request carried no usable MS-CHAPv2 data.
+pub fn authenticate(
+    packet: &RadiusPacket,
+    known: &KnownPassword,
+    secret: &str,
+) -> Result<Vec<RadiusAttribute>, Vec<RadiusAttribute>> {
+    let (Some(mschap), Some(username)) = (MsChapV2::from_packet(packet), packet.username()) else {
+        return Err(vec![]);
+    };
+
+    if mschap.verify(&username, known) {
+        Ok(mschap.success_attributes(&username, known, secret, &packet.authenticator))
+    } else {
+        Err(vec![mschap.error_attribute()])
+    }
+}
+
+/// MS-CHAP hashes the bare account name, without any `DOMAIN\` prefix.
+fn strip_domain(username: &str) -> &str {
+    username.rsplit_once('\\').map(|(_, user)| user).unwrap_or(username)
+}
+
+/// Spreads 56 key bits over 8 bytes, leaving the DES parity bit clear.
+fn des_key(key: &[u8; 7]) -> [u8; 8] {
+    [
+        key[0],
+        (key[0] << 7) | (key[1] >> 1),
+        (key[1] << 6) | (key[2] >> 2),
+        (key[2] << 5) | (key[3] >> 3),
+        (key[3] << 4) | (key[4] >> 4),
+        (key[4] << 3) | (key[5] >> 5),
+        (key[5] << 2) | (key[6] >> 6),
+        key[6] << 1,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 2759 §9.2 and RFC 3079 §3.5.3.
+    const USERNAME: &str = "User";
+    const PASSWORD: &str = "clientPass";
+    const AUTH_CHALLENGE: &str = "5B5D7C7D7B3F2F3E3C2C602132262628";
+    const PEER_CHALLENGE: &str = "21402324255E262A28295F2B3A337C7E";
+    const NT_RESPONSE: &str = "82309ECD8D708B5EA08FAA3981CD83544233114A3D85D6DF";
+
+    fn exchange() -> MsChapV2 {
+        MsChapV2 {
+            ident: 1,
+            auth_challenge: hex::decode_array(AUTH_CHALLENGE).unwrap(),
+            peer_challenge: hex::decode_array(PEER_CHALLENGE).unwrap(),
+            nt_response: hex::decode_array(NT_RESPONSE).unwrap(),
+        }
+    }
+
+    #[test]
+    fn matches_rfc_2759_vectors() {
+        let chap = exchange();
+        let nt_hash = nt_password_hash(PASSWORD);
+        assert_eq!(nt_hash, hex::decode_array::<16>("44EBBA8D5312B8D611474411F56989AE").unwrap());
+        assert_eq!(
+            challenge_hash(&chap.peer_challenge, &chap.auth_challenge, USERNAME),
+            hex::decode_array::<8>("D02E4386BCE91226").unwrap()
+        );
+
+        let respond = MsChapV2::respond(1, chap.auth_challenge, chap.peer_challenge, USERNAME, PASSWORD);
+        assert_eq!(respond.nt_response, chap.nt_response);
+        assert_eq!(
+            chap.authenticator_response(USERNAME, &KnownPassword::Cleartext(PASSWORD.to_string())),
+            "S=407A5589115FD0D6209F510FE9C04566932CDA56"
+        );
+    }
+
+    #[test]
+    fn verifies_with_cleartext_or_nt_hash() {
+        let chap = exchange();
+        assert!(chap.verify(USERNAME, &KnownPassword::Cleartext(PASSWORD.to_string())));
+        assert!(chap.verify("DOMAIN\\User", &KnownPassword::nt_from_hex("44EBBA8D5312B8D611474411F56989AE").unwrap()));
+        assert!(!chap.verify(USERNAME, &KnownPassword::Cleartext("serverPass".to_string())));
+    }
+
+    #[test]
+    fn matches_rfc_3079_send_key() {
+        let chap = exchange();
+        let (send, _) = mppe_keys(&nt_password_hash(PASSWORD), &chap.nt_response);
+        assert_eq!(send, hex::decode_array::<16>("8B7CDC149B993A1BA118CB153F56DCCB").unwrap());
+    }
+}
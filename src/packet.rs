@@ -1,14 +1,164 @@
+use crate::dictionary::{AttributeKey, Dictionary, Encryption, RadiusAttributeDef};
+use crate::error::RadiusError;
+use crate::mschap::{decrypt_mppe_key, encrypt_mppe_key};
+use crate::value::{split_tlvs, AttributeValue, ValueTree};
+use rand::RngCore;
+use std::borrow::Cow;
+use std::fmt;
 use std::str;
-pub const CODE_ACCOUNTING_REQUEST: u8 = 4;
-pub const CODE_ACCOUNTING_RESPONSE: u8 = 5;
 
+pub const ATTR_USER_NAME: u8 = 1;
+pub const ATTR_USER_PASSWORD: u8 = 2;
+pub const ATTR_CHAP_PASSWORD: u8 = 3;
+pub const ATTR_REPLY_MESSAGE: u8 = 18;
+pub const ATTR_STATE: u8 = 24;
+pub const ATTR_VENDOR_SPECIFIC: u8 = 26;
 pub const ATTR_ACCT_STATUS_TYPE: u8 = 40;
 pub const ATTR_ACCT_SESSION_ID: u8 = 44;
 pub const ATTR_ACCT_SESSION_TIME: u8 = 46;
+pub const ATTR_CHAP_CHALLENGE: u8 = 60;
+pub const ATTR_TUNNEL_PASSWORD: u8 = 69;
+pub const ATTR_EAP_MESSAGE: u8 = 79;
+pub const ATTR_MESSAGE_AUTHENTICATOR: u8 = 80;
+pub const ATTR_EXTENDED_1: u8 = 241;
+pub const ATTR_LONG_EXTENDED_1: u8 = 245;
+pub const ATTR_LONG_EXTENDED_2: u8 = 246;
+
+/// The More flag of a Long-Extended-Type fragment (RFC 6929 §2.2).
+const LONG_EXTENDED_MORE: u8 = 0x80;
+
+/// Largest RADIUS packet allowed on the wire (RFC 2865 §3).
+pub const MAX_PACKET_LEN: usize = 4096;
+/// Largest value a single attribute can carry: 255 minus the type and
+/// length octets.
+pub const MAX_ATTRIBUTE_VALUE_LEN: usize = 253;
+/// Longest password User-Password can hide (RFC 2865 §5.2).
+pub const MAX_PASSWORD_LEN: usize = 128;
+
+#[deprecated(note = "use `RadiusCode::AccountingRequest`")]
+pub const CODE_ACCOUNTING_REQUEST: u8 = RadiusCode::AccountingRequest.as_u8();
+#[deprecated(note = "use `RadiusCode::AccountingResponse`")]
+pub const CODE_ACCOUNTING_RESPONSE: u8 = RadiusCode::AccountingResponse.as_u8();
+
+/// RADIUS packet type (RFC 2865, 2866, 5176 and 5997).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RadiusCode {
+    AccessRequest,
+    AccessAccept,
+    AccessReject,
+    AccountingRequest,
+    AccountingResponse,
+    AccessChallenge,
+    StatusServer,
+    StatusClient,
+    DisconnectRequest,
+    DisconnectAck,
+    DisconnectNak,
+    CoaRequest,
+    CoaAck,
+    CoaNak,
+    /// Any code without a variant of its own.
+    Other(u8),
+}
+
+impl RadiusCode {
+    /// The name used in dictionaries and logs, e.g. "Access-Request".
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self {
+            RadiusCode::AccessRequest => "Access-Request",
+            RadiusCode::AccessAccept => "Access-Accept",
+            RadiusCode::AccessReject => "Access-Reject",
+            RadiusCode::AccountingRequest => "Accounting-Request",
+            RadiusCode::AccountingResponse => "Accounting-Response",
+            RadiusCode::AccessChallenge => "Access-Challenge",
+            RadiusCode::StatusServer => "Status-Server",
+            RadiusCode::StatusClient => "Status-Client",
+            RadiusCode::DisconnectRequest => "Disconnect-Request",
+            RadiusCode::DisconnectAck => "Disconnect-ACK",
+            RadiusCode::DisconnectNak => "Disconnect-NAK",
+            RadiusCode::CoaRequest => "CoA-Request",
+            RadiusCode::CoaAck => "CoA-ACK",
+            RadiusCode::CoaNak => "CoA-NAK",
+            RadiusCode::Other(_) => return None,
+        })
+    }
+
+    /// The code octet on the wire. Same as `u8::from`, but usable in
+    /// constants.
+    pub const fn as_u8(self) -> u8 {
+        match self {
+            RadiusCode::AccessRequest => 1,
+            RadiusCode::AccessAccept => 2,
+            RadiusCode::AccessReject => 3,
+            RadiusCode::AccountingRequest => 4,
+            RadiusCode::AccountingResponse => 5,
+            RadiusCode::AccessChallenge => 11,
+            RadiusCode::StatusServer => 12,
+            RadiusCode::StatusClient => 13,
+            RadiusCode::DisconnectRequest => 40,
+            RadiusCode::DisconnectAck => 41,
+            RadiusCode::DisconnectNak => 42,
+            RadiusCode::CoaRequest => 43,
+            RadiusCode::CoaAck => 44,
+            RadiusCode::CoaNak => 45,
+            RadiusCode::Other(code) => code,
+        }
+    }
+
+    /// Whether packets of this type are sent by a client and answered by
+    /// a server.
+    pub fn is_request(self) -> bool {
+        matches!(
+            self,
+            RadiusCode::AccessRequest
+                | RadiusCode::AccountingRequest
+                | RadiusCode::StatusServer
+                | RadiusCode::DisconnectRequest
+                | RadiusCode::CoaRequest
+        )
+    }
+}
+
+impl From<u8> for RadiusCode {
+    fn from(code: u8) -> Self {
+        match code {
+            1 => RadiusCode::AccessRequest,
+            2 => RadiusCode::AccessAccept,
+            3 => RadiusCode::AccessReject,
+            4 => RadiusCode::AccountingRequest,
+            5 => RadiusCode::AccountingResponse,
+            11 => RadiusCode::AccessChallenge,
+            12 => RadiusCode::StatusServer,
+            13 => RadiusCode::StatusClient,
+            40 => RadiusCode::DisconnectRequest,
+            41 => RadiusCode::DisconnectAck,
+            42 => RadiusCode::DisconnectNak,
+            43 => RadiusCode::CoaRequest,
+            44 => RadiusCode::CoaAck,
+            45 => RadiusCode::CoaNak,
+            other => RadiusCode::Other(other),
+        }
+    }
+}
+
+impl From<RadiusCode> for u8 {
+    fn from(code: RadiusCode) -> Self {
+        code.as_u8()
+    }
+}
+
+impl fmt::Display for RadiusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "Unknown-Code-{}", u8::from(*self)),
+        }
+    }
+}
 
 #[derive(Debug,Clone)]
 pub struct RadiusPacket {
-    pub code: u8,
+    pub code: RadiusCode,
     pub identifier: u8,
     pub length: u16,
     pub authenticator: [u8; 16],
@@ -23,164 +173,172 @@ pub struct RadiusAttribute {
 }
 
 impl RadiusAttribute {
-    pub fn reply_message(msg: &str) -> Self {
-        let value = msg.as_bytes().to_vec();
-        let len = (value.len() + 2) as u8;
+    /// Builds an attribute, failing if `value` does not fit in one.
+    pub fn new(typ: u8, value: Vec<u8>) -> Result<Self, RadiusError> {
+        if value.len() > MAX_ATTRIBUTE_VALUE_LEN {
+            return Err(RadiusError::AttributeTooLong { typ, len: value.len() });
+        }
 
+        Ok(RadiusAttribute {
+            typ,
+            len: (value.len() + 2) as u8,
+            value,
+        })
+    }
+
+    /// [`new`](Self::new) for values whose size is known to fit. Callers
+    /// pass fixed-size values, chunks of at most [`MAX_ATTRIBUTE_VALUE_LEN`]
+    /// bytes or values of received attributes, so this cannot fail.
+    pub(crate) fn fixed(typ: u8, value: Vec<u8>) -> Self {
+        debug_assert!(value.len() <= MAX_ATTRIBUTE_VALUE_LEN, "attribute {typ} value of {} bytes", value.len());
         RadiusAttribute {
-            typ: 18,
-            len,
+            typ,
+            len: (value.len() + 2) as u8,
             value,
         }
     }
 
-    pub fn user_name(name: &str) -> Self {
-        let value = name.as_bytes().to_vec();
-        let len = (value.len() + 2) as u8;
+    pub fn reply_message(msg: &str) -> Result<Self, RadiusError> {
+        Self::new(ATTR_REPLY_MESSAGE, msg.as_bytes().to_vec())
+    }
 
-        RadiusAttribute {
-            typ: 1,
-            len,
-            value,
+    /// Splits a message of any length over as many Reply-Message attributes
+    /// as needed, without breaking UTF-8 characters. The NAS displays them
+    /// concatenated (RFC 2865 §5.18).
+    pub fn reply_messages(msg: &str) -> Vec<RadiusAttribute> {
+        let mut attributes = Vec::new();
+        let mut rest = msg;
+        while !rest.is_empty() {
+            let mut end = rest.len().min(MAX_ATTRIBUTE_VALUE_LEN);
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            let (chunk, tail) = rest.split_at(end);
+            attributes.push(Self::fixed(ATTR_REPLY_MESSAGE, chunk.as_bytes().to_vec()));
+            rest = tail;
         }
+        attributes
+    }
+
+    pub fn user_name(name: &str) -> Result<Self, RadiusError> {
+        Self::new(ATTR_USER_NAME, name.as_bytes().to_vec())
     }
 
-    pub fn vendor_specific(vendor_id: u32, payload: Vec<u8>) -> Self {
+    pub fn vendor_specific(vendor_id: u32, payload: Vec<u8>) -> Result<Self, RadiusError> {
         let mut value = Vec::new();
         value.extend_from_slice(&vendor_id.to_be_bytes());
         value.extend(payload);
 
-        RadiusAttribute {
-            typ: 26,
-            len: (2 + value.len()) as u8,
-            value,
-        }
+        Self::new(ATTR_VENDOR_SPECIFIC, value)
     }
 
     pub fn wispr_bandwidth_max_up(bps: u32) -> Self {
-        let mut value = Vec::new();
-        value.extend_from_slice(&14122u32.to_be_bytes());
-        value.push(7);
-        value.push(6);
-        value.extend_from_slice(&bps.to_be_bytes());
-
-        RadiusAttribute {
-            typ: 26,
-            len: (2 + value.len()) as u8,
-            value,
-        }
+        Self::wispr_bandwidth(7, bps)
     }
 
     pub fn wispr_bandwidth_max_down(bps: u32) -> Self {
+        Self::wispr_bandwidth(8, bps)
+    }
+
+    fn wispr_bandwidth(vendor_type: u8, bps: u32) -> Self {
         let mut value = Vec::new();
         value.extend_from_slice(&14122u32.to_be_bytes());
-        value.push(8);
+        value.push(vendor_type);
         value.push(6);
         value.extend_from_slice(&bps.to_be_bytes());
 
-        RadiusAttribute {
-            typ: 26,
-            len: (2 + value.len()) as u8,
-            value,
-        }
-    }
-
-    pub fn session_timeout(seconds: u32) -> Self {
-        RadiusAttribute {
-            typ: 27,
-            len: 6,
-            value: seconds.to_be_bytes().to_vec(),
-        }
+        Self::fixed(ATTR_VENDOR_SPECIFIC, value)
     }
 
-    pub fn idle_timeout(seconds: u32) -> Self {
-        RadiusAttribute {
-            typ: 28,
-            len: 6,
-            value: seconds.to_be_bytes().to_vec(),
-        }
+    /// Builds a hidden User-Password for an outgoing Access-Request whose
+    /// Request Authenticator is `authenticator` (RFC 2865 §5.2). Fails for
+    /// passwords over 128 bytes.
+    pub fn user_password(password: &str, secret: &str, authenticator: &[u8; 16]) -> Result<Self, RadiusError> {
+        Self::new(ATTR_USER_PASSWORD, hide_password(password.as_bytes(), secret, authenticator)?)
     }
-}
 
-impl RadiusPacket {
-    pub fn from_bytes(buf: &[u8]) -> Result<Self, String> {
-        if buf.len() < 20 {
-            return Err("Packet too short".to_string());
-        }
+    /// Builds a CHAP-Password (RFC 2865 §5.3) from the CHAP identifier, the
+    /// cleartext password and the challenge it answers.
+    pub fn chap_password(chap_id: u8, password: &str, challenge: &[u8]) -> Self {
+        let mut value = vec![chap_id];
+        value.extend_from_slice(&chap_response(chap_id, password.as_bytes(), challenge));
 
-        let code = buf[0];
-        let identifier = buf[1];
-        let length = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        Self::fixed(ATTR_CHAP_PASSWORD, value)
+    }
 
-        if buf.len() < length {
-            return Err(format!(
-                "Length mismatch: header says {}, but got {} bytes",
-                length,
-                buf.len()
-            ));
-        }
+    pub fn chap_challenge(challenge: &[u8]) -> Result<Self, RadiusError> {
+        Self::new(ATTR_CHAP_CHALLENGE, challenge.to_vec())
+    }
 
-        let authenticator: [u8; 16] = buf[4..20].try_into().unwrap();
-        let mut attributes = Vec::new();
-        let mut i = 20;
+    pub fn state(state: &[u8]) -> Result<Self, RadiusError> {
+        Self::new(ATTR_STATE, state.to_vec())
+    }
 
-        while i < length {
-            if i + 2 > length {
-                return Err("Truncated attribute header".to_string());
-            }
+    /// Splits an EAP packet over as many EAP-Message attributes as needed
+    /// (RFC 3579 §3.1).
+    pub fn eap_message(eap: &[u8]) -> Vec<RadiusAttribute> {
+        eap.chunks(MAX_ATTRIBUTE_VALUE_LEN)
+            .map(|chunk| Self::fixed(ATTR_EAP_MESSAGE, chunk.to_vec()))
+            .collect()
+    }
 
-            let typ = buf[i];
-            let len = buf[i + 1] as usize;
+    /// Encodes an RFC 6929 Extended-Type attribute `typ.ext_type`. Values
+    /// of Long-Extended-Type attributes (245, 246) are fragmented as
+    /// needed; see [`ExtendedAttribute::to_attributes`].
+    pub fn extended(typ: u8, ext_type: u8, value: &[u8]) -> Result<Vec<RadiusAttribute>, RadiusError> {
+        ExtendedAttribute::new(typ, ext_type, value.to_vec()).to_attributes()
+    }
 
-            if len < 2 || i + len > length {
-                return Err(format!(
-                    "Invalid attribute length at index {}: len = {}",
-                    i, len
-                ));
-            }
+    /// The Extended-Type of an attribute in the 241–246 space.
+    pub fn extended_type(&self) -> Option<u8> {
+        if is_extended_type(self.typ) { self.value.first().copied() } else { None }
+    }
 
-            let value = buf[i + 2..i + len].to_vec();
-            attributes.push(RadiusAttribute {
-                typ,
-                len: len as u8,
-                value,
-            });
+    pub fn session_timeout(seconds: u32) -> Self {
+        Self::fixed(27, seconds.to_be_bytes().to_vec())
+    }
 
-            i += len;
-        }
+    pub fn idle_timeout(seconds: u32) -> Self {
+        Self::fixed(28, seconds.to_be_bytes().to_vec())
+    }
+}
 
-        Ok(RadiusPacket {
-            code,
-            identifier,
-            length: length as u16,
-            authenticator,
-            attributes,
-        })
+impl RadiusPacket {
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, RadiusError> {
+        RadiusPacketRef::parse(buf).map(|packet| packet.to_packet())
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
+    /// Encodes the packet, failing if an attribute value is longer than
+    /// 253 bytes or the whole packet exceeds [`MAX_PACKET_LEN`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, RadiusError> {
         let mut buf = Vec::new();
-        buf.push(self.code);
+        buf.push(self.code.into());
         buf.push(self.identifier);
         buf.extend_from_slice(&[0x00, 0x00]);
         buf.extend_from_slice(&self.authenticator);
 
         for attr in &self.attributes {
+            if attr.value.len() > MAX_ATTRIBUTE_VALUE_LEN {
+                return Err(RadiusError::AttributeTooLong { typ: attr.typ, len: attr.value.len() });
+            }
             buf.push(attr.typ);
-            buf.push(attr.len);
+            buf.push((attr.value.len() + 2) as u8);
             buf.extend_from_slice(&attr.value);
         }
 
+        if buf.len() > MAX_PACKET_LEN {
+            return Err(RadiusError::PacketTooLarge { len: buf.len() });
+        }
         let length = buf.len() as u16;
         buf[2] = (length >> 8) as u8;
         buf[3] = length as u8;
 
-        buf
+        Ok(buf)
     }
 
     pub fn access_accept(identifier: u8, attributes: Vec<RadiusAttribute>) -> Self {
         RadiusPacket {
-            code: 2,
+            code: RadiusCode::AccessAccept,
             identifier,
             length: 0,
             authenticator: [0u8; 16],
@@ -190,33 +348,36 @@ impl RadiusPacket {
 
     pub fn access_reject(identifier: u8, msg: &str) -> Self {
         RadiusPacket {
-            code: 3,
+            code: RadiusCode::AccessReject,
             identifier,
             length: 0,
             authenticator: [0u8; 16],
-            attributes: vec![RadiusAttribute::reply_message(msg)],
+            attributes: RadiusAttribute::reply_messages(msg),
         }
     }
 
     pub fn access_challenge(identifier: u8, msg: &str) -> Self {
         RadiusPacket {
-            code: 11,
+            code: RadiusCode::AccessChallenge,
             identifier,
             length: 0,
             authenticator: [0u8; 16],
-            attributes: vec![RadiusAttribute::reply_message(msg)],
+            attributes: RadiusAttribute::reply_messages(msg),
         }
     }
 
     pub fn log(&self) {
-        let code_name = match self.code {
-            1 => "Access-Request",
-            2 => "Access-Accept",
-            3 => "Access-Reject",
-            _ => "Unknown",
-        };
+        self.log_attributes(None);
+    }
 
-        println!("📨 RADIUS {} (id: {})", code_name, self.identifier);
+    /// Like [`log`](Self::log), but Vendor-Specific sub-attributes are
+    /// named and decoded using the dictionary's vendor definitions.
+    pub fn log_with(&self, dict: &Dictionary) {
+        self.log_attributes(Some(dict));
+    }
+
+    fn log_attributes(&self, dict: Option<&Dictionary>) {
+        println!("📨 RADIUS {} (id: {})", self.code, self.identifier);
 
         for attr in &self.attributes {
             let name = radius_type_name(attr.typ);
@@ -226,7 +387,11 @@ impl RadiusPacket {
                     Err(_) => println!("  • {}: {:?}", name, attr.value),
                 }
             } else if attr.typ == 26 {
-                println!("  • {}: {}", name, decode_vendor_specific(&attr.value));
+                println!("  • {}: {}", name, decode_vendor_specific(&attr.value, dict));
+            } else if let Some(ext_type) = attr.extended_type() {
+                let header = if is_long_extended_type(attr.typ) { 2 } else { 1 };
+                let data = attr.value.get(header..).unwrap_or(&[]);
+                println!("  • {} ({}.{}): {:?}", name, attr.typ, ext_type, data);
             } else {
                 println!("  • {}: {:?}", name, attr.value);
             }
@@ -234,40 +399,939 @@ impl RadiusPacket {
     }
       pub fn reply_accept(&self, attributes: Vec<RadiusAttribute>) -> RadiusPacket {
         RadiusPacket {
-            code: 2,
+            code: RadiusCode::AccessAccept,
             identifier: self.identifier,
             length: 0,
-            authenticator: [0; 16],
+            authenticator: self.authenticator,
             attributes,
         }
     }
 
     pub fn reply_reject(&self, message: &str) -> RadiusPacket {
         RadiusPacket {
-            code: 3,
+            code: RadiusCode::AccessReject,
             identifier: self.identifier,
             length: 0,
-            authenticator: [0; 16],
-            attributes: vec![RadiusAttribute::reply_message(message)],
+            authenticator: self.authenticator,
+            attributes: RadiusAttribute::reply_messages(message),
         }
     }
 
     pub fn reply_challenge(&self, message: &str) -> RadiusPacket {
         RadiusPacket {
-            code: 11,
+            code: RadiusCode::AccessChallenge,
+            identifier: self.identifier,
+            length: 0,
+            authenticator: self.authenticator,
+            attributes: RadiusAttribute::reply_messages(message),
+        }
+    }
+
+    /// Builds an Access-Challenge carrying an EAP request and the State
+    /// the peer must echo back. Fails if `state` is over 253 bytes.
+    pub fn reply_eap_challenge(&self, eap_message: &[u8], state: &[u8]) -> Result<RadiusPacket, RadiusError> {
+        let mut attributes = RadiusAttribute::eap_message(eap_message);
+        attributes.push(RadiusAttribute::state(state)?);
+
+        Ok(RadiusPacket {
+            code: RadiusCode::AccessChallenge,
+            identifier: self.identifier,
+            length: 0,
+            authenticator: self.authenticator,
+            attributes,
+        })
+    }
+
+    /// Reassembles the EAP packet from all EAP-Message attributes, in order.
+    pub fn eap_message(&self) -> Option<Vec<u8>> {
+        let mut parts = self.attributes.iter().filter(|a| a.typ == ATTR_EAP_MESSAGE).peekable();
+        parts.peek()?;
+        Some(parts.flat_map(|a| a.value.iter().copied()).collect())
+    }
+
+    /// All Extended-Type attributes in the packet, with Long-Extended-Type
+    /// fragments reassembled.
+    pub fn extended_attributes(&self) -> Result<Vec<ExtendedAttribute>, RadiusError> {
+        let mut extended: Vec<ExtendedAttribute> = Vec::new();
+        // Whether the last Long-Extended attribute had the More flag set.
+        let mut more = false;
+
+        for attr in &self.attributes {
+            if !is_extended_type(attr.typ) {
+                if more {
+                    return Err(RadiusError::InvalidAttribute {
+                        typ: extended.last().unwrap().typ,
+                        reason: "fragment with More flag is not followed by its continuation".to_string(),
+                    });
+                }
+                continue;
+            }
+
+            let Some(&ext_type) = attr.value.first() else {
+                return Err(RadiusError::InvalidAttribute {
+                    typ: attr.typ,
+                    reason: "missing Extended-Type".to_string(),
+                });
+            };
+
+            if !is_long_extended_type(attr.typ) {
+                if more {
+                    return Err(RadiusError::InvalidAttribute {
+                        typ: attr.typ,
+                        reason: "interrupts a fragmented Long-Extended-Type attribute".to_string(),
+                    });
+                }
+                extended.push(ExtendedAttribute::new(attr.typ, ext_type, attr.value[1..].to_vec()));
+                continue;
+            }
+
+            let Some(&flags) = attr.value.get(1) else {
+                return Err(RadiusError::InvalidAttribute {
+                    typ: attr.typ,
+                    reason: "missing Long-Extended-Type flags".to_string(),
+                });
+            };
+            let data = &attr.value[2..];
+
+            match extended.last_mut() {
+                Some(last) if more => {
+                    if last.typ != attr.typ || last.ext_type != ext_type {
+                        return Err(RadiusError::InvalidAttribute {
+                            typ: attr.typ,
+                            reason: format!(
+                                "fragment of {}.{} follows an unfinished {}.{}",
+                                attr.typ, ext_type, last.typ, last.ext_type
+                            ),
+                        });
+                    }
+                    last.value.extend_from_slice(data);
+                }
+                _ => extended.push(ExtendedAttribute::new(attr.typ, ext_type, data.to_vec())),
+            }
+            more = flags & LONG_EXTENDED_MORE != 0;
+        }
+
+        if more {
+            return Err(RadiusError::InvalidAttribute {
+                typ: extended.last().unwrap().typ,
+                reason: "last fragment has the More flag set".to_string(),
+            });
+        }
+        Ok(extended)
+    }
+
+    /// The (reassembled) value of the first `typ.ext_type` attribute.
+    pub fn extended(&self, typ: u8, ext_type: u8) -> Option<Vec<u8>> {
+        self.extended_attributes()
+            .ok()?
+            .into_iter()
+            .find(|a| a.typ == typ && a.ext_type == ext_type)
+            .map(|a| a.value)
+    }
+
+    /// The values of every `name` attribute, in packet order. `name` is
+    /// resolved through the dictionary and may be a standard, extended or
+    /// vendor attribute (`Vendor.Attribute`), an ALIAS, or a member of a
+    /// `tlv` (`Net.Src.IP`); unknown names match nothing.
+    pub fn attrs_all(&self, dict: &Dictionary, name: &str) -> Vec<Cow<'_, [u8]>> {
+        let Some((key, path, _)) = dict.lookup_path(name) else {
+            return Vec::new();
+        };
+        if path.is_empty() {
+            return self.values_of(key);
+        }
+        if !in_tlvs(dict, key, path) {
+            return Vec::new();
+        }
+        self.values_of(key)
+            .into_iter()
+            .flat_map(|value| match value {
+                Cow::Borrowed(value) => tlv_members(value, path).into_iter().map(Cow::Borrowed).collect(),
+                Cow::Owned(value) => tlv_members(&value, path)
+                    .into_iter()
+                    .map(|member| Cow::Owned(member.to_vec()))
+                    .collect::<Vec<_>>(),
+            })
+            .collect()
+    }
+
+    /// The values of every top-level attribute at `key`.
+    fn values_of(&self, key: AttributeKey) -> Vec<Cow<'_, [u8]>> {
+        match key {
+            AttributeKey::Standard(code) => self
+                .attributes
+                .iter()
+                .filter(|a| a.typ as u32 == code)
+                .map(|a| Cow::Borrowed(a.value.as_slice()))
+                .collect(),
+            AttributeKey::Extended(typ, ext_type) if is_long_extended_type(typ) => self
+                .extended_attributes()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|a| a.typ == typ && a.ext_type == ext_type)
+                .map(|a| Cow::Owned(a.value))
+                .collect(),
+            AttributeKey::Extended(typ, ext_type) => self
+                .attributes
+                .iter()
+                .filter(|a| a.typ == typ && a.extended_type() == Some(ext_type))
+                .map(|a| Cow::Borrowed(&a.value[1..]))
+                .collect(),
+            AttributeKey::Vendor(vendor_id, code) => self
+                .attributes
+                .iter()
+                .filter(|a| a.typ == ATTR_VENDOR_SPECIFIC)
+                .filter_map(|a| vendor_sub_attributes(&a.value))
+                .filter(|(id, _)| *id == vendor_id)
+                .flat_map(|(_, subs)| subs)
+                .filter(|(typ, _)| *typ as u32 == code)
+                .map(|(_, value)| Cow::Borrowed(value))
+                .collect(),
+            AttributeKey::Internal(_) => Vec::new(),
+        }
+    }
+
+    /// The value of the first `name` attribute; see [`attrs_all`](Self::attrs_all).
+    pub fn attr(&self, dict: &Dictionary, name: &str) -> Option<Cow<'_, [u8]>> {
+        self.attrs_all(dict, name).into_iter().next()
+    }
+
+    /// Removes every `name` attribute and returns how many there were.
+    /// Vendor-Specific attributes left empty are removed too. Removing a
+    /// `tlv` member rewrites the attributes carrying it, which move to the
+    /// end of the packet; ones left empty are dropped.
+    pub fn remove(&mut self, dict: &Dictionary, name: &str) -> usize {
+        let Some((key, path, _)) = dict.lookup_path(name) else {
+            return 0;
+        };
+        let before = self.attrs_all(dict, name).len();
+        if before == 0 {
+            return 0;
+        }
+        if path.is_empty() {
+            self.remove_key(key);
+            return before;
+        }
+
+        let containers: Vec<Vec<u8>> = self.values_of(key).into_iter().map(Cow::into_owned).collect();
+        self.remove_key(key);
+        for container in containers {
+            let rest = tlv_remove(&container, path);
+            // Cannot fail: the value only got shorter.
+            if !rest.is_empty() {
+                let _ = self.append(name, key, false, rest);
+            }
+        }
+        before
+    }
+
+    /// Removes every top-level attribute at `key`.
+    fn remove_key(&mut self, key: AttributeKey) {
+        match key {
+            AttributeKey::Standard(code) => self.attributes.retain(|a| a.typ as u32 != code),
+            AttributeKey::Extended(typ, ext_type) => {
+                self.attributes.retain(|a| !(a.typ == typ && a.extended_type() == Some(ext_type)))
+            }
+            AttributeKey::Vendor(vendor_id, code) => {
+                for attr in self.attributes.iter_mut().filter(|a| a.typ == ATTR_VENDOR_SPECIFIC) {
+                    let Some((id, subs)) = vendor_sub_attributes(&attr.value) else {
+                        continue;
+                    };
+                    if id != vendor_id {
+                        continue;
+                    }
+                    let mut value = vendor_id.to_be_bytes().to_vec();
+                    for (typ, data) in subs.into_iter().filter(|(typ, _)| *typ as u32 != code) {
+                        value.extend_from_slice(&[typ, (data.len() + 2) as u8]);
+                        value.extend_from_slice(data);
+                    }
+                    attr.len = (value.len() + 2) as u8;
+                    attr.value = value;
+                }
+                self.attributes.retain(|a| a.typ != ATTR_VENDOR_SPECIFIC || a.value.len() > 4);
+            }
+            AttributeKey::Internal(_) => {}
+        }
+    }
+
+    /// Replaces all `name` attributes with a single one holding `value`.
+    /// The packet is left as it was when `value` cannot be encoded.
+    pub fn set(&mut self, dict: &Dictionary, name: &str, value: AttributeValue) -> Result<(), RadiusError> {
+        let mut encoded = RadiusPacket {
+            code: self.code,
             identifier: self.identifier,
             length: 0,
-            authenticator: [0; 16],
-            attributes: vec![RadiusAttribute::reply_message(message)],
+            authenticator: self.authenticator,
+            attributes: Vec::new(),
+        };
+        encoded.add_typed(dict, name, value)?;
+        self.remove(dict, name);
+        self.attributes.append(&mut encoded.attributes);
+        Ok(())
+    }
+
+    /// Renders every attribute as `Name = Value` using the dictionary, e.g.
+    /// `Service-Type = Framed-User`. Extended-Type attributes come last,
+    /// reassembled; unknown attributes are shown as `Attr-N` with a hex value.
+    pub fn render(&self, dict: &Dictionary) -> Vec<String> {
+        let line = |key: AttributeKey, fallback: String, value: &[u8]| {
+            let name = dict.get(key).map(|def| def.name.clone()).unwrap_or(fallback);
+            format!("{} = {}", name, dict.format_value(key, value))
+        };
+
+        let mut lines = Vec::new();
+        for attr in &self.attributes {
+            if is_extended_type(attr.typ) {
+                continue;
+            }
+            if attr.typ == ATTR_VENDOR_SPECIFIC
+                && let Some((vendor_id, subs)) = vendor_sub_attributes(&attr.value)
+            {
+                for (typ, value) in subs {
+                    let key = AttributeKey::Vendor(vendor_id, typ as u32);
+                    lines.push(line(key, format!("Vendor-{}-Attr-{}", vendor_id, typ), value));
+                }
+                continue;
+            }
+            lines.push(line(AttributeKey::Standard(attr.typ as u32), format!("Attr-{}", attr.typ), &attr.value));
+        }
+
+        for attr in self.extended_attributes().unwrap_or_default() {
+            let key = AttributeKey::Extended(attr.typ, attr.ext_type);
+            lines.push(line(key, format!("Attr-{}.{}", attr.typ, attr.ext_type), &attr.value));
+        }
+        lines
+    }
+
+    /// Decodes the first `name` attribute according to its dictionary type.
+    /// Returns `Ok(None)` when the packet does not carry it.
+    ///
+    /// Dictionary flags are applied: tags are stripped, `concat` values are
+    /// joined and `array` values give their first element. Encrypted
+    /// attributes need [`get_typed_with_secret`](Self::get_typed_with_secret).
+    pub fn get_typed(&self, dict: &Dictionary, name: &str) -> Result<Option<AttributeValue>, RadiusError> {
+        Ok(self.get_tagged(dict, name, None)?.map(|(_, value)| value))
+    }
+
+    /// Like [`get_typed`](Self::get_typed), decrypting `encrypt=` attributes
+    /// such as User-Password with the shared secret and this packet's
+    /// authenticator.
+    pub fn get_typed_with_secret(
+        &self,
+        dict: &Dictionary,
+        name: &str,
+        secret: &str,
+    ) -> Result<Option<AttributeValue>, RadiusError> {
+        Ok(self.get_tagged(dict, name, Some(secret))?.map(|(_, value)| value))
+    }
+
+    /// The first `name` value together with its RFC 2868 tag (0 if none).
+    pub fn get_tagged(
+        &self,
+        dict: &Dictionary,
+        name: &str,
+        secret: Option<&str>,
+    ) -> Result<Option<(u8, AttributeValue)>, RadiusError> {
+        Ok(self.typed_values(dict, name, secret)?.into_iter().next())
+    }
+
+    /// Every `name` value, in packet order, with `array` attributes split
+    /// into their elements.
+    pub fn get_all_typed(&self, dict: &Dictionary, name: &str) -> Result<Vec<AttributeValue>, RadiusError> {
+        Ok(self.typed_values(dict, name, None)?.into_iter().map(|(_, value)| value).collect())
+    }
+
+    /// The first `name` value decoded along its definition, with the
+    /// members of `tlv` and `struct` attributes by name, e.g.
+    /// `tree.get("Src.IP")`.
+    pub fn get_tree(&self, dict: &Dictionary, name: &str) -> Result<Option<ValueTree>, RadiusError> {
+        let (def, values) = self.raw_values(dict, name, None)?;
+        values.first().map(|(_, bytes)| def.decode_tree(bytes)).transpose()
+    }
+
+    fn typed_values(
+        &self,
+        dict: &Dictionary,
+        name: &str,
+        secret: Option<&str>,
+    ) -> Result<Vec<(u8, AttributeValue)>, RadiusError> {
+        let (def, raw) = self.raw_values(dict, name, secret)?;
+        let mut values = Vec::new();
+        for (tag, bytes) in raw {
+            for element in def.split_array(&bytes)? {
+                values.push((tag, AttributeValue::decode(&def.data_type, element)?));
+            }
+        }
+        Ok(values)
+    }
+
+    /// Every `name` value with its tag split off, `concat` values joined
+    /// and `encrypt=` values decrypted.
+    fn raw_values<'d>(
+        &self,
+        dict: &'d Dictionary,
+        name: &str,
+        secret: Option<&str>,
+    ) -> Result<(&'d RadiusAttributeDef, Vec<TaggedBytes>), RadiusError> {
+        let (key, path, def) = resolve(dict, name)?;
+        if !in_tlvs(dict, key, path) {
+            return Err(RadiusError::StructMember(name.to_string()));
+        }
+        let mut raw = self.attrs_all(dict, name);
+        if def.flags.concat && raw.len() > 1 {
+            raw = vec![Cow::Owned(raw.concat())];
+        }
+
+        let mut values = Vec::new();
+        for bytes in raw {
+            let (tag, bytes) = def.split_tag(&bytes);
+            let bytes = match def.flags.encrypt {
+                Some(method) => {
+                    let secret = secret.ok_or_else(|| RadiusError::SecretRequired(name.to_string()))?;
+                    decrypt_value(method, &bytes, secret, &self.authenticator)?
+                }
+                None => bytes.into_owned(),
+            };
+            values.push((tag, bytes));
+        }
+        Ok((def, values))
+    }
+
+    /// Appends a `name` attribute, checking `value` against its dictionary
+    /// type. A string is accepted for an enumerated attribute when it is
+    /// one of its VALUE names, e.g. `"Framed-User"` for Service-Type.
+    ///
+    /// `has_tag` attributes get tag 0, `concat` values are split over as
+    /// many attributes as needed and `array` values are packed into the
+    /// last attribute while it has room. Encrypted attributes need
+    /// [`add_typed_with_secret`](Self::add_typed_with_secret).
+    pub fn add_typed(&mut self, dict: &Dictionary, name: &str, value: AttributeValue) -> Result<(), RadiusError> {
+        self.add_tagged(dict, name, 0, value, None)
+    }
+
+    /// Like [`add_typed`](Self::add_typed), encrypting `encrypt=` attributes
+    /// with the shared secret and this packet's authenticator. Replies
+    /// built with [`reply_accept`](Self::reply_accept) and friends carry
+    /// the request's authenticator, as RFC 2865 and RFC 2868 require.
+    pub fn add_typed_with_secret(
+        &mut self,
+        dict: &Dictionary,
+        name: &str,
+        value: AttributeValue,
+        secret: &str,
+    ) -> Result<(), RadiusError> {
+        self.add_tagged(dict, name, 0, value, Some(secret))
+    }
+
+    /// Appends a `name` attribute built from a tree of named members, as
+    /// returned by [`get_tree`](Self::get_tree). Struct members are laid
+    /// out in dictionary order whatever order the tree has them in.
+    pub fn add_tree(&mut self, dict: &Dictionary, name: &str, tree: &ValueTree) -> Result<(), RadiusError> {
+        let (_, _, def) = resolve(dict, name)?;
+        let data = def.encode_tree(tree)?;
+        self.add_tagged(dict, name, 0, AttributeValue::Octets(data), None)
+    }
+
+    /// Appends a `name` attribute with an RFC 2868 tag (1–31, or 0 for
+    /// none), e.g. one of several Tunnel-Type/Tunnel-Medium-Type groups.
+    pub fn add_tagged(
+        &mut self,
+        dict: &Dictionary,
+        name: &str,
+        tag: u8,
+        value: AttributeValue,
+        secret: Option<&str>,
+    ) -> Result<(), RadiusError> {
+        let (key, path, def) = resolve(dict, name)?;
+        if !in_tlvs(dict, key, path) {
+            return Err(RadiusError::StructMember(name.to_string()));
+        }
+        if def.flags.internal || matches!(key, AttributeKey::Internal(_)) {
+            return Err(RadiusError::InternalAttribute {
+                name: name.to_string(),
+                code: def.code,
+            });
+        }
+        let value = match &value {
+            AttributeValue::String(value_name) if !value.fits(&def.data_type) => {
+                dict.value(name, value_name).unwrap_or(value)
+            }
+            _ => value,
+        };
+        if !value.fits(&def.data_type) {
+            return Err(RadiusError::TypeMismatch {
+                attribute: name.to_string(),
+                expected: def.data_type.clone(),
+                found: value.data_type(),
+            });
+        }
+
+        let mut data = def.array_element(value.encode());
+        if let Some(method) = def.flags.encrypt {
+            let secret = secret.ok_or_else(|| RadiusError::SecretRequired(name.to_string()))?;
+            data = encrypt_value(method, &data, secret, &self.authenticator)?;
+        }
+        let data = def.join_tag(tag, data);
+        if !path.is_empty() {
+            let data = wrap_tlvs(path, data)?;
+            return self.append(name, key, false, data);
+        }
+
+        if def.flags.array
+            && let AttributeKey::Standard(code) = key
+            && let Some(last) = self.attributes.iter_mut().rev().find(|a| a.typ as u32 == code)
+            && last.value.len() + data.len() <= MAX_ATTRIBUTE_VALUE_LEN
+        {
+            last.value.extend_from_slice(&data);
+            last.len = (last.value.len() + 2) as u8;
+            return Ok(());
+        }
+        self.append(name, key, def.flags.concat, data)
+    }
+
+    /// Appends `data` as top-level attributes at `key`, split over as many
+    /// as needed when `concat` is set.
+    fn append(&mut self, name: &str, key: AttributeKey, concat: bool, data: Vec<u8>) -> Result<(), RadiusError> {
+        let chunk = match (concat, key) {
+            (false, _) => data.len().max(1),
+            (true, AttributeKey::Extended(typ, _)) if is_long_extended_type(typ) => data.len().max(1),
+            (true, AttributeKey::Extended(..)) => MAX_ATTRIBUTE_VALUE_LEN - 1,
+            // Vendor type and length octets.
+            (true, AttributeKey::Vendor(..)) => MAX_ATTRIBUTE_VALUE_LEN - 6,
+            (true, AttributeKey::Standard(_) | AttributeKey::Internal(_)) => MAX_ATTRIBUTE_VALUE_LEN,
+        };
+        let chunks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(chunk).collect() };
+        for data in chunks {
+            match key {
+                AttributeKey::Standard(code) => {
+                    self.attributes.push(RadiusAttribute::new(wire_type(name, code)?, data.to_vec())?);
+                }
+                AttributeKey::Extended(typ, ext_type) => {
+                    self.attributes.extend(RadiusAttribute::extended(typ, ext_type, data)?);
+                }
+                AttributeKey::Vendor(vendor_id, code) => {
+                    // Oversized values are caught by `vendor_specific`.
+                    let mut payload = vec![wire_type(name, code)?, (data.len() + 2) as u8];
+                    payload.extend_from_slice(data);
+                    self.attributes.push(RadiusAttribute::vendor_specific(vendor_id, payload)?);
+                }
+                AttributeKey::Internal(code) => {
+                    return Err(RadiusError::InternalAttribute {
+                        name: name.to_string(),
+                        code,
+                    });
+                }
+            }
         }
+        Ok(())
+    }
+
+    pub fn state(&self) -> Option<&[u8]> {
+        self.attributes
+            .iter()
+            .find(|a| a.typ == ATTR_STATE)
+            .map(|a| a.value.as_slice())
     }
 
     pub fn username(&self) -> Option<String> {
         self.attributes
             .iter()
-            .find(|a| a.typ == 1)
+            .find(|a| a.typ == ATTR_USER_NAME)
             .and_then(|a| String::from_utf8(a.value.clone()).ok())
     }
+
+    /// Recovers the cleartext User-Password of an Access-Request.
+    ///
+    /// Returns `None` if the attribute is missing, malformed or does not
+    /// decode to UTF-8 (usually a sign of a wrong shared secret).
+    pub fn user_password(&self, secret: &str) -> Option<String> {
+        let attr = self.attributes.iter().find(|a| a.typ == ATTR_USER_PASSWORD)?;
+        let clear = unhide_password(&attr.value, secret, &self.authenticator).ok()?;
+        String::from_utf8(clear).ok()
+    }
+}
+
+/// Resolves `name`, following ALIASes, to the top-level attribute that
+/// carries it, the path below that and its definition.
+fn resolve<'d>(dict: &'d Dictionary, name: &str) -> Result<(AttributeKey, &'d [u32], &'d RadiusAttributeDef), RadiusError> {
+    dict.lookup_path(name)
+        .ok_or_else(|| RadiusError::UnknownAttribute(name.to_string()))
+}
+
+/// Whether every attribute above `path` is a `tlv`, so the member can be
+/// reached through sub-attribute headers. Struct members cannot.
+fn in_tlvs(dict: &Dictionary, key: AttributeKey, path: &[u32]) -> bool {
+    (0..path.len()).all(|depth| dict.child(key, &path[..depth]).is_some_and(|def| def.data_type == "tlv"))
+}
+
+/// The values of the sub-attributes at `path` inside a `tlv` value.
+/// Malformed values contain nothing.
+fn tlv_members<'a>(value: &'a [u8], path: &[u32]) -> Vec<&'a [u8]> {
+    let Some((&first, rest)) = path.split_first() else {
+        return vec![value];
+    };
+    split_tlvs(value)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(typ, _)| *typ as u32 == first)
+        .flat_map(|(_, member)| tlv_members(member, rest))
+        .collect()
+}
+
+/// A `tlv` value without the sub-attributes at `path`. Containers left
+/// empty are dropped too.
+fn tlv_remove(value: &[u8], path: &[u32]) -> Vec<u8> {
+    let Some((&first, rest)) = path.split_first() else {
+        return Vec::new();
+    };
+    let Ok(tlvs) = split_tlvs(value) else {
+        return value.to_vec();
+    };
+    let mut out = Vec::new();
+    for (typ, member) in tlvs {
+        let member = if typ as u32 == first { tlv_remove(member, rest) } else { member.to_vec() };
+        if typ as u32 != first || !member.is_empty() {
+            out.extend_from_slice(&[typ, (member.len() + 2) as u8]);
+            out.extend(member);
+        }
+    }
+    out
+}
+
+/// Wraps `data` in the sub-attribute headers along `path`, innermost last.
+fn wrap_tlvs(path: &[u32], mut data: Vec<u8>) -> Result<Vec<u8>, RadiusError> {
+    for &code in path.iter().rev() {
+        let typ = code as u8;
+        if data.len() > MAX_ATTRIBUTE_VALUE_LEN {
+            return Err(RadiusError::AttributeTooLong { typ, len: data.len() });
+        }
+        data.splice(0..0, [typ, (data.len() + 2) as u8]);
+    }
+    Ok(data)
+}
+
+/// The attribute type byte for a dictionary code. Server-internal
+/// attributes (above 255) never appear in packets.
+fn wire_type(name: &str, code: u32) -> Result<u8, RadiusError> {
+    u8::try_from(code).map_err(|_| RadiusError::InternalAttribute {
+        name: name.to_string(),
+        code,
+    })
+}
+
+/// Whether `typ` is one of the Extended-Type attributes 241–246 (RFC 6929).
+pub fn is_extended_type(typ: u8) -> bool {
+    (ATTR_EXTENDED_1..=ATTR_LONG_EXTENDED_2).contains(&typ)
+}
+
+/// Whether `typ` is a Long-Extended-Type attribute (245 or 246), whose
+/// values can span several attributes.
+pub fn is_long_extended_type(typ: u8) -> bool {
+    matches!(typ, ATTR_LONG_EXTENDED_1 | ATTR_LONG_EXTENDED_2)
+}
+
+/// An RFC 6929 attribute such as `241.1` (Frag-Status), with its value
+/// independent of how it was split on the wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedAttribute {
+    pub typ: u8,
+    pub ext_type: u8,
+    pub value: Vec<u8>,
+}
+
+impl ExtendedAttribute {
+    pub fn new(typ: u8, ext_type: u8, value: Vec<u8>) -> Self {
+        ExtendedAttribute { typ, ext_type, value }
+    }
+
+    /// Encodes the attribute. Extended-Type values are limited to 252
+    /// bytes; Long-Extended-Type values are split into fragments of up to
+    /// 251 bytes, all but the last with the More flag set.
+    pub fn to_attributes(&self) -> Result<Vec<RadiusAttribute>, RadiusError> {
+        if !is_extended_type(self.typ) {
+            return Err(RadiusError::InvalidAttribute {
+                typ: self.typ,
+                reason: "not an Extended-Type attribute".to_string(),
+            });
+        }
+
+        if !is_long_extended_type(self.typ) {
+            let mut value = vec![self.ext_type];
+            value.extend_from_slice(&self.value);
+            return Ok(vec![RadiusAttribute::new(self.typ, value)?]);
+        }
+
+        let mut chunks: Vec<&[u8]> = self.value.chunks(MAX_ATTRIBUTE_VALUE_LEN - 2).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        let last = chunks.len() - 1;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let flags = if i < last { LONG_EXTENDED_MORE } else { 0 };
+                let mut value = vec![self.ext_type, flags];
+                value.extend_from_slice(chunk);
+                RadiusAttribute::new(self.typ, value)
+            })
+            .collect()
+    }
+}
+
+/// A received packet borrowed from the receive buffer. The framing is
+/// validated once by [`parse`](Self::parse); attributes are then read as
+/// slices of the buffer without copying.
+#[derive(Debug, Clone, Copy)]
+pub struct RadiusPacketRef<'a> {
+    buf: &'a [u8],
+}
+
+/// One attribute of a [`RadiusPacketRef`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadiusAttributeRef<'a> {
+    pub typ: u8,
+    pub value: &'a [u8],
+}
+
+impl RadiusAttributeRef<'_> {
+    pub fn to_attribute(&self) -> RadiusAttribute {
+        RadiusAttribute::fixed(self.typ, self.value.to_vec())
+    }
+}
+
+impl<'a> RadiusPacketRef<'a> {
+    /// Checks the header and attribute framing. Bytes after the header's
+    /// Length (UDP padding) are ignored.
+    pub fn parse(buf: &'a [u8]) -> Result<Self, RadiusError> {
+        if buf.len() < 20 {
+            return Err(RadiusError::TruncatedHeader { len: buf.len() });
+        }
+
+        let length = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if !(20..=MAX_PACKET_LEN).contains(&length) {
+            return Err(RadiusError::BadPacketLength { len: length });
+        }
+        if buf.len() < length {
+            return Err(RadiusError::LengthMismatch {
+                header: length,
+                actual: buf.len(),
+            });
+        }
+
+        let mut i = 20;
+        while i < length {
+            if i + 2 > length {
+                return Err(RadiusError::TruncatedAttribute { offset: i });
+            }
+
+            let len = buf[i + 1] as usize;
+            if len < 2 || i + len > length {
+                return Err(RadiusError::BadAttributeLength { offset: i, len });
+            }
+
+            i += len;
+        }
+
+        Ok(RadiusPacketRef { buf: &buf[..length] })
+    }
+
+    pub fn code(&self) -> RadiusCode {
+        RadiusCode::from(self.buf[0])
+    }
+
+    pub fn identifier(&self) -> u8 {
+        self.buf[1]
+    }
+
+    pub fn length(&self) -> u16 {
+        self.buf.len() as u16
+    }
+
+    pub fn authenticator(&self) -> [u8; 16] {
+        self.buf[4..20].try_into().unwrap()
+    }
+
+    /// The packet as received, up to its Length.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    pub fn attributes(&self) -> AttributeIter<'a> {
+        AttributeIter { buf: &self.buf[20..] }
+    }
+
+    /// The value of the first attribute of type `typ`.
+    pub fn find(&self, typ: u8) -> Option<&'a [u8]> {
+        self.attributes().find(|a| a.typ == typ).map(|a| a.value)
+    }
+
+    /// Copies the packet into an owned [`RadiusPacket`].
+    pub fn to_packet(&self) -> RadiusPacket {
+        RadiusPacket {
+            code: self.code(),
+            identifier: self.identifier(),
+            length: self.length(),
+            authenticator: self.authenticator(),
+            attributes: self.attributes().map(|a| a.to_attribute()).collect(),
+        }
+    }
+}
+
+impl From<RadiusPacketRef<'_>> for RadiusPacket {
+    fn from(packet: RadiusPacketRef<'_>) -> Self {
+        packet.to_packet()
+    }
+}
+
+/// Iterator over the attributes of a [`RadiusPacketRef`].
+#[derive(Debug, Clone)]
+pub struct AttributeIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for AttributeIter<'a> {
+    type Item = RadiusAttributeRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The framing was validated by `RadiusPacketRef::parse`.
+        let (&typ, rest) = self.buf.split_first()?;
+        let len = rest[0] as usize;
+        let value = &rest[1..len - 1];
+        self.buf = &self.buf[len..];
+        Some(RadiusAttributeRef { typ, value })
+    }
+}
+
+/// The RFC 1994 CHAP response: `MD5(id + secret + challenge)`.
+pub fn chap_response(chap_id: u8, password: &[u8], challenge: &[u8]) -> [u8; 16] {
+    let mut data = vec![chap_id];
+    data.extend_from_slice(password);
+    data.extend_from_slice(challenge);
+    md5::compute(&data).0
+}
+
+/// Hides a password per RFC 2865 §5.2: the cleartext is NUL-padded to a
+/// multiple of 16 bytes and XORed block by block with
+/// `MD5(secret + previous)`, where `previous` starts as the Request
+/// Authenticator and then chains through each ciphertext block.
+/// Passwords longer than 128 bytes cannot be hidden.
+pub fn hide_password(password: &[u8], secret: &str, authenticator: &[u8; 16]) -> Result<Vec<u8>, RadiusError> {
+    if password.len() > MAX_PASSWORD_LEN {
+        return Err(RadiusError::InvalidAttribute {
+            typ: ATTR_USER_PASSWORD,
+            reason: format!("password of {} bytes exceeds the {}-byte maximum", password.len(), MAX_PASSWORD_LEN),
+        });
+    }
+    let padded_len = password.len().div_ceil(16).max(1) * 16;
+    let mut out = password.to_vec();
+    out.resize(padded_len, 0);
+
+    let mut previous = authenticator.to_vec();
+    for block in out.chunks_mut(16) {
+        let mut data = secret.as_bytes().to_vec();
+        data.extend_from_slice(&previous);
+        let key = md5::compute(&data);
+
+        for (b, k) in block.iter_mut().zip(key.0.iter()) {
+            *b ^= k;
+        }
+        previous = block.to_vec();
+    }
+
+    Ok(out)
+}
+
+/// Reverses [`hide_password`], stripping the trailing NUL padding.
+pub fn unhide_password(hidden: &[u8], secret: &str, authenticator: &[u8; 16]) -> Result<Vec<u8>, RadiusError> {
+    let problem = if hidden.is_empty() {
+        Some("is empty".to_string())
+    } else if hidden.len() > MAX_PASSWORD_LEN {
+        Some(format!("exceeds the {}-byte maximum", MAX_PASSWORD_LEN))
+    } else if !hidden.len().is_multiple_of(16) {
+        Some("is not a multiple of 16".to_string())
+    } else {
+        None
+    };
+    if let Some(problem) = problem {
+        return Err(RadiusError::InvalidAttribute {
+            typ: ATTR_USER_PASSWORD,
+            reason: format!("hidden length {} {}", hidden.len(), problem),
+        });
+    }
+
+    let mut out = Vec::with_capacity(hidden.len());
+    let mut previous: &[u8] = authenticator;
+    for block in hidden.chunks(16) {
+        let mut data = secret.as_bytes().to_vec();
+        data.extend_from_slice(previous);
+        let key = md5::compute(&data);
+
+        out.extend(block.iter().zip(key.0.iter()).map(|(b, k)| b ^ k));
+        previous = block;
+    }
+
+    while out.last() == Some(&0) {
+        out.pop();
+    }
+
+    Ok(out)
+}
+
+/// Encrypts an attribute value as its dictionary `encrypt=` option asks.
+/// Tunnel-Password values get a fresh random salt.
+fn encrypt_value(
+    method: Encryption,
+    data: &[u8],
+    secret: &str,
+    authenticator: &[u8; 16],
+) -> Result<Vec<u8>, RadiusError> {
+    Ok(match method {
+        Encryption::UserPassword => hide_password(data, secret, authenticator)?,
+        Encryption::TunnelPassword => {
+            // RFC 2868 uses the same salted scheme as the MS-MPPE keys.
+            let mut salt = [0u8; 2];
+            rand::rng().fill_bytes(&mut salt);
+            encrypt_mppe_key(data, secret, authenticator, salt)
+        }
+        Encryption::AscendSecret => ascend_secret(data, secret, authenticator),
+    })
+}
+
+/// Reverses [`encrypt_value`].
+fn decrypt_value(method: Encryption, data: &[u8], secret: &str, authenticator: &[u8; 16]) -> Result<Vec<u8>, RadiusError> {
+    match method {
+        Encryption::UserPassword => unhide_password(data, secret, authenticator),
+        Encryption::TunnelPassword => {
+            decrypt_mppe_key(data, secret, authenticator).map_err(|e| match e {
+                RadiusError::InvalidAttribute { reason, .. } => RadiusError::InvalidAttribute {
+                    typ: ATTR_TUNNEL_PASSWORD,
+                    reason,
+                },
+                e => e,
+            })
+        }
+        Encryption::AscendSecret => {
+            let mut clear = ascend_secret(data, secret, authenticator);
+            while clear.last() == Some(&0) {
+                clear.pop();
+            }
+            Ok(clear)
+        }
+    }
+}
+
+/// Ascend's Ascend-Send-Secret/Ascend-Receive-Secret obfuscation: the
+/// value, NUL-padded to 16 bytes, XORed with `MD5(authenticator + secret)`.
+fn ascend_secret(data: &[u8], secret: &str, authenticator: &[u8; 16]) -> Vec<u8> {
+    let mut input = authenticator.to_vec();
+    input.extend_from_slice(secret.as_bytes());
+    let key = md5::compute(&input);
+
+    let mut out = data[..data.len().min(16)].to_vec();
+    out.resize(16, 0);
+    out.iter_mut().zip(key.0.iter()).for_each(|(b, k)| *b ^= k);
+    out
 }
 
 fn radius_type_name(typ: u8) -> &'static str {
@@ -277,32 +1341,69 @@ fn radius_type_name(typ: u8) -> &'static str {
         3 => "CHAP-Password",
         4 => "NAS-IP-Address",
         18 => "Reply-Message",
+        24 => "State",
         26 => "Vendor-Specific",
         27 => "Session-Timeout",
         28 => "Idle-Timeout",
+        60 => "CHAP-Challenge",
+        79 => "EAP-Message",
         80 => "Message-Authenticator",
+        241 => "Extended-Attribute-1",
+        242 => "Extended-Attribute-2",
+        243 => "Extended-Attribute-3",
+        244 => "Extended-Attribute-4",
+        245 => "Extended-Attribute-5",
+        246 => "Extended-Attribute-6",
         _ => "Unknown",
     }
 }
 
-fn decode_vendor_specific(value: &[u8]) -> String {
-    if value.len() < 6 {
-        return "Invalid VSA".to_string();
-    }
+type SubAttributes<'a> = Vec<(u8, &'a [u8])>;
 
-    let vendor_id = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
-    let vendor_type = value.get(4).copied().unwrap_or(0);
-    let vendor_len = value.get(5).copied().unwrap_or(0) as usize;
+/// A value and its RFC 2868 tag (0 if none).
+type TaggedBytes = (u8, Vec<u8>);
 
-    let data_start = 6;
-    let data_end = data_start + (vendor_len.saturating_sub(2));
-    let payload = &value.get(data_start..data_end).unwrap_or(&[]);
+/// Splits a Vendor-Specific value into its vendor ID and the
+/// (vendor type, data) sub-attributes of the common RFC 2865 format.
+/// Returns `None` when the value does not follow that format.
+fn vendor_sub_attributes(value: &[u8]) -> Option<(u32, SubAttributes<'_>)> {
+    let vendor_id = u32::from_be_bytes(value.get(..4)?.try_into().unwrap());
+    let mut subs = Vec::new();
+    let mut rest = &value[4..];
+    while !rest.is_empty() {
+        let len = *rest.get(1)? as usize;
+        if len < 2 || len > rest.len() {
+            return None;
+        }
+        subs.push((rest[0], &rest[2..len]));
+        rest = &rest[len..];
+    }
+    Some((vendor_id, subs))
+}
+
+/// Describes a Vendor-Specific value. Sub-attributes defined in `dict`
+/// are shown as `Name = Value`; the rest by vendor ID, type and data.
+pub(crate) fn decode_vendor_specific(value: &[u8], dict: Option<&Dictionary>) -> String {
+    let Some((vendor_id, subs)) = vendor_sub_attributes(value).filter(|(_, subs)| !subs.is_empty()) else {
+        return "Invalid VSA".to_string();
+    };
 
-    format!("VendorID={}, Type={}, Data={:?}", vendor_id, vendor_type, payload)
+    let parts: Vec<String> = subs
+        .into_iter()
+        .map(|(typ, data)| {
+            let key = AttributeKey::Vendor(vendor_id, typ as u32);
+            match dict.and_then(|d| d.get(key).map(|def| (d, def))) {
+                Some((d, def)) => format!("{} = {}", def.name, d.format_value(key, data)),
+                None => format!("VendorID={}, Type={}, Data={:?}", vendor_id, typ, data),
+            }
+        })
+        .collect();
+    parts.join("; ")
 }
+
 #[derive(Debug, Clone)]
 pub struct AccountingPacket {
-    pub code: u8,
+    pub code: RadiusCode,
     pub identifier: u8,
     pub length: u16,
     pub authenticator: [u8; 16],
@@ -323,18 +1424,38 @@ pub enum AccountingAttribute {
 }
 impl From<RadiusPacket> for AccountingPacket {
     fn from(pkt: RadiusPacket) -> Self {
+        AccountingPacket::convert(pkt, None)
+    }
+}
+
+impl AccountingPacket {
+    /// Like the `From` conversion, but names Acct-Status-Type and
+    /// Acct-Terminate-Cause values from the dictionary's VALUE lines.
+    pub fn from_packet(pkt: RadiusPacket, dict: &Dictionary) -> Self {
+        AccountingPacket::convert(pkt, Some(dict))
+    }
+
+    fn convert(pkt: RadiusPacket, dict: Option<&Dictionary>) -> Self {
+        let value_name = |typ: u8, value: u32| {
+            dict.and_then(|d| d.value_names(AttributeKey::Standard(typ as u32)))
+                .and_then(|names| names.name(value as u64))
+                .map(str::to_string)
+        };
         let attributes = pkt.attributes.into_iter().map(|attr| {
             match attr.typ {
                 1 => AccountingAttribute::UserName(String::from_utf8_lossy(&attr.value).to_string()),
                 40 => { // Acct-Status-Type
                     let v = u32::from_be_bytes(attr.value.try_into().unwrap_or([0,0,0,0]));
-                    let status = match v {
-                        1 => "Start",
-                        2 => "Stop",
-                        3 => "Interim-Update",
-                        _ => "Unknown",
-                    };
-                    AccountingAttribute::AcctStatusType(status.to_string())
+                    let status = value_name(ATTR_ACCT_STATUS_TYPE, v).unwrap_or_else(|| {
+                        match v {
+                            1 => "Start",
+                            2 => "Stop",
+                            3 => "Interim-Update",
+                            _ => "Unknown",
+                        }
+                        .to_string()
+                    });
+                    AccountingAttribute::AcctStatusType(status)
                 }
                 44 => AccountingAttribute::AcctSessionId(String::from_utf8_lossy(&attr.value).to_string()),
                 46 => {
@@ -351,13 +1472,16 @@ impl From<RadiusPacket> for AccountingPacket {
                 }
                 49 => { // Acct-Terminate-Cause
                     let v = u32::from_be_bytes(attr.value.try_into().unwrap_or([0,0,0,0]));
-                    let cause = match v {
-                        1 => "User-Request",
-                        2 => "Lost-Carrier",
-                        3 => "Lost-Service",
-                        _ => "Other",
-                    };
-                    AccountingAttribute::AcctTerminateCause(cause.to_string())
+                    let cause = value_name(49, v).unwrap_or_else(|| {
+                        match v {
+                            1 => "User-Request",
+                            2 => "Lost-Carrier",
+                            3 => "Lost-Service",
+                            _ => "Other",
+                        }
+                        .to_string()
+                    });
+                    AccountingAttribute::AcctTerminateCause(cause)
                 }
                 4 => {
                     if attr.value.len() == 4 {
@@ -379,3 +1503,129 @@ impl From<RadiusPacket> for AccountingPacket {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DICT: &str = "\
+ATTRIBUTE\tUser-Name\t1\tstring
+ATTRIBUTE\tSession-Timeout\t27\tinteger
+";
+
+    fn request() -> RadiusPacket {
+        RadiusPacket {
+            code: RadiusCode::AccessRequest,
+            identifier: 1,
+            length: 0,
+            authenticator: [0x5a; 16],
+            attributes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hides_rfc_2865_example_password() {
+        // RFC 2865 §7.1: "arctangent" with secret "xyzzy5461".
+        let authenticator = 0x0f403f9473978057bd83d5cb98f4227a_u128.to_be_bytes();
+        let hidden = hide_password(b"arctangent", "xyzzy5461", &authenticator).unwrap();
+        assert_eq!(hidden, 0x0dbe708d93d413ce3196e43f782a0aee_u128.to_be_bytes());
+        assert_eq!(unhide_password(&hidden, "xyzzy5461", &authenticator).unwrap(), b"arctangent");
+    }
+
+    #[test]
+    fn password_round_trips_at_block_boundaries() {
+        let authenticator = [0x5a; 16];
+        for (len, hidden_len) in [(16, 16), (17, 32), (128, 128)] {
+            let password = vec![b'p'; len];
+            let hidden = hide_password(&password, "s3cret", &authenticator).unwrap();
+            assert_eq!(hidden.len(), hidden_len);
+            assert_ne!(&hidden[..16], &password[..16]);
+            assert_eq!(unhide_password(&hidden, "s3cret", &authenticator).unwrap(), password);
+            assert_ne!(unhide_password(&hidden, "wrong", &authenticator).unwrap(), password);
+        }
+        assert!(hide_password(&[b'p'; 129], "s3cret", &authenticator).is_err());
+        assert!(unhide_password(&[0; 17], "s3cret", &authenticator).is_err());
+    }
+
+    #[test]
+    fn packet_recovers_user_password() {
+        let mut packet = request();
+        let attr = RadiusAttribute::user_password("correct horse", "s3cret", &packet.authenticator).unwrap();
+        packet.attributes.push(attr);
+        assert_eq!(packet.user_password("s3cret").as_deref(), Some("correct horse"));
+    }
+
+    #[test]
+    fn set_replaces_every_value() {
+        let dict = Dictionary::parse_from_str(DICT).unwrap();
+        let mut packet = request();
+        packet.add_typed(&dict, "Session-Timeout", AttributeValue::Integer(60)).unwrap();
+        packet.add_typed(&dict, "Session-Timeout", AttributeValue::Integer(120)).unwrap();
+
+        packet.set(&dict, "Session-Timeout", AttributeValue::Integer(3600)).unwrap();
+        assert_eq!(packet.get_all_typed(&dict, "Session-Timeout").unwrap(), [AttributeValue::Integer(3600)]);
+    }
+
+    #[test]
+    fn failed_set_keeps_the_existing_value() {
+        let dict = Dictionary::parse_from_str(DICT).unwrap();
+        let mut packet = request();
+        packet.add_typed(&dict, "User-Name", AttributeValue::String("alice".into())).unwrap();
+
+        assert!(packet.set(&dict, "User-Name", AttributeValue::Integer(7)).is_err());
+        assert!(packet.set(&dict, "User-Name", AttributeValue::String("x".repeat(300))).is_err());
+        assert_eq!(packet.get_typed(&dict, "User-Name").unwrap(), Some(AttributeValue::String("alice".into())));
+    }
+
+    #[test]
+    fn long_extended_values_fragment_and_reassemble() {
+        let value: Vec<u8> = (0..600).map(|i| i as u8).collect();
+        let fragments = RadiusAttribute::extended(ATTR_LONG_EXTENDED_1, 1, &value).unwrap();
+        let lengths: Vec<usize> = fragments.iter().map(|a| a.value.len()).collect();
+        assert_eq!(lengths, [253, 253, 100]);
+        let flags: Vec<u8> = fragments.iter().map(|a| a.value[1]).collect();
+        assert_eq!(flags, [LONG_EXTENDED_MORE, LONG_EXTENDED_MORE, 0]);
+
+        let mut packet = request();
+        packet.attributes = fragments;
+        packet.attributes.push(RadiusAttribute::session_timeout(60));
+        let packet = RadiusPacket::from_bytes(&packet.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            packet.extended_attributes().unwrap(),
+            [ExtendedAttribute::new(ATTR_LONG_EXTENDED_1, 1, value.clone())]
+        );
+        assert_eq!(packet.extended(ATTR_LONG_EXTENDED_1, 1), Some(value));
+
+        for (len, count) in [(0, 1), (251, 1), (252, 2)] {
+            let fragments = RadiusAttribute::extended(ATTR_LONG_EXTENDED_2, 2, &vec![0; len]).unwrap();
+            assert_eq!(fragments.len(), count);
+        }
+    }
+
+    #[test]
+    fn rejects_broken_long_extended_fragments() {
+        let value = vec![0x42; 300];
+        let fragments = RadiusAttribute::extended(ATTR_LONG_EXTENDED_1, 1, &value).unwrap();
+        let reassemble = |attributes: Vec<RadiusAttribute>| {
+            let mut packet = request();
+            packet.attributes = attributes;
+            packet.extended_attributes()
+        };
+
+        // Truncated: the continuation never arrives.
+        let err = reassemble(fragments[..1].to_vec()).unwrap_err();
+        assert!(err.to_string().contains("More flag"), "{}", err);
+
+        // Interrupted by an unrelated attribute.
+        let interrupted = vec![fragments[0].clone(), RadiusAttribute::session_timeout(60), fragments[1].clone()];
+        assert!(reassemble(interrupted).is_err());
+
+        // Continued by a different Extended-Type.
+        let mut other = fragments[1].clone();
+        other.value[0] = 2;
+        assert!(reassemble(vec![fragments[0].clone(), other]).is_err());
+
+        let missing_flags = RadiusAttribute::new(ATTR_LONG_EXTENDED_1, vec![1]).unwrap();
+        assert!(reassemble(vec![missing_flags]).is_err());
+    }
+}
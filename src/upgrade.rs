@@ -0,0 +1,121 @@
+use crate::error::RadiusError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The table bundled with the crate, from FreeRADIUS' upgrade tool.
+const EMBEDDED_UPGRADE: &str = include_str!("../dictionaries/v3/upgrade.txt");
+
+/// Attribute names in FreeRADIUS v3 and their v4 equivalents, e.g.
+/// `Cleartext-Password` and `Password.Cleartext`. Names not in the table
+/// are the same in both.
+#[derive(Debug, Clone, Default)]
+pub struct NameMap {
+    to_v4: HashMap<String, String>,
+    to_v3: HashMap<String, String>,
+}
+
+impl NameMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bundled `dictionaries/v3/upgrade.txt`.
+    pub fn embedded() -> Self {
+        Self::parse_from_str(EMBEDDED_UPGRADE).expect("bundled upgrade table is valid")
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, RadiusError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|source| RadiusError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        })?;
+        Self::parse_from_str(&content).map_err(|e| e.in_file(path))
+    }
+
+    /// Parses `<v3 name> <v4 name>` lines; `#` starts a comment.
+    pub fn parse_from_str(content: &str) -> Result<Self, RadiusError> {
+        let mut map = Self::new();
+        for (lineno, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [v3, v4] => map.insert(v3, v4),
+                _ => {
+                    return Err(RadiusError::DictionaryParse {
+                        file: None,
+                        line: lineno + 1,
+                        message: "expected a v3 name and a v4 name".to_string(),
+                    });
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    /// Adds (or replaces) a mapping.
+    pub fn insert(&mut self, v3: &str, v4: &str) {
+        self.to_v4.insert(v3.to_string(), v4.to_string());
+        self.to_v3.insert(v4.to_string(), v3.to_string());
+    }
+
+    /// The v4 name of a v3 attribute, if it changed.
+    pub fn to_v4(&self, v3: &str) -> Option<&str> {
+        self.to_v4.get(v3).map(String::as_str)
+    }
+
+    /// The v3 name of a v4 attribute, if it changed.
+    pub fn to_v3(&self, v4: &str) -> Option<&str> {
+        self.to_v3.get(v4).map(String::as_str)
+    }
+
+    /// `name` in the v4 scheme, whichever scheme it was written in.
+    pub fn v4_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.to_v4(name).unwrap_or(name)
+    }
+
+    /// `name` in the v3 scheme, whichever scheme it was written in.
+    pub fn v3_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.to_v3(name).unwrap_or(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.to_v4.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_v4.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_names_both_ways() {
+        let map = NameMap::parse_from_str("# v3\tv4\nCleartext-Password\tPassword.Cleartext\n").unwrap();
+        assert_eq!(map.to_v4("Cleartext-Password"), Some("Password.Cleartext"));
+        assert_eq!(map.to_v3("Password.Cleartext"), Some("Cleartext-Password"));
+        assert_eq!(map.to_v4("User-Name"), None);
+        assert_eq!(map.v4_name("Cleartext-Password"), "Password.Cleartext");
+        assert_eq!(map.v4_name("User-Name"), "User-Name");
+        assert_eq!(map.v3_name("Password.Cleartext"), "Cleartext-Password");
+    }
+
+    #[test]
+    fn embedded_table_covers_passwords() {
+        let map = NameMap::embedded();
+        assert_eq!(map.v4_name("Cleartext-Password"), "Password.Cleartext");
+        assert!(!map.is_empty());
+    }
+
+    #[test]
+    fn rejects_lines_without_two_names() {
+        let err = NameMap::parse_from_str("Cleartext-Password\n").unwrap_err();
+        assert!(matches!(err, RadiusError::DictionaryParse { line: 1, .. }));
+    }
+}
@@ -0,0 +1,353 @@
+//! Attribute values decoded according to their dictionary data type
+//! (RFC 8044).
+
+use crate::error::RadiusError;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// A typed attribute value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeValue {
+    String(String),
+    Octets(Vec<u8>),
+    Integer(u32),
+    Integer64(u64),
+    IpAddr(Ipv4Addr),
+    Ipv6Addr(Ipv6Addr),
+    /// An IPv6 prefix and its length in bits.
+    Ipv6Prefix(Ipv6Addr, u8),
+    /// An IPv6 interface identifier.
+    Ifid([u8; 8]),
+    /// Seconds since the Unix epoch.
+    Date(u32),
+    Ether([u8; 6]),
+    Byte(u8),
+    Short(u16),
+    Signed(i32),
+    /// Sub-attributes as (type, value) pairs.
+    Tlv(Vec<(u8, Vec<u8>)>),
+}
+
+/// The dictionary type name without any `[size]` suffix, with the
+/// FreeRADIUS v4 spellings mapped onto their v3 equivalents.
+fn normalize(data_type: &str) -> &str {
+    let base = data_type.split('[').next().unwrap_or(data_type);
+    match base {
+        "uint8" => "byte",
+        "uint16" => "short",
+        "uint32" => "integer",
+        "uint64" => "integer64",
+        "int32" => "signed",
+        "ipv4addr" => "ipaddr",
+        other => other,
+    }
+}
+
+fn fixed<const N: usize>(data_type: &'static str, bytes: &[u8]) -> Result<[u8; N], RadiusError> {
+    bytes.try_into().map_err(|_| RadiusError::ValueLength {
+        data_type,
+        expected: N.to_string(),
+        actual: bytes.len(),
+    })
+}
+
+impl AttributeValue {
+    /// Decodes `bytes` as the dictionary type `data_type`. Types without a
+    /// dedicated variant are returned as [`Octets`](Self::Octets).
+    pub fn decode(data_type: &str, bytes: &[u8]) -> Result<Self, RadiusError> {
+        Ok(match normalize(data_type) {
+            "string" => AttributeValue::String(String::from_utf8_lossy(bytes).into_owned()),
+            "integer" => AttributeValue::Integer(u32::from_be_bytes(fixed("integer", bytes)?)),
+            "integer64" => AttributeValue::Integer64(u64::from_be_bytes(fixed("integer64", bytes)?)),
+            "ipaddr" => AttributeValue::IpAddr(Ipv4Addr::from(fixed::<4>("ipaddr", bytes)?)),
+            "ipv6addr" => AttributeValue::Ipv6Addr(Ipv6Addr::from(fixed::<16>("ipv6addr", bytes)?)),
+            "ipv6prefix" => decode_ipv6_prefix(bytes)?,
+            "ifid" => AttributeValue::Ifid(fixed("ifid", bytes)?),
+            "date" => AttributeValue::Date(u32::from_be_bytes(fixed("date", bytes)?)),
+            "ether" => AttributeValue::Ether(fixed("ether", bytes)?),
+            "byte" => AttributeValue::Byte(u8::from_be_bytes(fixed("byte", bytes)?)),
+            "short" => AttributeValue::Short(u16::from_be_bytes(fixed("short", bytes)?)),
+            "signed" => AttributeValue::Signed(i32::from_be_bytes(fixed("signed", bytes)?)),
+            "tlv" => decode_tlv(bytes)?,
+            _ => AttributeValue::Octets(bytes.to_vec()),
+        })
+    }
+
+    /// The wire form of the value.
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            AttributeValue::String(s) => s.as_bytes().to_vec(),
+            AttributeValue::Octets(b) => b.clone(),
+            AttributeValue::Integer(v) => v.to_be_bytes().to_vec(),
+            AttributeValue::Integer64(v) => v.to_be_bytes().to_vec(),
+            AttributeValue::IpAddr(ip) => ip.octets().to_vec(),
+            AttributeValue::Ipv6Addr(ip) => ip.octets().to_vec(),
+            AttributeValue::Ipv6Prefix(ip, len) => {
+                // Only the octets covering the prefix are sent (RFC 3162 §2.3).
+                let mut out = vec![0, *len];
+                out.extend_from_slice(&ip.octets()[..(*len as usize).div_ceil(8).min(16)]);
+                out
+            }
+            AttributeValue::Ifid(b) => b.to_vec(),
+            AttributeValue::Date(v) => v.to_be_bytes().to_vec(),
+            AttributeValue::Ether(b) => b.to_vec(),
+            AttributeValue::Byte(v) => vec![*v],
+            AttributeValue::Short(v) => v.to_be_bytes().to_vec(),
+            AttributeValue::Signed(v) => v.to_be_bytes().to_vec(),
+            AttributeValue::Tlv(tlvs) => {
+                let mut out = Vec::new();
+                for (typ, value) in tlvs {
+                    out.push(*typ);
+                    out.push((value.len() + 2) as u8);
+                    out.extend_from_slice(value);
+                }
+                out
+            }
+        }
+    }
+
+    /// The dictionary type this value encodes as.
+    pub fn data_type(&self) -> &'static str {
+        match self {
+            AttributeValue::String(_) => "string",
+            AttributeValue::Octets(_) => "octets",
+            AttributeValue::Integer(_) => "integer",
+            AttributeValue::Integer64(_) => "integer64",
+            AttributeValue::IpAddr(_) => "ipaddr",
+            AttributeValue::Ipv6Addr(_) => "ipv6addr",
+            AttributeValue::Ipv6Prefix(..) => "ipv6prefix",
+            AttributeValue::Ifid(_) => "ifid",
+            AttributeValue::Date(_) => "date",
+            AttributeValue::Ether(_) => "ether",
+            AttributeValue::Byte(_) => "byte",
+            AttributeValue::Short(_) => "short",
+            AttributeValue::Signed(_) => "signed",
+            AttributeValue::Tlv(_) => "tlv",
+        }
+    }
+
+    /// The number held by an integer-like value, as used by VALUE names.
+    /// Signed values give their 32-bit two's complement.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            AttributeValue::Byte(v) => Some(*v as u64),
+            AttributeValue::Short(v) => Some(*v as u64),
+            AttributeValue::Integer(v) => Some(*v as u64),
+            AttributeValue::Integer64(v) => Some(*v),
+            AttributeValue::Signed(v) => Some(*v as u32 as u64),
+            _ => None,
+        }
+    }
+
+    /// Builds an integer-like value of `data_type` from a number, or
+    /// `None` if the type is not numeric or the number does not fit.
+    pub fn from_number(data_type: &str, n: u64) -> Option<Self> {
+        Some(match normalize(data_type) {
+            "byte" => AttributeValue::Byte(u8::try_from(n).ok()?),
+            "short" => AttributeValue::Short(u16::try_from(n).ok()?),
+            "integer" => AttributeValue::Integer(u32::try_from(n).ok()?),
+            "integer64" => AttributeValue::Integer64(n),
+            "signed" => AttributeValue::Signed(u32::try_from(n).ok()? as i32),
+            _ => return None,
+        })
+    }
+
+    /// The wire size of every value of `data_type`, or `None` for
+    /// variable-length types.
+    pub fn fixed_size(data_type: &str) -> Option<usize> {
+        // `octets[16]`, `string[4]`
+        if let Some((base, size)) = data_type.strip_suffix(']').and_then(|t| t.split_once('['))
+            && matches!(base, "octets" | "string")
+        {
+            return size.parse().ok();
+        }
+        Some(match normalize(data_type) {
+            "byte" => 1,
+            "short" => 2,
+            "integer" | "ipaddr" | "date" | "signed" => 4,
+            "ether" => 6,
+            "integer64" | "ifid" => 8,
+            "ipv6addr" => 16,
+            _ => return None,
+        })
+    }
+
+    /// Whether this value may be stored in an attribute of `data_type`.
+    /// Octets are accepted for any type.
+    pub fn fits(&self, data_type: &str) -> bool {
+        match self {
+            AttributeValue::Octets(_) => true,
+            _ => self.data_type() == normalize(data_type),
+        }
+    }
+}
+
+fn decode_ipv6_prefix(bytes: &[u8]) -> Result<AttributeValue, RadiusError> {
+    let bad = || RadiusError::ValueLength {
+        data_type: "ipv6prefix",
+        expected: "2 to 18".to_string(),
+        actual: bytes.len(),
+    };
+    if !(2..=18).contains(&bytes.len()) || bytes[1] > 128 {
+        return Err(bad());
+    }
+    let len = bytes[1];
+    if bytes.len() - 2 < (len as usize).div_ceil(8) {
+        return Err(bad());
+    }
+    let mut octets = [0u8; 16];
+    octets[..bytes.len() - 2].copy_from_slice(&bytes[2..]);
+    Ok(AttributeValue::Ipv6Prefix(Ipv6Addr::from(octets), len))
+}
+
+fn decode_tlv(bytes: &[u8]) -> Result<AttributeValue, RadiusError> {
+    let tlvs = split_tlvs(bytes)?;
+    Ok(AttributeValue::Tlv(tlvs.into_iter().map(|(typ, value)| (typ, value.to_vec())).collect()))
+}
+
+/// Splits a `tlv` value into its (type, value) sub-attributes.
+pub(crate) fn split_tlvs(mut bytes: &[u8]) -> Result<Vec<(u8, &[u8])>, RadiusError> {
+    let mut tlvs = Vec::new();
+    while !bytes.is_empty() {
+        let len = bytes.get(1).copied().unwrap_or(0) as usize;
+        if len < 2 || len > bytes.len() {
+            return Err(RadiusError::ValueLength {
+                data_type: "tlv",
+                expected: format!("a sub-attribute of 2 to {} bytes", bytes.len()),
+                actual: len,
+            });
+        }
+        tlvs.push((bytes[0], &bytes[2..len]));
+        bytes = &bytes[len..];
+    }
+    Ok(tlvs)
+}
+
+fn hex(bytes: &[u8], sep: &str) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(sep)
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::String(s) => f.write_str(s),
+            AttributeValue::Octets(b) => write!(f, "0x{}", hex(b, "")),
+            AttributeValue::Integer(v) => write!(f, "{v}"),
+            AttributeValue::Integer64(v) => write!(f, "{v}"),
+            AttributeValue::IpAddr(ip) => write!(f, "{ip}"),
+            AttributeValue::Ipv6Addr(ip) => write!(f, "{ip}"),
+            AttributeValue::Ipv6Prefix(ip, len) => write!(f, "{ip}/{len}"),
+            AttributeValue::Ifid(b) => {
+                let groups: Vec<String> = b.chunks(2).map(|g| hex(g, "")).collect();
+                f.write_str(&groups.join(":"))
+            }
+            AttributeValue::Date(v) => write!(f, "{v}"),
+            AttributeValue::Ether(b) => f.write_str(&hex(b, ":")),
+            AttributeValue::Byte(v) => write!(f, "{v}"),
+            AttributeValue::Short(v) => write!(f, "{v}"),
+            AttributeValue::Signed(v) => write!(f, "{v}"),
+            AttributeValue::Tlv(tlvs) => {
+                let parts: Vec<String> = tlvs.iter().map(|(t, v)| format!("{t}=0x{}", hex(v, ""))).collect();
+                write!(f, "{{ {} }}", parts.join(", "))
+            }
+        }
+    }
+}
+
+/// A value decoded along its dictionary definition: the sub-attributes
+/// of a `tlv` and the members of a `struct` by name, other types as
+/// plain values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueTree {
+    Value(AttributeValue),
+    /// Named members in wire order.
+    Group(Vec<(String, ValueTree)>),
+}
+
+impl ValueTree {
+    /// An empty group, to be filled with [`member`](Self::member).
+    pub fn group() -> Self {
+        ValueTree::Group(Vec::new())
+    }
+
+    /// Adds a member to a group. A plain value is turned into a group
+    /// first.
+    pub fn member(self, name: &str, value: impl Into<ValueTree>) -> Self {
+        let mut members = match self {
+            ValueTree::Group(members) => members,
+            ValueTree::Value(_) => Vec::new(),
+        };
+        members.push((name.to_string(), value.into()));
+        ValueTree::Group(members)
+    }
+
+    /// The member at a dotted path, e.g. `Src.IP`.
+    pub fn get(&self, path: &str) -> Option<&ValueTree> {
+        path.split('.').try_fold(self, |tree, name| match tree {
+            ValueTree::Group(members) => members.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            ValueTree::Value(_) => None,
+        })
+    }
+
+    /// The plain value, if this is not a group.
+    pub fn value(&self) -> Option<&AttributeValue> {
+        match self {
+            ValueTree::Value(value) => Some(value),
+            ValueTree::Group(_) => None,
+        }
+    }
+}
+
+impl From<AttributeValue> for ValueTree {
+    fn from(value: AttributeValue) -> Self {
+        ValueTree::Value(value)
+    }
+}
+
+impl fmt::Display for ValueTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueTree::Value(value) => write!(f, "{value}"),
+            ValueTree::Group(members) => {
+                let parts: Vec<String> = members.iter().map(|(name, value)| format!("{name} = {value}")).collect();
+                write!(f, "{{ {} }}", parts.join(", "))
+            }
+        }
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(s: &str) -> Self {
+        AttributeValue::String(s.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(s: String) -> Self {
+        AttributeValue::String(s)
+    }
+}
+
+impl From<u32> for AttributeValue {
+    fn from(v: u32) -> Self {
+        AttributeValue::Integer(v)
+    }
+}
+
+impl From<u64> for AttributeValue {
+    fn from(v: u64) -> Self {
+        AttributeValue::Integer64(v)
+    }
+}
+
+impl From<Ipv4Addr> for AttributeValue {
+    fn from(ip: Ipv4Addr) -> Self {
+        AttributeValue::IpAddr(ip)
+    }
+}
+
+impl From<Ipv6Addr> for AttributeValue {
+    fn from(ip: Ipv6Addr) -> Self {
+        AttributeValue::Ipv6Addr(ip)
+    }
+}
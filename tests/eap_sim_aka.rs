@@ -0,0 +1,120 @@
+//! EAP-SIM, EAP-AKA and EAP-AKA' conversations against an in-process
+//! EapServer, with vectors generated from the 3GPP TS 35.208 Milenage
+//! test set 1.
+
+use std::sync::Arc;
+use radius_server::{
+    eap::{
+        aka::{AkaPeer, EapAka},
+        milenage::{Milenage, Usim},
+        sim::{EapSim, SimPeer},
+        simaka::SimAkaKeys,
+        vectors::FileVectorSource,
+        EapKeys, EapPacket, EapReply, EapServer, EAP_TYPE_IDENTITY,
+    },
+    packet::{RadiusAttribute, RadiusCode, RadiusPacket},
+    RadiusError,
+};
+
+const VECTORS: &str = "
+# TS 35.208 test set 1 subscriber keys
+234150999999999  milenage  ki=465b5ce8b199b49faa5f0a2ee238a6bc opc=cd63cb71954a9f4e48a5994e37a02baf sqn=000000000020
+# A card whose HSS has fallen behind, to exercise resynchronisation
+234150999999998  milenage  ki=465b5ce8b199b49faa5f0a2ee238a6bc opc=cd63cb71954a9f4e48a5994e37a02baf
+";
+
+const KI: u128 = 0x465b5ce8b199b49faa5f0a2ee238a6bc;
+const OP: u128 = 0xcdc202d5123e20f62b6d676ac72cb318;
+const REALM: &str = "wlan.mnc015.mcc234.3gppnetwork.org";
+
+fn server() -> EapServer {
+    let source = Arc::new(FileVectorSource::parse_from_str(VECTORS).unwrap());
+    EapServer::new()
+        .register(EapSim::new(source.clone()))
+        .register(EapAka::new(source.clone()))
+        .register(EapAka::prime(source, "WLAN"))
+}
+
+fn card(sqn: u64) -> Usim {
+    Usim::new(Milenage::with_op(KI.to_be_bytes(), OP.to_be_bytes()), sqn)
+}
+
+/// Carries each EAP response to the server in an Access-Request, as a NAS
+/// would, until it accepts or rejects. Returns the server's keys on success.
+fn run<F>(server: &EapServer, mut respond: F) -> Result<Option<EapKeys>, RadiusError>
+where
+    F: FnMut(&EapPacket) -> Result<EapPacket, RadiusError>,
+{
+    let mut response = respond(&EapPacket::request(0, EAP_TYPE_IDENTITY, Vec::new()))?;
+    let mut state: Option<Vec<u8>> = None;
+
+    for identifier in 1..=16u8 {
+        let mut attributes = RadiusAttribute::eap_message(&response.to_bytes());
+        if let Some(state) = &state {
+            attributes.push(RadiusAttribute::state(state)?);
+        }
+        let request = RadiusPacket {
+            code: RadiusCode::AccessRequest,
+            identifier,
+            length: 0,
+            authenticator: [identifier; 16],
+            attributes,
+        };
+
+        match server.handle(&request, "testing123") {
+            EapReply::Challenge(reply) => {
+                state = reply.state().map(|s| s.to_vec());
+                let raw = reply.eap_message().ok_or(RadiusError::InvalidEap("missing EAP-Message".to_string()))?;
+                response = respond(&EapPacket::from_bytes(&raw)?)?;
+            }
+            EapReply::Accept { keys, .. } => return Ok(Some(keys)),
+            EapReply::Reject(_) => return Ok(None),
+            EapReply::Discard(reason) => return Err(RadiusError::Discarded(reason)),
+        }
+    }
+    Err(RadiusError::Eap("conversation did not finish".to_string()))
+}
+
+fn assert_keys_match(server: Option<EapKeys>, peer: Option<&SimAkaKeys>) {
+    let (server, peer) = (server.expect("server accepted"), peer.expect("peer derived keys"));
+    assert_eq!(server.msk.len(), 64);
+    assert_eq!(server.emsk.len(), 64);
+    assert_eq!(server.msk, peer.msk);
+    assert_eq!(server.emsk, peer.emsk);
+}
+
+#[test]
+fn eap_sim_accepts_card() {
+    let mut peer = SimPeer::new(&format!("1234150999999999@{REALM}"), card(0));
+    let keys = run(&server(), |req| peer.respond(req)).unwrap();
+    assert_keys_match(keys, peer.keys());
+}
+
+#[test]
+fn eap_aka_accepts_card() {
+    let mut peer = AkaPeer::new(&format!("0234150999999999@{REALM}"), card(0));
+    let keys = run(&server(), |req| peer.respond(req)).unwrap();
+    assert_keys_match(keys, peer.keys());
+}
+
+#[test]
+fn eap_aka_prime_accepts_card() {
+    let mut peer = AkaPeer::prime(&format!("6234150999999999@{REALM}"), card(0));
+    let keys = run(&server(), |req| peer.respond(req)).unwrap();
+    assert_keys_match(keys, peer.keys());
+}
+
+#[test]
+fn eap_aka_resynchronises_stale_sequence_number() {
+    let mut peer = AkaPeer::new(&format!("0234150999999998@{REALM}"), card(0x1000));
+    let keys = run(&server(), |req| peer.respond(req)).unwrap();
+    assert_keys_match(keys, peer.keys());
+}
+
+#[test]
+fn eap_aka_rejects_card_with_wrong_key() {
+    let wrong_key = Usim::new(Milenage::new([0x11; 16], [0x22; 16]), 0);
+    let mut peer = AkaPeer::new(&format!("0234150999999999@{REALM}"), wrong_key);
+    assert!(run(&server(), |req| peer.respond(req)).unwrap().is_none());
+    assert!(peer.keys().is_none());
+}
@@ -0,0 +1,166 @@
+//! EAP-TLS, PEAP and TTLS conversations against an in-process EapServer
+//! using throwaway certificates, without any network I/O.
+
+use std::sync::Arc;
+use radius_server::{
+    eap::{
+        peap::Peap,
+        supplicant::{PeerMethod, Supplicant, SupplicantOutcome},
+        tls::{client_config, server_config, EapTls},
+        ttls::Ttls,
+        EapServer, PasswordLookup,
+    },
+    mschap::KnownPassword,
+};
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use rustls::{ClientConfig, ServerConfig};
+
+const SECRET: &str = "testing123";
+const SERVER_NAME: &str = "radius.example.com";
+
+struct Pki {
+    ca: String,
+    server: (String, String),
+    client: (String, String),
+}
+
+impl Pki {
+    fn generate() -> Self {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.distinguished_name.push(DnType::CommonName, "Example RADIUS CA");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let mut server_params = CertificateParams::new(vec![SERVER_NAME.to_string()]).unwrap();
+        server_params.extended_key_usages.push(ExtendedKeyUsagePurpose::ServerAuth);
+        let server_cert = server_params.signed_by(&server_key, &ca, &ca_key).unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(vec!["alice.example.com".to_string()]).unwrap();
+        client_params.extended_key_usages.push(ExtendedKeyUsagePurpose::ClientAuth);
+        let client_cert = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+        Pki {
+            ca: ca.pem(),
+            server: (server_cert.pem(), server_key.serialize_pem()),
+            client: (client_cert.pem(), client_key.serialize_pem()),
+        }
+    }
+
+    fn mutual(&self) -> Arc<ServerConfig> {
+        server_config(self.server.0.as_bytes(), self.server.1.as_bytes(), Some(self.ca.as_bytes())).unwrap()
+    }
+
+    fn tunnel(&self) -> Arc<ServerConfig> {
+        server_config(self.server.0.as_bytes(), self.server.1.as_bytes(), None).unwrap()
+    }
+
+    fn with_cert(&self) -> Arc<ClientConfig> {
+        client_config(self.ca.as_bytes(), Some((self.client.0.as_bytes(), self.client.1.as_bytes()))).unwrap()
+    }
+
+    fn anonymous(&self) -> Arc<ClientConfig> {
+        client_config(self.ca.as_bytes(), None).unwrap()
+    }
+
+    fn server(&self) -> EapServer {
+        let lookup: PasswordLookup = Arc::new(|user: &str| match user {
+            "alice" => Some(KnownPassword::Cleartext("wonderland".to_string())),
+            _ => None,
+        });
+        EapServer::new()
+            .register(EapTls::new(self.mutual()).unwrap())
+            .register(Peap::new(self.tunnel(), lookup.clone()))
+            .register(Ttls::new(self.tunnel(), lookup))
+    }
+}
+
+fn run(server: &EapServer, method: PeerMethod, config: Arc<ClientConfig>) -> SupplicantOutcome {
+    Supplicant::new("anonymous@example.com", method, config, SERVER_NAME)
+        .unwrap()
+        .authenticate(server, SECRET)
+        .unwrap()
+}
+
+fn assert_keys_match(outcome: &SupplicantOutcome) {
+    let (server, peer) = (outcome.server_keys.as_ref().unwrap(), outcome.peer_keys.as_ref().unwrap());
+    assert_eq!(server.msk.len(), 64);
+    assert_eq!(server.msk, peer.msk);
+    assert_eq!(server.emsk, peer.emsk);
+}
+
+fn peap(password: &str) -> PeerMethod {
+    PeerMethod::Peap { username: "alice".to_string(), password: password.to_string() }
+}
+
+fn ttls(password: &str) -> PeerMethod {
+    PeerMethod::Ttls { username: "alice".to_string(), password: password.to_string() }
+}
+
+#[test]
+fn eap_tls_accepts_client_certificate() {
+    let pki = Pki::generate();
+    let outcome = run(&pki.server(), PeerMethod::Tls, pki.with_cert());
+    assert!(outcome.accepted);
+    assert!(!outcome.resumed);
+    assert_keys_match(&outcome);
+}
+
+#[test]
+fn eap_tls_resumes_session() {
+    let pki = Pki::generate();
+    let (server, config) = (pki.server(), pki.with_cert());
+    assert!(run(&server, PeerMethod::Tls, config.clone()).accepted);
+
+    let outcome = run(&server, PeerMethod::Tls, config);
+    assert!(outcome.accepted);
+    assert!(outcome.resumed);
+    assert_keys_match(&outcome);
+}
+
+#[test]
+fn eap_tls_rejects_client_without_certificate() {
+    let pki = Pki::generate();
+    let server = pki.server();
+    let outcome = run(&server, PeerMethod::Tls, pki.anonymous());
+    assert!(!outcome.accepted);
+    assert_eq!(server.pending(), 0);
+}
+
+#[test]
+fn eap_tls_refuses_config_without_client_ca() {
+    let pki = Pki::generate();
+    assert!(EapTls::new(pki.tunnel()).is_err());
+}
+
+#[test]
+fn peap_mschapv2_accepts_password() {
+    let pki = Pki::generate();
+    let outcome = run(&pki.server(), peap("wonderland"), pki.anonymous());
+    assert!(outcome.accepted);
+    assert_keys_match(&outcome);
+}
+
+#[test]
+fn peap_mschapv2_rejects_wrong_password() {
+    let pki = Pki::generate();
+    let outcome = run(&pki.server(), peap("looking-glass"), pki.anonymous());
+    assert!(!outcome.accepted);
+}
+
+#[test]
+fn ttls_pap_accepts_password() {
+    let pki = Pki::generate();
+    let outcome = run(&pki.server(), ttls("wonderland"), pki.anonymous());
+    assert!(outcome.accepted);
+    assert_keys_match(&outcome);
+}
+
+#[test]
+fn ttls_pap_rejects_wrong_password() {
+    let pki = Pki::generate();
+    let outcome = run(&pki.server(), ttls("looking-glass"), pki.anonymous());
+    assert!(!outcome.accepted);
+}